@@ -1,49 +1,857 @@
-use gpui::{AppContext, Context, Entity, Render, SharedString, Window, div};
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use gpui::{AppContext, AsyncApp, Context, Entity, Render, SharedString, WeakEntity, Window, div};
 use gpui_component::input::InputState;
+use rpc::{
+    common::Empty,
+    models::{
+        auth::UserInfo,
+        channels::{ChannelListUpdate, ChannelListUpdateMessage, GetTextChannels},
+        common::RPCMethod as _,
+        markers::{MsgId, TextChannelId, UserId},
+        mentions::{GetMentions, GetMentionsPayload, Mention, Mentioned},
+        messages::{
+            GetMessages, GetMessagesPayload, Message, MessageContent, MessageCreated,
+            MessageCursor, MessageDeleted, MessageUpdated, Reaction, ReactionUpdate, SendMessage,
+            SendMessagePayload, StartTyping, StartTypingPayload, TYPING_INTERVAL_SECS,
+            TextMessageChannel, TypingUpdate,
+        },
+        profiles::{GetUsers, GetUsersPayload, MAX_USERS_PER_REQUEST, ProfileUpdated},
+        read_state::{
+            AckChannel, AckChannelPayload, ChannelUnread, GetUnreadSummary, ReadStateUpdate,
+            SetChannelMuted, SetChannelMutedPayload,
+        },
+    },
+};
+
+use crate::ConnectionManger;
 
 #[derive(Clone, Debug)]
 pub struct TextChannel {
-    pub id: u64,
+    pub id: TextChannelId,
     pub name: SharedString,
+    pub position: i32,
 
     pub is_active: bool,
     pub is_muted: bool,
 
     pub unread_messages: usize,
+    pub mention_count: usize,
+}
+
+impl TextChannel {
+    fn new(channel: rpc::models::channels::TextChannel) -> Self {
+        Self {
+            id: channel.id,
+            name: channel.name.into(),
+            position: channel.position,
+            is_active: false,
+            is_muted: false,
+            unread_messages: 0,
+            mention_count: 0,
+        }
+    }
+}
+
+/// Loaded part of the channel history
+#[derive(Default)]
+pub struct ChannelMessages {
+    /// Ordered from the oldest to the newest
+    pub messages: Vec<Message>,
+    /// Whether older messages can be fetched
+    pub has_more: bool,
 }
 
 pub struct ChatState {
     _input_state: Entity<InputState>,
 
     pub text_channels: Vec<TextChannel>,
+    pub messages: HashMap<TextChannelId, ChannelMessages>,
+    /// Other users typing in the channels
+    pub typing: HashMap<TextChannelId, Vec<UserId>>,
+    /// Unread mentions of the user, newest first
+    pub mentions: Vec<Mention>,
+    /// Profiles of message authors
+    pub users: HashMap<UserId, UserInfo>,
+    /// Messages of these users are kept but hidden
+    blocked: HashSet<UserId>,
+
+    /// When `StartTyping` was sent the last time, used for throttling
+    typing_sent_at: Option<Instant>,
 }
 
 impl ChatState {
-    pub fn new(
-        window: &mut Window,
-        cx: &mut Context<Self>,
-    ) -> Self {
-        let input_state = cx.new(|cx| {
-            InputState::new(window, cx)
-        });
+    pub fn new(window: &mut Window, cx: &mut Context<Self>) -> Self {
+        let input_state = cx.new(|cx| InputState::new(window, cx));
 
         Self {
             _input_state: input_state,
-            text_channels: (0..4).map(|i| {
-                TextChannel {
-                    id: i,
-                    name: format!("Text channel {i}").into(),
-                    is_active: i == 0,
-                    is_muted: false,
-                    unread_messages: i as usize,
+            text_channels: vec![],
+            messages: HashMap::new(),
+            typing: HashMap::new(),
+            mentions: vec![],
+            users: HashMap::new(),
+            blocked: HashSet::new(),
+            typing_sent_at: None,
+        }
+    }
+
+    pub fn get_active_channel(&self) -> Option<&TextChannel> {
+        self.text_channels.iter().find(|channel| channel.is_active)
+    }
+
+    pub fn is_blocked(&self, user_id: UserId) -> bool {
+        self.blocked.contains(&user_id)
+    }
+
+    pub fn set_blocked(&mut self, blocked: HashSet<UserId>, cx: &mut Context<Self>) {
+        for users in self.typing.values_mut() {
+            users.retain(|user| !blocked.contains(user));
+        }
+
+        self.blocked = blocked;
+
+        cx.notify();
+    }
+
+    /// Loaded messages of the channel without the ones from blocked users
+    pub fn visible_messages(&self, id: TextChannelId) -> impl Iterator<Item = &Message> {
+        self.messages
+            .get(&id)
+            .into_iter()
+            .flat_map(|channel| channel.messages.iter())
+            .filter(|message| !self.blocked.contains(&message.author))
+    }
+
+    fn sort_channels(&mut self) {
+        self.text_channels
+            .sort_by_key(|channel| (channel.position, channel.id.value));
+    }
+
+    pub fn fetch_text_channels(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let Ok(channels) = GetTextChannels::execute(&connection, &Empty {}).await else {
+                // TODO: Send notification with an error
+                return;
+            };
+
+            let unread = GetUnreadSummary::execute(&connection, &Empty {})
+                .await
+                .unwrap_or_default();
+
+            this.update(cx, move |this, cx| {
+                let active = this.get_active_channel().map(|channel| channel.id);
+
+                this.text_channels = channels.into_iter().map(TextChannel::new).collect();
+
+                if let Some(channel) =
+                    active.and_then(|id| this.text_channels.iter_mut().find(|item| item.id == id))
+                {
+                    channel.is_active = true;
+                }
+
+                for item in unread {
+                    this.apply_unread(item);
+                }
+
+                this.sort_channels();
+
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    fn apply_unread(&mut self, unread: ChannelUnread) {
+        let TextMessageChannel::TextChannel(id) = unread.channel else {
+            return;
+        };
+
+        let Some(channel) = self.text_channels.iter_mut().find(|item| item.id == id) else {
+            return;
+        };
+
+        channel.is_muted = unread.is_muted;
+        channel.unread_messages = unread.unread_count as usize;
+        channel.mention_count = unread.mention_count as usize;
+    }
+
+    pub fn select_channel(&mut self, id: TextChannelId, cx: &mut Context<Self>) {
+        for channel in self.text_channels.iter_mut() {
+            channel.is_active = channel.id == id;
+        }
+
+        if self.messages.contains_key(&id) {
+            self.ack_channel(id, cx);
+        } else {
+            self.fetch_messages(id, None, cx);
+        }
+
+        cx.notify();
+    }
+
+    /// Marks everything loaded in the channel as read
+    fn ack_channel(&mut self, id: TextChannelId, cx: &mut Context<Self>) {
+        self.clear_read(id);
+
+        let Some(message) = self
+            .messages
+            .get(&id)
+            .and_then(|channel| channel.messages.last())
+            .map(|message| message.id)
+        else {
+            return;
+        };
+
+        let payload = AckChannelPayload {
+            channel: TextMessageChannel::TextChannel(id),
+            message,
+        };
+
+        cx.spawn(async move |_, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            _ = AckChannel::execute(&connection, &payload).await;
+        })
+        .detach();
+    }
+
+    pub fn set_channel_muted(&mut self, id: TextChannelId, is_muted: bool, cx: &mut Context<Self>) {
+        let payload = SetChannelMutedPayload {
+            channel: TextMessageChannel::TextChannel(id),
+            is_muted,
+        };
+
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            if SetChannelMuted::execute(&connection, &payload)
+                .await
+                .is_err()
+            {
+                // TODO: Send notification with an error
+                return;
+            }
+
+            this.update(cx, move |this, cx| {
+                this.set_muted(id, is_muted);
+
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    fn set_muted(&mut self, id: TextChannelId, is_muted: bool) {
+        if let Some(channel) = self.text_channels.iter_mut().find(|item| item.id == id) {
+            channel.is_muted = is_muted;
+
+            // Muted channels don't show unread messages, only mentions
+            if is_muted {
+                channel.unread_messages = 0;
+            }
+        }
+    }
+
+    /// Loads the page before the oldest loaded message
+    pub fn fetch_older_messages(&mut self, id: TextChannelId, cx: &mut Context<Self>) {
+        let Some(channel) = self.messages.get(&id) else {
+            return self.fetch_messages(id, None, cx);
+        };
+
+        if !channel.has_more {
+            return;
+        }
+
+        if let Some(oldest) = channel.messages.first() {
+            self.fetch_messages(id, Some(MessageCursor::Before(oldest.id)), cx);
+        }
+    }
+
+    fn fetch_messages(
+        &mut self,
+        id: TextChannelId,
+        cursor: Option<MessageCursor>,
+        cx: &mut Context<Self>,
+    ) {
+        cx.spawn(async move |this, cx| {
+            Self::fetch_messages_inner(&this, id, cursor, cx).await;
+        })
+        .detach();
+    }
+
+    async fn fetch_messages_inner(
+        this: &WeakEntity<Self>,
+        id: TextChannelId,
+        cursor: Option<MessageCursor>,
+        cx: &mut AsyncApp,
+    ) {
+        let connection = ConnectionManger::get(cx);
+
+        let response = GetMessages::execute(
+            &connection,
+            &GetMessagesPayload {
+                channel: TextMessageChannel::TextChannel(id),
+                cursor,
+                limit: 0,
+            },
+        )
+        .await;
+
+        let Ok(page) = response else {
+            // TODO: Send notification with an error
+            return;
+        };
+
+        this.update(cx, move |this, cx| {
+            let authors = page
+                .messages
+                .iter()
+                .map(|message| message.author)
+                .collect::<Vec<_>>();
+            this.fetch_missing_users(authors, cx);
+
+            let channel = this.messages.entry(id).or_default();
+
+            match cursor {
+                Some(MessageCursor::Before(_)) => {
+                    channel.messages.splice(0..0, page.messages);
+                    channel.has_more = page.has_more;
+                }
+                Some(MessageCursor::After(_)) => {
+                    channel.messages.extend(page.messages);
                 }
-            }).collect(),
+                None => {
+                    channel.messages = page.messages;
+                    channel.has_more = page.has_more;
+                }
+            }
+
+            if this
+                .get_active_channel()
+                .is_some_and(|channel| channel.id == id)
+            {
+                this.ack_channel(id, cx);
+            }
+
+            cx.notify();
+        })
+        .ok();
+    }
+
+    pub fn send_message(&mut self, text: String, cx: &mut Context<Self>) {
+        let Some(channel) = self.get_active_channel() else {
+            return;
+        };
+
+        let payload = SendMessagePayload {
+            content: MessageContent {
+                attached_media: vec![],
+                reply: None,
+                content: text,
+            },
+            destination: TextMessageChannel::TextChannel(channel.id),
+        };
+
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let Ok(message) = SendMessage::execute(&connection, &payload).await else {
+                // TODO: Send notification with an error
+                return;
+            };
+
+            this.update(cx, move |this, cx| {
+                this.typing_sent_at = None;
+                this.push_message(message);
+
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    /// Lets others know that the user is typing in the active channel.
+    /// Can be called on every input change, requests are throttled
+    pub fn notify_typing(&mut self, cx: &mut Context<Self>) {
+        let Some(channel_id) = self.get_active_channel().map(|channel| channel.id) else {
+            return;
+        };
+
+        let interval = Duration::from_secs(TYPING_INTERVAL_SECS);
+        if self
+            .typing_sent_at
+            .is_some_and(|sent_at| sent_at.elapsed() < interval)
+        {
+            return;
         }
+
+        self.typing_sent_at = Some(Instant::now());
+
+        let payload = StartTypingPayload {
+            channel: TextMessageChannel::TextChannel(channel_id),
+        };
+
+        cx.spawn(async move |_, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            _ = StartTyping::execute(&connection, &payload).await;
+        })
+        .detach();
+    }
+
+    fn set_typing(&mut self, id: TextChannelId, user_id: UserId, is_typing: bool) {
+        let users = self.typing.entry(id).or_default();
+        users.retain(|user| *user != user_id);
+
+        if is_typing {
+            users.push(user_id);
+        }
+    }
+
+    pub fn watch_typing_updates(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let mut subscription = connection.subscribe::<TypingUpdate>();
+            while let Some(event) = subscription.recv().await {
+                this.update(cx, |this, cx| {
+                    let TextMessageChannel::TextChannel(id) = event.channel else {
+                        return;
+                    };
+
+                    if ConnectionManger::get_user_id(cx) == Some(event.user)
+                        || this.is_blocked(event.user)
+                    {
+                        return;
+                    }
+
+                    this.set_typing(id, event.user, event.is_typing);
+
+                    cx.notify();
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+
+    /// Appends a new message unless it was already received
+    fn push_message(&mut self, message: Message) {
+        let TextMessageChannel::TextChannel(id) = message.channel else {
+            return;
+        };
+
+        // Sending a message stops typing
+        self.set_typing(id, message.author, false);
+
+        // History wasn't loaded yet, it'll be fetched with this message
+        let Some(channel) = self.messages.get_mut(&id) else {
+            return;
+        };
+
+        if channel.messages.iter().any(|item| item.id == message.id) {
+            return;
+        }
+
+        channel.messages.push(message);
+    }
+
+    fn clear_read(&mut self, id: TextChannelId) {
+        if let Some(channel) = self.text_channels.iter_mut().find(|item| item.id == id) {
+            channel.unread_messages = 0;
+            channel.mention_count = 0;
+        }
+
+        self.mentions
+            .retain(|mention| mention.message.channel != TextMessageChannel::TextChannel(id));
+    }
+
+    pub fn fetch_mentions(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let payload = GetMentionsPayload {
+                before: None,
+                limit: 0,
+            };
+
+            let Ok(page) = GetMentions::execute(&connection, &payload).await else {
+                // TODO: Send notification with an error
+                return;
+            };
+
+            this.update(cx, move |this, cx| {
+                this.mentions = page.mentions;
+
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    pub fn watch_mentions(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let mut subscription = connection.subscribe::<Mentioned>();
+            while let Some(event) = subscription.recv().await {
+                this.update(cx, |this, cx| {
+                    let mention = event.mention;
+
+                    let TextMessageChannel::TextChannel(id) = mention.message.channel else {
+                        return;
+                    };
+
+                    // Highlights the message if it's already shown
+                    if let Some(message) = this.messages.get_mut(&id).and_then(|channel| {
+                        channel
+                            .messages
+                            .iter_mut()
+                            .find(|item| item.id == mention.message.id)
+                    }) {
+                        message.mentions_me = true;
+                    }
+
+                    let Some(channel) = this.text_channels.iter_mut().find(|item| item.id == id)
+                    else {
+                        return;
+                    };
+
+                    // The active channel is acked as messages arrive
+                    if channel.is_active {
+                        return;
+                    }
+
+                    channel.mention_count += 1;
+                    this.mentions
+                        .retain(|item| item.message.id != mention.message.id);
+                    this.mentions.insert(0, mention);
+
+                    cx.notify();
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+
+    /// Counts a message of another user, the active channel is read right away
+    fn mark_unread(&mut self, id: TextChannelId, cx: &mut Context<Self>) {
+        let Some(channel) = self.text_channels.iter_mut().find(|item| item.id == id) else {
+            return;
+        };
+
+        if channel.is_active {
+            self.ack_channel(id, cx);
+        } else if !channel.is_muted {
+            channel.unread_messages += 1;
+        }
+    }
+
+    pub fn watch_read_state_updates(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let mut subscription = connection.subscribe::<ReadStateUpdate>();
+            while let Some(event) = subscription.recv().await {
+                this.update(cx, |this, cx| {
+                    let TextMessageChannel::TextChannel(id) = event.channel else {
+                        return;
+                    };
+
+                    this.set_muted(id, event.is_muted);
+
+                    // Another session has read the channel up to the latest message we know of
+                    let latest = this
+                        .messages
+                        .get(&id)
+                        .and_then(|channel| channel.messages.last())
+                        .map(|message| message.id);
+
+                    if is_read_up_to(event.last_read, latest) {
+                        this.clear_read(id);
+                    }
+
+                    cx.notify();
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+
+    pub fn watch_message_updates(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let mut subscription = connection.subscribe::<MessageCreated>();
+            while let Some(event) = subscription.recv().await {
+                this.update(cx, |this, cx| {
+                    let is_mine = ConnectionManger::get_user_id(cx) == Some(event.message.author);
+                    let channel = event.message.channel;
+
+                    this.fetch_missing_users(vec![event.message.author], cx);
+
+                    this.push_message(event.message);
+
+                    if let TextMessageChannel::TextChannel(id) = channel
+                        && !is_mine
+                    {
+                        this.mark_unread(id, cx);
+                    }
+
+                    cx.notify();
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+
+    pub fn watch_message_edits(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let mut subscription = connection.subscribe::<MessageUpdated>();
+            while let Some(event) = subscription.recv().await {
+                this.update(cx, |this, cx| {
+                    let message = event.message;
+
+                    let TextMessageChannel::TextChannel(id) = message.channel else {
+                        return;
+                    };
+
+                    let Some(existing) = this.messages.get_mut(&id).and_then(|channel| {
+                        channel
+                            .messages
+                            .iter_mut()
+                            .find(|item| item.id == message.id)
+                    }) else {
+                        return;
+                    };
+
+                    // Reactions and mentions are only sent with the history
+                    // and kept up to date separately
+                    let reactions = std::mem::take(&mut existing.reactions);
+                    let mentions_me = existing.mentions_me;
+                    *existing = message;
+                    existing.reactions = reactions;
+                    existing.mentions_me = mentions_me;
+
+                    cx.notify();
+                })
+                .ok();
+            }
+        })
+        .detach();
+
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let mut subscription = connection.subscribe::<MessageDeleted>();
+            while let Some(event) = subscription.recv().await {
+                this.update(cx, |this, cx| {
+                    let TextMessageChannel::TextChannel(id) = event.channel else {
+                        return;
+                    };
+
+                    if let Some(channel) = this.messages.get_mut(&id) {
+                        channel.messages.retain(|item| item.id != event.id);
+
+                        cx.notify();
+                    }
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+
+    pub fn watch_reaction_updates(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let mut subscription = connection.subscribe::<ReactionUpdate>();
+            while let Some(event) = subscription.recv().await {
+                this.update(cx, |this, cx| {
+                    let TextMessageChannel::TextChannel(id) = event.channel else {
+                        return;
+                    };
+
+                    let Some(message) = this.messages.get_mut(&id).and_then(|channel| {
+                        channel
+                            .messages
+                            .iter_mut()
+                            .find(|item| item.id == event.message)
+                    }) else {
+                        return;
+                    };
+
+                    let is_me = ConnectionManger::get_user_id(cx) == Some(event.user);
+                    let reactions = &mut message.reactions;
+                    let index = reactions
+                        .iter()
+                        .position(|reaction| reaction.emoji == event.emoji);
+
+                    match (index, event.added) {
+                        (Some(index), true) => {
+                            reactions[index].count += 1;
+                            reactions[index].me |= is_me;
+                        }
+                        (None, true) => reactions.push(Reaction {
+                            emoji: event.emoji,
+                            count: 1,
+                            me: is_me,
+                        }),
+                        (Some(index), false) => {
+                            let reaction = &mut reactions[index];
+                            reaction.count = reaction.count.saturating_sub(1);
+                            reaction.me &= !is_me;
+
+                            if reaction.count == 0 {
+                                reactions.remove(index);
+                            }
+                        }
+                        (None, false) => return,
+                    }
+
+                    cx.notify();
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+
+    /// Name of the user if the profile was loaded
+    pub fn user_name(&self, id: UserId) -> Option<&str> {
+        self.users.get(&id).map(UserInfo::name)
+    }
+
+    /// Loads profiles of the users that aren't known yet
+    fn fetch_missing_users(&mut self, mut ids: Vec<UserId>, cx: &mut Context<Self>) {
+        ids.retain(|id| !self.users.contains_key(id));
+        ids.sort_by_key(|id| id.value);
+        ids.dedup();
+
+        if ids.is_empty() {
+            return;
+        }
+
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            for chunk in ids.chunks(MAX_USERS_PER_REQUEST) {
+                let payload = GetUsersPayload {
+                    users: chunk.to_vec(),
+                };
+
+                let Ok(users) = GetUsers::execute(&connection, &payload).await else {
+                    // TODO: Send notification with an error
+                    return;
+                };
+
+                this.update(cx, move |this, cx| {
+                    for user in users {
+                        this.users.insert(user.id, user);
+                    }
+
+                    cx.notify();
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+
+    pub fn watch_profile_updates(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let mut subscription = connection.subscribe::<ProfileUpdated>();
+            while let Some(event) = subscription.recv().await {
+                this.update(cx, |this, cx| {
+                    this.users.insert(event.user.id, event.user);
+
+                    cx.notify();
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+
+    pub fn watch_channel_list_updates(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let mut subscription = connection.subscribe::<ChannelListUpdate>();
+            while let Some(event) = subscription.recv().await {
+                this.update(cx, |this, cx| {
+                    match event.message {
+                        ChannelListUpdateMessage::TextChannelCreated(channel) => {
+                            if this.text_channels.iter().any(|item| item.id == channel.id) {
+                                return;
+                            }
+
+                            this.text_channels.push(TextChannel::new(channel));
+                        }
+                        ChannelListUpdateMessage::TextChannelUpdated(channel) => {
+                            let Some(existing) = this
+                                .text_channels
+                                .iter_mut()
+                                .find(|item| item.id == channel.id)
+                            else {
+                                return;
+                            };
+
+                            existing.name = channel.name.into();
+                            existing.position = channel.position;
+                        }
+                        ChannelListUpdateMessage::TextChannelDeleted(id) => {
+                            this.text_channels.retain(|channel| channel.id != id);
+                            this.messages.remove(&id);
+                        }
+                        // Voice channels are handled by the streaming state
+                        _ => return,
+                    }
+
+                    this.sort_channels();
+
+                    cx.notify();
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+}
+
+/// Whether an ack covers the message, unknown history counts as read
+fn is_read_up_to(last_read: Option<MsgId>, message: Option<MsgId>) -> bool {
+    match (last_read, message) {
+        (Some(last_read), Some(message)) => last_read.value >= message.value,
+        (Some(_), None) => true,
+        (None, _) => false,
     }
 }
 
 impl Render for ChatState {
-    fn render(&mut self, _window: &mut gpui::Window, _cx: &mut gpui::Context<Self>) -> impl gpui::IntoElement {
+    fn render(
+        &mut self,
+        _window: &mut gpui::Window,
+        _cx: &mut gpui::Context<Self>,
+    ) -> impl gpui::IntoElement {
         div()
     }
 }
@@ -2,7 +2,8 @@ use std::time::Duration;
 
 use gpui::{
     Animation, ElementId, Entity, InteractiveElement as _, IntoElement, ParentElement as _,
-    RenderOnce, Styled as _, div, ease_in_out, px,
+    RenderOnce, StatefulInteractiveElement as _, Styled as _, div, ease_in_out,
+    prelude::FluentBuilder as _, px,
 };
 use gpui_component::{ActiveTheme as _, Icon, Sizable as _, Size, StyledExt as _, label::Label};
 
@@ -31,38 +32,57 @@ impl TextChannelsComponent {
 }
 
 impl RenderOnce for TextChannelsComponent {
-    fn render(self, _window: &mut gpui::Window, cx: &mut gpui::App) -> impl gpui::IntoElement {
+    fn render(self, window: &mut gpui::Window, cx: &mut gpui::App) -> impl gpui::IntoElement {
         let state = self.chat_state.read(cx);
         let secondary = cx.theme().secondary;
 
         let channels = state.text_channels.iter().map(|channel| {
+            let channel_id = channel.id;
             let is_active = channel.is_active;
             let muted = cx.theme().muted;
 
-            div().id(ElementId::Integer(channel.id)).child(
-                div()
-                    .rounded_lg()
-                    .child(
-                        div()
-                            .flex()
-                            .items_center()
-                            .py_2()
-                            .px_3()
-                            .child(Icon::new(IconName::Hash).mr_2().with_size(Size::Medium))
-                            .child(Label::new(channel.name.clone()).mt(px(0.5))),
-                    )
-                    .with_hover_animation(
-                        "hover-bg",
-                        Animation::new(Duration::from_millis(200)).with_easing(ease_in_out),
-                        move |this, delta| {
-                            if is_active {
-                                this.bg(muted.opacity(1. - delta.min(0.2)))
-                            } else {
-                                this.bg(secondary.opacity(delta))
-                            }
-                        },
-                    ),
-            )
+            // Mentions are shown even in muted channels
+            let badge = match (channel.mention_count, channel.unread_messages) {
+                (0, 0) => None,
+                (0, unread) => Some(unread.to_string()),
+                (mentions, _) => Some(format!("@{mentions}")),
+            };
+
+            div()
+                .id(ElementId::Integer(channel.id.value as u64))
+                .on_click(
+                    window.listener_for(&self.chat_state, move |state, _, _, cx| {
+                        state.select_channel(channel_id, cx);
+                    }),
+                )
+                .child(
+                    div()
+                        .rounded_lg()
+                        .child(
+                            div()
+                                .flex()
+                                .items_center()
+                                .py_2()
+                                .px_3()
+                                .child(Icon::new(IconName::Hash).mr_2().with_size(Size::Medium))
+                                .child(Label::new(channel.name.clone()).mt(px(0.5)))
+                                .when(channel.is_muted, |this| this.opacity(0.6))
+                                .when_some(badge, |this, badge| {
+                                    this.child(Label::new(badge).ml_auto().text_xs())
+                                }),
+                        )
+                        .with_hover_animation(
+                            "hover-bg",
+                            Animation::new(Duration::from_millis(200)).with_easing(ease_in_out),
+                            move |this, delta| {
+                                if is_active {
+                                    this.bg(muted.opacity(1. - delta.min(0.2)))
+                                } else {
+                                    this.bg(secondary.opacity(delta))
+                                }
+                            },
+                        ),
+                )
         });
 
         CollapsableCard::new("text-channels", self.card_state)
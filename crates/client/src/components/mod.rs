@@ -5,6 +5,8 @@ pub mod chat_state;
 pub mod collapsable_card;
 pub mod context_popover;
 pub mod left_sidebar;
+pub mod presence_state;
+pub mod relationships_state;
 pub mod streaming_state;
 
 pub type EventCallback<T> = Box<dyn Fn(&T, &mut Window, &mut App)>;
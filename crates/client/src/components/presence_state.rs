@@ -0,0 +1,166 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use gpui::Context;
+use rpc::models::{
+    common::RPCMethod as _,
+    markers::UserId,
+    presence::{
+        CustomStatus, GetPresences, GetPresencesPayload, IDLE_AFTER_SECS, Presence, PresenceStatus,
+        PresenceUpdate, SetIdle, SetIdlePayload, SetPresence, SetPresencePayload,
+    },
+};
+use smol::stream::StreamExt as _;
+
+use crate::ConnectionManger;
+
+/// How often inactivity is checked
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+pub struct PresenceState {
+    /// Users that aren't listed are offline
+    pub presences: HashMap<UserId, Presence>,
+
+    last_activity: Instant,
+    is_idle: bool,
+}
+
+impl PresenceState {
+    pub fn new(_cx: &mut Context<Self>) -> Self {
+        Self {
+            presences: HashMap::new(),
+            last_activity: Instant::now(),
+            is_idle: false,
+        }
+    }
+
+    pub fn status(&self, user_id: UserId) -> PresenceStatus {
+        self.presences
+            .get(&user_id)
+            .map(|presence| presence.status)
+            .unwrap_or(PresenceStatus::Offline)
+    }
+
+    fn set_presence(&mut self, presence: Presence) {
+        if presence.status == PresenceStatus::Offline {
+            self.presences.remove(&presence.user);
+        } else {
+            self.presences.insert(presence.user, presence);
+        }
+    }
+
+    pub fn fetch_presences(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let payload = GetPresencesPayload { users: vec![] };
+
+            let Ok(presences) = GetPresences::execute(&connection, &payload).await else {
+                // TODO: Send notification with an error
+                return;
+            };
+
+            this.update(cx, move |this, cx| {
+                this.presences.clear();
+
+                for presence in presences {
+                    this.set_presence(presence);
+                }
+
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    pub fn update_presence(
+        &mut self,
+        status: PresenceStatus,
+        custom_status: Option<CustomStatus>,
+        cx: &mut Context<Self>,
+    ) {
+        let payload = SetPresencePayload {
+            status,
+            custom_status,
+        };
+
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let Ok(presence) = SetPresence::execute(&connection, &payload).await else {
+                // TODO: Send notification with an error
+                return;
+            };
+
+            this.update(cx, move |this, cx| {
+                this.set_presence(presence);
+
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    /// Should be called on user input, brings the session back from idle
+    pub fn notify_activity(&mut self, cx: &mut Context<Self>) {
+        self.last_activity = Instant::now();
+
+        if self.is_idle {
+            self.report_idle(false, cx);
+        }
+    }
+
+    fn report_idle(&mut self, is_idle: bool, cx: &mut Context<Self>) {
+        self.is_idle = is_idle;
+
+        cx.spawn(async move |_, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            _ = SetIdle::execute(&connection, &SetIdlePayload { is_idle }).await;
+        })
+        .detach();
+    }
+
+    pub fn watch_idle(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async move |this, cx| {
+            let mut timer = smol::Timer::interval(IDLE_CHECK_INTERVAL);
+            let idle_after = Duration::from_secs(IDLE_AFTER_SECS);
+
+            loop {
+                timer.next().await;
+
+                let result = this.update(cx, |this, cx| {
+                    if !this.is_idle && this.last_activity.elapsed() >= idle_after {
+                        this.report_idle(true, cx);
+                    }
+                });
+
+                if result.is_err() {
+                    break;
+                }
+            }
+        })
+        .detach();
+    }
+
+    pub fn watch_presence_updates(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let mut subscription = connection.subscribe::<PresenceUpdate>();
+            while let Some(event) = subscription.recv().await {
+                this.update(cx, |this, cx| {
+                    this.set_presence(event.presence);
+
+                    cx.notify();
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::{HashMap, HashSet};
+
+use gpui::Context;
+use rpc::{
+    common::Empty,
+    models::{
+        common::{APIResult, RPCMethod},
+        markers::UserId,
+        relationships::{
+            AcceptFriendRequest, BlockUser, CancelFriendRequest, DeclineFriendRequest,
+            GetRelationships, Relationship, RelationshipError, RelationshipKind,
+            RelationshipPayload, RelationshipUpdate, RemoveFriend, SendFriendRequest, UnblockUser,
+        },
+    },
+};
+
+use crate::ConnectionManger;
+
+pub struct RelationshipsState {
+    pub relationships: HashMap<UserId, Relationship>,
+}
+
+impl RelationshipsState {
+    pub fn new(_cx: &mut Context<Self>) -> Self {
+        Self {
+            relationships: HashMap::new(),
+        }
+    }
+
+    pub fn kind(&self, user_id: UserId) -> Option<RelationshipKind> {
+        self.relationships
+            .get(&user_id)
+            .map(|relationship| relationship.kind)
+    }
+
+    pub fn users(&self, kind: RelationshipKind) -> impl Iterator<Item = &Relationship> {
+        self.relationships
+            .values()
+            .filter(move |relationship| relationship.kind == kind)
+    }
+
+    pub fn blocked_users(&self) -> HashSet<UserId> {
+        self.users(RelationshipKind::Blocked)
+            .map(|relationship| relationship.user)
+            .collect()
+    }
+
+    fn set_kind(&mut self, user_id: UserId, kind: Option<RelationshipKind>) {
+        let Some(kind) = kind else {
+            self.relationships.remove(&user_id);
+            return;
+        };
+
+        self.relationships
+            .entry(user_id)
+            .and_modify(|relationship| relationship.kind = kind)
+            .or_insert(Relationship {
+                user: user_id,
+                kind,
+                presence: None,
+            });
+    }
+
+    pub fn fetch_relationships(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let Ok(relationships) = GetRelationships::execute(&connection, &Empty {}).await else {
+                // TODO: Send notification with an error
+                return;
+            };
+
+            this.update(cx, move |this, cx| {
+                this.relationships = relationships
+                    .into_iter()
+                    .map(|relationship| (relationship.user, relationship))
+                    .collect();
+
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    pub fn send_friend_request(&mut self, user: UserId, cx: &mut Context<Self>) {
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let payload = RelationshipPayload { user };
+
+            let Ok(kind) = SendFriendRequest::execute(&connection, &payload).await else {
+                // TODO: Send notification with an error
+                return;
+            };
+
+            this.update(cx, move |this, cx| {
+                this.set_kind(user, Some(kind));
+
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    pub fn accept_friend_request(&mut self, user: UserId, cx: &mut Context<Self>) {
+        self.update_relationship::<AcceptFriendRequest>(user, Some(RelationshipKind::Friend), cx);
+    }
+
+    pub fn decline_friend_request(&mut self, user: UserId, cx: &mut Context<Self>) {
+        self.update_relationship::<DeclineFriendRequest>(user, None, cx);
+    }
+
+    pub fn cancel_friend_request(&mut self, user: UserId, cx: &mut Context<Self>) {
+        self.update_relationship::<CancelFriendRequest>(user, None, cx);
+    }
+
+    pub fn remove_friend(&mut self, user: UserId, cx: &mut Context<Self>) {
+        self.update_relationship::<RemoveFriend>(user, None, cx);
+    }
+
+    pub fn block_user(&mut self, user: UserId, cx: &mut Context<Self>) {
+        self.update_relationship::<BlockUser>(user, Some(RelationshipKind::Blocked), cx);
+    }
+
+    pub fn unblock_user(&mut self, user: UserId, cx: &mut Context<Self>) {
+        self.update_relationship::<UnblockUser>(user, None, cx);
+    }
+
+    fn update_relationship<M>(
+        &mut self,
+        user: UserId,
+        kind: Option<RelationshipKind>,
+        cx: &mut Context<Self>,
+    ) where
+        M: RPCMethod<Request = RelationshipPayload, Response = APIResult<(), RelationshipError>>
+            + 'static,
+    {
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            if M::execute(&connection, &RelationshipPayload { user })
+                .await
+                .is_err()
+            {
+                // TODO: Send notification with an error
+                return;
+            }
+
+            this.update(cx, move |this, cx| {
+                this.set_kind(user, kind);
+
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    pub fn watch_relationship_updates(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let mut subscription = connection.subscribe::<RelationshipUpdate>();
+            while let Some(event) = subscription.recv().await {
+                this.update(cx, |this, cx| {
+                    this.set_kind(event.user, event.kind);
+
+                    cx.notify();
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+}
@@ -1,18 +1,23 @@
 use std::{
+    collections::HashSet,
     sync::{Arc, atomic::Ordering},
     time::Duration,
 };
 
 use atomic_enum::atomic_enum;
 use capture::audio::{AudioDevice, playback::AudioStreamingClientSharedState};
-use gpui::{AppContext, AsyncApp, Context, Entity, SharedString, Subscription, WeakEntity, Window};
+use gpui::{
+    App, AppContext, AsyncApp, Context, Entity, SharedString, Subscription, WeakEntity, Window,
+};
 use gpui_component::slider::{SliderEvent, SliderState, SliderValue};
 use rpc::{
     common::Empty,
     models::{
         auth::{GetUserInfo, GetUserPayload},
+        channels::{ChannelListUpdate, ChannelListUpdateMessage},
         common::RPCMethod as _,
         markers::{UserId, VoiceChannelId},
+        profiles::ProfileUpdated,
         voice::{
             GetVoiceChannels, JoinVoiceChannel, JoinVoiceChannelPayload, UpdateVoiceUserState,
             VoiceChannelUpdate, VoiceChannelUpdateMessage, VoiceUserState,
@@ -27,6 +32,7 @@ use crate::{ConnectionManger, gpui_audio::Streaming};
 pub struct VoiceChannel {
     pub id: VoiceChannelId,
     pub name: SharedString,
+    pub position: i32,
 
     pub is_active: bool,
     pub members: Vec<VoiceChannelMember>,
@@ -43,11 +49,16 @@ pub struct VoiceChannelMember {
     pub id: UserId,
     pub name: SharedString,
 
+    /// Muted by a moderator
     pub is_muted: bool,
+    /// Deafened by a moderator
+    pub is_deafened: bool,
     pub is_mic_off: bool,
     pub is_sound_off: bool,
     pub is_streaming: bool,
     pub is_talking: bool,
+    /// Blocked by the user, never played back
+    pub is_blocked: bool,
 
     pub output_volume: Entity<SliderState>,
 
@@ -68,10 +79,12 @@ impl VoiceChannelMember {
             id,
             name,
             is_muted: false,
+            is_deafened: false,
             is_mic_off: false,
             is_sound_off: false,
             is_streaming: false,
             is_talking: false,
+            is_blocked: false,
             output_volume,
             shared: None,
             // _volume_subscription: subscription,
@@ -99,9 +112,14 @@ impl VoiceChannelMember {
         let playback_state = Arc::new(AudioStreamingClientSharedState::new(self.id.value));
 
         let subscription = cx.subscribe(&self.output_volume, {
+            let id = self.id;
             let playback_state = playback_state.clone();
 
-            move |_, _, ev, _| {
+            move |this, _, ev, _| {
+                if this.blocked.contains(&id) {
+                    return;
+                }
+
                 let SliderEvent::Change(value) = ev;
                 let SliderValue::Single(value) = value else {
                     return;
@@ -123,6 +141,7 @@ impl VoiceChannelMember {
         });
 
         self.shared = Some(shared);
+        self.apply_volume(cx);
 
         Streaming::add_voice_member(cx, Arc::downgrade(&playback_state));
     }
@@ -130,6 +149,24 @@ impl VoiceChannelMember {
     pub fn unregister(&mut self) {
         self.shared = None;
     }
+
+    /// Applies the volume slider to the playback, blocked users are muted
+    fn apply_volume(&self, cx: &App) {
+        let Some(shared) = self.shared.as_ref() else {
+            return;
+        };
+
+        let volume = match self.output_volume.read(cx).value() {
+            SliderValue::Single(value) if !self.is_blocked => (value / 100.).powf(3.),
+            _ => 0.,
+        };
+
+        shared
+            .read(cx)
+            .playback
+            .volume
+            .store(volume, Ordering::Relaxed);
+    }
 }
 
 #[atomic_enum]
@@ -170,6 +207,11 @@ pub struct StreamingState {
     pub input_devices: Vec<AudioDevice>,
     pub output_devices: Vec<AudioDevice>,
 
+    /// Users blocked by the user
+    blocked: HashSet<UserId>,
+    /// Version of the last applied voice channel update
+    voice_version: u64,
+
     noise_reduction: NoiseReductionAlgorithm,
 }
 
@@ -196,6 +238,9 @@ impl StreamingState {
             input_devices: vec![],
             output_devices: vec![],
 
+            blocked: HashSet::new(),
+            voice_version: 0,
+
             is_playback_enabled: true,
             is_capture_enabled: true,
 
@@ -225,6 +270,21 @@ impl StreamingState {
 }
 
 impl StreamingState {
+    pub fn set_blocked(&mut self, blocked: HashSet<UserId>, cx: &mut Context<Self>) {
+        for member in self
+            .voice_channels
+            .iter_mut()
+            .flat_map(|channel| channel.members.iter_mut())
+        {
+            member.is_blocked = blocked.contains(&member.id);
+            member.apply_volume(cx);
+        }
+
+        self.blocked = blocked;
+
+        cx.notify();
+    }
+
     pub fn noise_reduction(&self) -> NoiseReductionAlgorithm {
         self.noise_reduction
     }
@@ -382,27 +442,92 @@ impl StreamingState {
         .detach();
     }
 
+    /// Moves a member between channels after a moderator did it on the server
+    fn move_member(
+        &mut self,
+        user_id: UserId,
+        from: VoiceChannelId,
+        to: VoiceChannelId,
+        current_user: Option<UserId>,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(source) = self.get_voice_channel_mut(from) else {
+            return;
+        };
+
+        let Some(index) = source.members.iter().position(|user| user.id == user_id) else {
+            return;
+        };
+
+        let mut member = source.members.remove(index);
+        member.unregister();
+
+        let is_current_user = current_user == Some(user_id);
+
+        // We were moved, so playback follows us into the new channel
+        if is_current_user {
+            source.is_active = false;
+
+            for member in source.members.iter_mut() {
+                member.unregister();
+            }
+        }
+
+        let Some(target) = self.get_voice_channel_mut(to) else {
+            return;
+        };
+
+        if is_current_user {
+            target.is_active = true;
+
+            for member in target.members.iter_mut() {
+                member.register(cx);
+            }
+        } else if target.is_active {
+            member.register(cx);
+        }
+
+        target.members.push(member);
+
+        cx.notify();
+    }
+
     async fn fetch_channels_inner(this: &WeakEntity<Self>, cx: &mut AsyncApp) {
         let connection = ConnectionManger::get(cx);
 
         let response = GetVoiceChannels::execute(&connection, &Empty {}).await;
 
-        let Ok(channels) = response else {
+        let Ok(snapshot) = response else {
             // TODO: Send notification with an error
             return;
         };
 
         this.update(cx, move |this, cx| {
-            this.voice_channels = channels
+            this.voice_version = snapshot.version;
+            this.voice_channels = snapshot
+                .channels
                 .into_iter()
                 .map(|channel| VoiceChannel {
                     id: channel.id,
                     name: channel.name.into(),
+                    position: channel.position,
                     is_active: false,
                     members: channel
                         .members
                         .into_iter()
-                        .map(|member| VoiceChannelMember::new(member.id, member.name.into(), cx))
+                        .map(|member| {
+                            let mut result =
+                                VoiceChannelMember::new(member.id, member.name.into(), cx);
+
+                            result.is_blocked = this.blocked.contains(&member.id);
+                            result.is_mic_off = member.is_muted;
+                            result.is_sound_off = member.is_sound_off;
+                            result.is_streaming = member.is_streaming;
+                            result.is_muted = member.server_state.is_server_muted;
+                            result.is_deafened = member.server_state.is_server_deafened;
+
+                            result
+                        })
                         .collect(),
                 })
                 .collect();
@@ -410,6 +535,38 @@ impl StreamingState {
         .ok();
     }
 
+    /// Replaces the channels with a fresh snapshot, keeping the active channel
+    async fn resync_channels(this: &WeakEntity<Self>, cx: &mut AsyncApp) {
+        let active_channel = this
+            .read_with(cx, |this, _cx| {
+                this.get_active_channel().map(|channel| channel.id)
+            })
+            .unwrap();
+
+        Self::fetch_channels_inner(this, cx).await;
+
+        let Some(active_channel) = active_channel else {
+            return;
+        };
+
+        let user_id = ConnectionManger::get_user_id(cx);
+
+        this.update(cx, move |this, cx| {
+            if let Some(channel) = this.get_voice_channel_mut(active_channel) {
+                channel.is_active = true;
+
+                for member in channel.members.iter_mut() {
+                    if Some(member.id) != user_id {
+                        member.register(cx);
+                    }
+                }
+
+                cx.notify();
+            }
+        })
+        .ok();
+    }
+
     pub fn fetch_voice_channels(&mut self, cx: &mut Context<Self>) {
         cx.spawn(async |this, cx| {
             Self::fetch_channels_inner(&this, cx).await;
@@ -423,6 +580,23 @@ impl StreamingState {
 
             let mut subscription = connection.subscribe::<VoiceChannelUpdate>();
             while let Some(event) = subscription.recv().await {
+                let version = this.read_with(cx, |this, _cx| this.voice_version).unwrap();
+
+                // The snapshot already has this update
+                if event.version <= version {
+                    continue;
+                }
+
+                // An update was missed, the state can't be trusted anymore
+                if event.version != version + 1 {
+                    Self::resync_channels(&this, cx).await;
+
+                    continue;
+                }
+
+                this.update(cx, |this, _cx| this.voice_version = event.version)
+                    .ok();
+
                 let channel_id = event.channel_id;
                 let channel = this
                     .read_with(cx, |this, _cx| this.get_voice_channel(channel_id).cloned())
@@ -431,22 +605,7 @@ impl StreamingState {
                 let Some(channel) = channel else {
                     // If there's no such channel, fetch updates
                     // and skip processing
-                    let active_channel = this
-                        .read_with(cx, |this, _cx| this.get_active_channel().cloned())
-                        .unwrap();
-
-                    Self::fetch_channels_inner(&this, cx).await;
-
-                    if let Some(channel) = active_channel {
-                        this.update(cx, move |this, cx| {
-                            if let Some(channel) = this.get_voice_channel_mut(channel.id) {
-                                channel.is_active = true;
-
-                                cx.notify();
-                            }
-                        })
-                        .ok();
-                    }
+                    Self::resync_channels(&this, cx).await;
 
                     continue;
                 };
@@ -474,7 +633,8 @@ impl StreamingState {
                             };
 
                             let mut member =
-                                VoiceChannelMember::new(user.id, user.username.into(), cx);
+                                VoiceChannelMember::new(user.id, user.name().to_owned().into(), cx);
+                            member.is_blocked = this.blocked.contains(&user.id);
 
                             if channel.is_active {
                                 member.register(cx);
@@ -487,6 +647,8 @@ impl StreamingState {
                         .ok();
                     }
                     VoiceChannelUpdateMessage::UserDisconnected(user_id) => {
+                        let current_user = ConnectionManger::get_user_id(cx);
+
                         this.update(cx, |this, cx| {
                             let Some(channel) = this.get_voice_channel_mut(channel_id) else {
                                 return;
@@ -494,6 +656,15 @@ impl StreamingState {
 
                             channel.members.retain(|user| user.id != user_id);
 
+                            // The server dropped us, e.g. the channel is being deleted
+                            if current_user == Some(user_id) {
+                                channel.is_active = false;
+
+                                for member in channel.members.iter_mut() {
+                                    member.unregister();
+                                }
+                            }
+
                             cx.notify();
                         })
                         .ok();
@@ -515,12 +686,111 @@ impl StreamingState {
                         })
                         .ok();
                     }
+                    VoiceChannelUpdateMessage::UserServerStateUpdated((user_id, state)) => {
+                        this.update(cx, |this, cx| {
+                            let Some(channel) = this.get_voice_channel_mut(channel_id) else {
+                                return;
+                            };
+
+                            if let Some(user) =
+                                channel.members.iter_mut().find(|user| user.id == user_id)
+                            {
+                                user.is_muted = state.is_server_muted;
+                                user.is_deafened = state.is_server_deafened;
+
+                                cx.notify();
+                            }
+                        })
+                        .ok();
+                    }
+                    VoiceChannelUpdateMessage::UserMoved((user_id, target_id)) => {
+                        let current_user = ConnectionManger::get_user_id(cx);
+
+                        this.update(cx, |this, cx| {
+                            this.move_member(user_id, channel_id, target_id, current_user, cx);
+                        })
+                        .ok();
+                    }
                 }
             }
         })
         .detach();
     }
 
+    /// Keeps names of voice channel members up to date
+    pub fn watch_profile_updates(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let mut subscription = connection.subscribe::<ProfileUpdated>();
+            while let Some(event) = subscription.recv().await {
+                this.update(cx, |this, cx| {
+                    let name = SharedString::from(event.user.name().to_owned());
+
+                    for channel in this.voice_channels.iter_mut() {
+                        for member in channel.members.iter_mut() {
+                            if member.id == event.user.id {
+                                member.name = name.clone();
+                            }
+                        }
+                    }
+
+                    cx.notify();
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+
+    pub fn watch_channel_list_updates(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async move |this, cx| {
+            let connection = ConnectionManger::get(cx);
+
+            let mut subscription = connection.subscribe::<ChannelListUpdate>();
+            while let Some(event) = subscription.recv().await {
+                this.update(cx, |this, cx| {
+                    match event.message {
+                        ChannelListUpdateMessage::VoiceChannelCreated(channel) => {
+                            if this.get_voice_channel(channel.id).is_some() {
+                                return;
+                            }
+
+                            this.voice_channels.push(VoiceChannel {
+                                id: channel.id,
+                                name: channel.name.into(),
+                                position: channel.position,
+                                is_active: false,
+                                members: vec![],
+                            });
+                        }
+                        ChannelListUpdateMessage::VoiceChannelUpdated(channel) => {
+                            let Some(existing) = this.get_voice_channel_mut(channel.id) else {
+                                return;
+                            };
+
+                            existing.name = channel.name.into();
+                            existing.position = channel.position;
+                        }
+                        ChannelListUpdateMessage::VoiceChannelDeleted(id) => {
+                            // Members are unregistered from playback once they're dropped
+                            this.voice_channels.retain(|channel| channel.id != id);
+                        }
+                        // Text channels are handled by the chat state
+                        _ => return,
+                    }
+
+                    this.voice_channels
+                        .sort_by_key(|channel| (channel.position, channel.id.value));
+
+                    cx.notify();
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+
     pub fn watch_streaming_state_updates(&mut self, cx: &mut Context<Self>) {
         cx.spawn(async move |this, cx| {
             let mut subscription = Streaming::get_device_registry(cx).subscribe();
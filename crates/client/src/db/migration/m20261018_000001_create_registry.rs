@@ -0,0 +1,37 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+/// The table is only created if it's missing, so profiles created
+/// by schema sync are adopted as they are
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Registry {
+    Table,
+    Id,
+    SessionKey,
+    ConnectedServer,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Registry::Table)
+                    .if_not_exists()
+                    .col(pk_auto(Registry::Id))
+                    .col(blob_null(Registry::SessionKey))
+                    .col(string_null(Registry::ConnectedServer))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Registry::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,11 @@
+use sea_orm_migration::{MigrationTrait, MigratorTrait};
+
+mod m20261018_000001_create_registry;
+
+pub struct Migrator;
+
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![Box::new(m20261018_000001_create_registry::Migration)]
+    }
+}
@@ -1,11 +1,14 @@
 use gpui::{AsyncApp, Global};
-use sea_orm::{ActiveModelTrait, Database, DatabaseConnection, EntityTrait};
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, Database, DatabaseConnection, EntityTrait};
+use sea_orm_migration::MigratorTrait as _;
 
 use crate::gpui_tokio::Tokio;
 
 use entity::registry::{self, Entity as Registry, Model as RegistryModel};
+use migration::Migrator;
 
 pub mod entity;
+mod migration;
 
 pub struct DBConnectionManager {
     db: DatabaseConnection,
@@ -17,10 +20,8 @@ impl DBConnectionManager {
             .await
             .unwrap();
 
-        db.get_schema_registry("hazel_client::db::entity::*")
-            .sync(&db)
-            .await
-            .unwrap();
+        // Fails if the profile was used by a newer version of the client
+        Migrator::up(&db, None).await.unwrap();
 
         Self { db }
     }
@@ -43,6 +44,15 @@ impl DBConnectionManager {
             }
         }
     }
+
+    pub async fn store_session_key(db: &DatabaseConnection, session_key: Vec<u8>) {
+        let registry = Self::get_registry(db).await;
+        let mut registry: registry::ActiveModel = registry.into();
+
+        registry.session_key = Set(Some(session_key));
+
+        registry.update(db).await.unwrap();
+    }
 }
 
 impl Global for DBConnectionManager {}
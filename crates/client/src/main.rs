@@ -16,6 +16,7 @@ use rpc::{
         auth::{Login, LoginPayload, SessionKey},
         common::RPCMethod,
         markers::{Id, UserId},
+        permissions::{GetPermissions, GetPermissionsPayload, Permissions},
     },
 };
 
@@ -52,6 +53,9 @@ impl MainWindow {
             this.init(cx);
         });
 
+        cx.spawn(async move |_, cx| ConnectionManger::fetch_permissions(cx).await)
+            .detach();
+
         cx.notify();
     }
 }
@@ -61,6 +65,10 @@ pub struct ConnectionManger {
 
     user_id: Option<UserId>,
     server_ip: Option<String>,
+
+    /// Server-wide permissions of the current user,
+    /// used to hide controls the user can't use
+    permissions: Permissions,
 }
 
 impl ConnectionManger {
@@ -69,9 +77,29 @@ impl ConnectionManger {
             conn: None,
             user_id: None,
             server_ip: None,
+            permissions: Permissions::NONE,
         }
     }
 
+    pub fn get_permissions<C: AppContext>(cx: &C) -> Permissions {
+        cx.read_global(|g: &Self, _| g.permissions)
+    }
+
+    pub async fn fetch_permissions(cx: &mut AsyncApp) {
+        let connection = Self::get(cx);
+
+        let response =
+            GetPermissions::execute(&connection, &GetPermissionsPayload { channel: None }).await;
+
+        let Ok(permissions) = response else {
+            return;
+        };
+
+        cx.update_global(|g: &mut Self, _| {
+            g.permissions = permissions;
+        });
+    }
+
     pub fn get_user_id<C: AppContext>(cx: &C) -> Option<UserId> {
         cx.read_global(|g: &Self, _| g.user_id)
     }
@@ -266,7 +294,21 @@ fn main() {
                                         Id::new(session_key.body.user_id),
                                     );
 
-                                    if result.is_ok() {
+                                    if let Ok(session_key) = result {
+                                        // The server prolongs the session on every login
+                                        let db = DBConnectionManager::get(cx);
+                                        let session_key = rmp_serde::to_vec(&session_key).unwrap();
+
+                                        Tokio::spawn(cx, async move {
+                                            DBConnectionManager::store_session_key(
+                                                &db,
+                                                session_key,
+                                            )
+                                            .await;
+                                        })
+                                        .await
+                                        .ok();
+
                                         view.update(cx, |this, cx| {
                                             this.set_workspace_screen(cx);
                                         });
@@ -11,7 +11,7 @@ use gpui_component::{
 };
 use rpc::models::{
     auth::{
-        GetSessionKey, GetSessionKeyError, GetSessionKeyPayload, GetSessionKeyResponse, LoginError,
+        GetSessionKey, GetSessionKeyError, GetSessionKeyPayload, GetSessionKeyResponse, Login,
         LoginPayload,
     },
     common::{APIError, RPCMethod},
@@ -143,6 +143,8 @@ impl LoginScreen {
                 &GetSessionKeyPayload {
                     login: login.into(),
                     password: password.into(),
+                    device_name: device_name(),
+                    invite: None,
                 },
             )
             .await;
@@ -160,6 +162,15 @@ impl LoginScreen {
                         }
                     };
 
+                    let session_key = Login::execute(
+                        &connection,
+                        &LoginPayload {
+                            session_key: session_key.clone(),
+                        },
+                    )
+                    .await
+                    .expect("We just logged in, it should not fail");
+
                     let db = DBConnectionManager::get(cx);
                     let session_key_bytes = rmp_serde::to_vec(&session_key).unwrap();
                     Tokio::spawn(cx, async move {
@@ -173,18 +184,6 @@ impl LoginScreen {
                     })
                     .await?;
 
-                    let data: Result<(), LoginError> = connection
-                        .execute(
-                            "Login",
-                            &LoginPayload {
-                                session_key: session_key.clone(),
-                            },
-                        )
-                        .await
-                        .expect("invalid params");
-
-                    data.expect("We just logged in, it should not fail");
-
                     ConnectionManger::set_user_id(cx, Id::new(session_key.body.user_id));
 
                     // Notify parent component that we're logged in
@@ -198,6 +197,14 @@ impl LoginScreen {
                         tx.send(ConnectionResult::Failed("incorrect password".to_string()))
                             .await?;
                     }
+                    APIError::RateLimited { retry_after_ms } => {
+                        let message = format!(
+                            "too many attempts, try again in {}s",
+                            retry_after_ms.div_ceil(1000)
+                        );
+
+                        tx.send(ConnectionResult::Failed(message)).await?;
+                    }
                     _ => {
                         tx.send(ConnectionResult::Failed(format!("{err:?}")))
                             .await?
@@ -220,6 +227,13 @@ impl LoginScreen {
 
 const INPUT_BG: u32 = 0x262626;
 
+/// Name of the device shown to the user in the list of active sessions
+fn device_name() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .unwrap_or_else(|_| std::env::consts::OS.into())
+}
+
 impl LoginScreen {
     fn create_input(
         &self,
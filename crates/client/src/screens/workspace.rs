@@ -1,6 +1,6 @@
 use gpui::{
-    AppContext, Context, Entity, IntoElement as _, ParentElement as _, Render, Styled, Window, div,
-    px,
+    AppContext, Context, Entity, InteractiveElement as _, IntoElement as _, ParentElement as _,
+    Render, Styled, Window, div, px,
 };
 use gpui_component::{
     StyledExt,
@@ -14,12 +14,16 @@ use crate::components::{
     left_sidebar::{
         ControlPanel, text_channels::TextChannelsComponent, voice_channels::VoiceChannelsComponent,
     },
+    presence_state::PresenceState,
+    relationships_state::RelationshipsState,
     streaming_state::StreamingState,
 };
 
 pub struct WorkspaceScreen {
     chat: Entity<ChatState>,
     streaming: Entity<StreamingState>,
+    presence: Entity<PresenceState>,
+    relationships: Entity<RelationshipsState>,
 
     text_card: Entity<CollapsableCardState>,
     voice_card: Entity<CollapsableCardState>,
@@ -27,17 +31,59 @@ pub struct WorkspaceScreen {
 
 impl WorkspaceScreen {
     pub fn init<C: AppContext>(&self, cx: &mut C) {
+        self.chat.update(cx, |this, cx| {
+            this.fetch_text_channels(cx);
+            this.fetch_mentions(cx);
+
+            this.watch_message_updates(cx);
+            this.watch_message_edits(cx);
+            this.watch_reaction_updates(cx);
+            this.watch_typing_updates(cx);
+            this.watch_read_state_updates(cx);
+            this.watch_mentions(cx);
+            this.watch_profile_updates(cx);
+            this.watch_channel_list_updates(cx);
+        });
+
         self.streaming.update(cx, |this, cx| {
             this.fetch_voice_channels(cx);
 
             this.watch_voice_channel_updates(cx);
+            this.watch_channel_list_updates(cx);
             this.watch_streaming_state_updates(cx);
+            this.watch_profile_updates(cx);
+        });
+
+        self.presence.update(cx, |this, cx| {
+            this.fetch_presences(cx);
+
+            this.watch_presence_updates(cx);
+            this.watch_idle(cx);
+        });
+
+        self.relationships.update(cx, |this, cx| {
+            this.fetch_relationships(cx);
+
+            this.watch_relationship_updates(cx);
         });
     }
 
     pub fn new(window: &mut Window, cx: &mut Context<Self>) -> Self {
         let chat = cx.new(|cx| ChatState::new(window, cx));
         let streaming = cx.new(StreamingState::new);
+        let presence = cx.new(PresenceState::new);
+        let relationships = cx.new(RelationshipsState::new);
+
+        // Blocked users are hidden from the chat and muted in voice
+        cx.observe(&relationships, |this, relationships, cx| {
+            let blocked = relationships.read(cx).blocked_users();
+
+            this.chat
+                .update(cx, |chat, cx| chat.set_blocked(blocked.clone(), cx));
+            this.streaming
+                .update(cx, |streaming, cx| streaming.set_blocked(blocked, cx));
+        })
+        .detach();
 
         let text_card = cx.new(|_| CollapsableCardState::new());
         let voice_card = cx.new(|_| CollapsableCardState::new());
@@ -45,6 +91,8 @@ impl WorkspaceScreen {
         Self {
             chat,
             streaming,
+            presence,
+            relationships,
 
             text_card,
             voice_card,
@@ -55,10 +103,10 @@ impl WorkspaceScreen {
 impl Render for WorkspaceScreen {
     fn render(
         &mut self,
-        _window: &mut gpui::Window,
+        window: &mut gpui::Window,
         _cx: &mut gpui::Context<Self>,
     ) -> impl gpui::IntoElement {
-        h_resizable("my-layout")
+        let layout = h_resizable("my-layout")
             .on_resize(|state, _window, cx| {
                 // Handle resize event
                 // You can read the panel sizes from the state.
@@ -88,6 +136,17 @@ impl Render for WorkspaceScreen {
                     .items_center()
                     .child("CHAT IS IN PROGRESS")
                     .into_any_element(),
-            )
+            );
+
+        // Any input counts as activity for the idle status
+        div()
+            .size_full()
+            .on_mouse_move(window.listener_for(&self.presence, |state, _, _, cx| {
+                state.notify_activity(cx);
+            }))
+            .on_key_down(window.listener_for(&self.presence, |state, _, _, cx| {
+                state.notify_activity(cx);
+            }))
+            .child(layout)
     }
 }
@@ -0,0 +1,111 @@
+use rpc_macros::rpc_method;
+use serde::{Deserialize, Serialize};
+
+use crate::models::markers::{AuditLogEntryId, ChannelId, InviteId, RoleId, SessionId, UserId};
+
+/// Administrative or moderation action recorded in the audit log
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum AuditAction {
+    ChannelCreate,
+    ChannelUpdate,
+    ChannelDelete,
+    ChannelReorder,
+    ChannelOverrideUpdate,
+
+    RoleCreate,
+    RoleUpdate,
+    RoleDelete,
+    RoleAssign,
+    RoleUnassign,
+
+    MemberKick,
+    MemberBan,
+    MemberUnban,
+    VoiceServerStateUpdate,
+    VoiceMemberMove,
+
+    SessionRevoke,
+
+    InviteCreate,
+}
+
+/// Entity an action was applied to
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum AuditTarget {
+    User(UserId),
+    Channel(ChannelId),
+    Role(RoleId),
+    Session(SessionId),
+    Invite(InviteId),
+}
+
+impl From<UserId> for AuditTarget {
+    fn from(id: UserId) -> Self {
+        Self::User(id)
+    }
+}
+
+impl From<ChannelId> for AuditTarget {
+    fn from(id: ChannelId) -> Self {
+        Self::Channel(id)
+    }
+}
+
+impl From<RoleId> for AuditTarget {
+    fn from(id: RoleId) -> Self {
+        Self::Role(id)
+    }
+}
+
+impl From<SessionId> for AuditTarget {
+    fn from(id: SessionId) -> Self {
+        Self::Session(id)
+    }
+}
+
+impl From<InviteId> for AuditTarget {
+    fn from(id: InviteId) -> Self {
+        Self::Invite(id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditLogEntry {
+    pub id: AuditLogEntryId,
+    pub actor: UserId,
+    pub action: AuditAction,
+    pub target: Option<AuditTarget>,
+    pub reason: Option<String>,
+    /// JSON encoded state of the target before the action
+    pub before: Option<String>,
+    /// JSON encoded state of the target after the action
+    pub after: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct GetAuditLogPayload {
+    /// Only entries older than this one are returned
+    pub before: Option<AuditLogEntryId>,
+    /// Page size, the server picks a default when it's 0
+    pub limit: u32,
+
+    pub actor: Option<UserId>,
+    pub action: Option<AuditAction>,
+    pub target: Option<AuditTarget>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuditLogPage {
+    /// Ordered from the newest to the oldest
+    pub entries: Vec<AuditLogEntry>,
+    pub has_more: bool,
+}
+
+// Requires the administrator permission
+#[rpc_method]
+pub struct GetAuditLog {
+    request: GetAuditLogPayload,
+    response: AuditLogPage,
+    error: (),
+}
@@ -1,11 +1,14 @@
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Utc};
 use hmac::{Hmac, Mac};
-use rpc_macros::rpc_method;
+use rpc_macros::{RPCNotification, rpc_method};
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use thiserror::Error;
 
-use crate::models::markers::UserId;
+use crate::{
+    common::Empty,
+    models::markers::{MediaId, SessionId, UserId},
+};
 
 type HmacSha256 = Hmac<Sha256>;
 
@@ -23,12 +26,17 @@ pub enum LoginError {
     SessionKeyExpired,
     #[error("Wasn't able to find requested User")]
     UserNotFound,
+    #[error("Session was revoked")]
+    SessionRevoked,
+    #[error("User is banned")]
+    UserBanned,
 }
 
+// Responds with a renewed Session Key that should replace the old one
 #[rpc_method]
 pub struct Login {
     request: LoginPayload,
-    response: (),
+    response: SessionKey,
     error: LoginError,
 }
 
@@ -36,10 +44,19 @@ pub struct Login {
 pub struct GetSessionKeyPayload {
     pub login: String,
     pub password: String,
+    /// Human readable name of the device, shown in the list of sessions
+    pub device_name: String,
+    /// Required to register when the server is invite only
+    #[serde(default)]
+    pub invite: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SessionKeyBody {
+    /// ID of the server key that was used to sign the body,
+    /// several keys can be valid at the same time during a rotation
+    pub key_id: u32,
+    pub session_id: i32,
     pub user_id: i32,
     pub expires_at: i64,
 }
@@ -51,6 +68,8 @@ impl SessionKeyBody {
 
         let mut payload = Vec::<u8>::new();
 
+        payload.extend_from_slice(&self.key_id.to_le_bytes());
+        payload.extend_from_slice(&self.session_id.to_le_bytes());
         payload.extend_from_slice(&self.user_id.to_le_bytes());
         payload.extend_from_slice(&self.expires_at.to_le_bytes());
 
@@ -67,15 +86,7 @@ pub struct SessionKey {
 }
 
 impl SessionKey {
-    pub fn new(user_id: i32, key: &[u8]) -> Self {
-        let expires_at = Utc::now() + Duration::days(1); // TODO: Change it
-        let timestamp = expires_at.timestamp();
-
-        let body = SessionKeyBody {
-            user_id,
-            expires_at: timestamp,
-        };
-
+    pub fn new(body: SessionKeyBody, key: &[u8]) -> Self {
         let sign = body.create_mac(key)
             .finalize()
             .into_bytes()
@@ -123,6 +134,10 @@ pub enum GetSessionKeyResponse {
 pub enum GetSessionKeyError {
     #[error("User with this login already exists")]
     UserAlreadyExists,
+    #[error("User is banned")]
+    UserBanned,
+    #[error("A valid invite is required to register")]
+    InviteRequired,
 }
 
 #[rpc_method]
@@ -139,10 +154,23 @@ pub enum GetCurrentUserError {
     ServerError,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct UserInfo {
     pub id: UserId,
     pub username: String,
+    /// Shown instead of the username when it's set
+    pub display_name: Option<String>,
+    pub avatar: Option<MediaId>,
+    pub bio: Option<String>,
+    /// RGB color
+    pub accent_color: Option<u32>,
+}
+
+impl UserInfo {
+    /// Name that should be shown to other users
+    pub fn name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.username)
+    }
 }
 
 
@@ -157,3 +185,108 @@ pub struct GetUserInfo {
     response: Option<UserInfo>,
     error: GetCurrentUserError,
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChangePasswordPayload {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Error, Debug)]
+pub enum ChangePasswordError {
+    #[error("Current password is wrong")]
+    WrongPassword,
+    #[error("New password can't be empty")]
+    InvalidPassword,
+}
+
+// Other sessions of the user are revoked, the current one stays logged in
+#[rpc_method]
+pub struct ChangePassword {
+    request: ChangePasswordPayload,
+    response: (),
+    error: ChangePasswordError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionInfo {
+    pub id: SessionId,
+    pub device_name: String,
+    pub ip_address: String,
+
+    pub created_at: i64,
+    pub last_used_at: i64,
+
+    /// Whether it's the session of the current connection
+    pub is_current: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error("Session does not exist")]
+    NotFound,
+}
+
+#[rpc_method]
+pub struct Logout {
+    request: Empty,
+    response: (),
+    error: SessionError,
+}
+
+#[rpc_method]
+pub struct ListSessions {
+    request: Empty,
+    response: Vec<SessionInfo>,
+    error: (),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RevokeSessionPayload {
+    pub id: SessionId,
+}
+
+#[rpc_method]
+pub struct RevokeSession {
+    request: RevokeSessionPayload,
+    response: (),
+    error: SessionError,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum SessionTerminationReason {
+    LoggedOut,
+    Revoked,
+    Kicked,
+    Banned,
+}
+
+/// Sent right before the server closes the connection
+#[derive(Serialize, Deserialize, Debug, RPCNotification)]
+pub struct SessionTerminated {
+    pub reason: SessionTerminationReason,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RotateSessionKeyPayload {
+    /// Drop all previous keys, which invalidates every session signed with them
+    pub retire_previous: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Error, Debug)]
+pub enum RotateSessionKeyError {
+    #[error("Session keys are provided by the server config")]
+    ManagedByConfig,
+}
+
+// Generates a new signing key and makes it the current one.
+// Responds with ID of the new key
+#[rpc_method]
+pub struct RotateSessionKey {
+    request: RotateSessionKeyPayload,
+    response: u32,
+    error: RotateSessionKeyError,
+}
@@ -0,0 +1,153 @@
+use rpc_macros::{RPCNotification, rpc_method};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    common::Empty,
+    models::markers::{ChannelId, TextChannelId, VoiceChannelId},
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TextChannel {
+    pub id: TextChannelId,
+    pub name: String,
+    pub position: i32,
+    pub category: Option<String>,
+}
+
+/// Voice channel without its members
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VoiceChannelInfo {
+    pub id: VoiceChannelId,
+    pub name: String,
+    pub position: i32,
+    pub category: Option<String>,
+
+    /// Zero means there's no limit
+    pub user_limit: u32,
+    /// Bits per second clients should encode audio with
+    pub bitrate: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TextChannelSettings {
+    pub name: String,
+    pub position: i32,
+    pub category: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VoiceChannelSettings {
+    pub name: String,
+    pub position: i32,
+    pub category: Option<String>,
+
+    pub user_limit: u32,
+    pub bitrate: u32,
+}
+
+#[derive(Serialize, Deserialize, Error, Debug)]
+pub enum ChannelError {
+    #[error("Channel does not exist")]
+    NotFound,
+    #[error("Channel name should be between 1 and 100 characters long")]
+    InvalidName,
+    #[error("Bitrate is out of the supported range")]
+    InvalidBitrate,
+}
+
+#[rpc_method]
+pub struct GetTextChannels {
+    request: Empty,
+    response: Vec<TextChannel>,
+    error: (),
+}
+
+#[rpc_method]
+pub struct CreateTextChannel {
+    request: TextChannelSettings,
+    response: TextChannelId,
+    error: ChannelError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateTextChannelPayload {
+    pub id: TextChannelId,
+    pub settings: TextChannelSettings,
+}
+
+#[rpc_method]
+pub struct UpdateTextChannel {
+    request: UpdateTextChannelPayload,
+    response: (),
+    error: ChannelError,
+}
+
+#[rpc_method]
+pub struct CreateVoiceChannel {
+    request: VoiceChannelSettings,
+    response: VoiceChannelId,
+    error: ChannelError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateVoiceChannelPayload {
+    pub id: VoiceChannelId,
+    pub settings: VoiceChannelSettings,
+}
+
+#[rpc_method]
+pub struct UpdateVoiceChannel {
+    request: UpdateVoiceChannelPayload,
+    response: (),
+    error: ChannelError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteChannelPayload {
+    pub channel: ChannelId,
+}
+
+// Users connected to a deleted voice channel are disconnected from it
+#[rpc_method]
+pub struct DeleteChannel {
+    request: DeleteChannelPayload,
+    response: (),
+    error: ChannelError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChannelPosition {
+    pub channel: ChannelId,
+    pub position: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReorderChannelsPayload {
+    pub positions: Vec<ChannelPosition>,
+}
+
+// Applies all positions at once, either every channel is moved or none
+#[rpc_method]
+pub struct ReorderChannels {
+    request: ReorderChannelsPayload,
+    response: (),
+    error: ChannelError,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ChannelListUpdateMessage {
+    TextChannelCreated(TextChannel),
+    TextChannelUpdated(TextChannel),
+    TextChannelDeleted(TextChannelId),
+
+    VoiceChannelCreated(VoiceChannelInfo),
+    VoiceChannelUpdated(VoiceChannelInfo),
+    VoiceChannelDeleted(VoiceChannelId),
+}
+
+/// Sent to everyone whenever the list of channels changes
+#[derive(Serialize, Deserialize, Debug, RPCNotification)]
+pub struct ChannelListUpdate {
+    pub message: ChannelListUpdateMessage,
+}
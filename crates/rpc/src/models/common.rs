@@ -13,6 +13,10 @@ pub enum APIError<T: Debug> {
     Err(T),
     ServerError,
     Unauthorized,
+    /// The user is authenticated, but lacks a permission
+    Forbidden,
+    /// Too many requests, the method can be called again after the delay
+    RateLimited { retry_after_ms: u64 },
 }
 
 pub type APIResult<T, E> = Result<T, APIError<E>>;
@@ -61,4 +65,12 @@ pub trait RPCNotification: Serialize + DeserializeOwned {
             None,
         ).await
     }
+
+    /// Like `notify`, but gives up instead of waiting if the client's queue is full
+    fn try_notify(self, writer: &RpcWriter) -> bool
+    where
+        Self: Sized,
+    {
+        writer.try_write(Self::key().into(), self, None)
+    }
 }
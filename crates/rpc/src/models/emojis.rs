@@ -0,0 +1,63 @@
+use rpc_macros::rpc_method;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    common::Empty,
+    models::markers::{EmojiId, MediaId},
+};
+
+/// Emoji uploaded to the server, used in reactions as `Emoji::Custom`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CustomEmoji {
+    pub id: EmojiId,
+    /// Used as `:name:` in messages
+    pub name: String,
+    /// Image of the emoji
+    pub media: MediaId,
+}
+
+#[derive(Serialize, Deserialize, Error, Debug)]
+pub enum EmojiError {
+    #[error("Emoji does not exist")]
+    NotFound,
+    #[error("Name should be 2 to 32 letters, digits or underscores")]
+    InvalidName,
+    #[error("Emoji with this name already exists")]
+    NameTaken,
+    #[error("Emoji should be an image uploaded by the user")]
+    InvalidMedia,
+}
+
+#[rpc_method]
+pub struct GetCustomEmojis {
+    request: Empty,
+    response: Vec<CustomEmoji>,
+    error: (),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateCustomEmojiPayload {
+    pub name: String,
+    pub media: MediaId,
+}
+
+#[rpc_method]
+pub struct CreateCustomEmoji {
+    request: CreateCustomEmojiPayload,
+    response: CustomEmoji,
+    error: EmojiError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteCustomEmojiPayload {
+    pub id: EmojiId,
+}
+
+// Also removes all reactions with the emoji
+#[rpc_method]
+pub struct DeleteCustomEmoji {
+    request: DeleteCustomEmojiPayload,
+    response: (),
+    error: EmojiError,
+}
@@ -0,0 +1,136 @@
+use rpc_macros::{RPCNotification, rpc_method};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    common::Empty,
+    models::markers::{GroupId, UserId},
+};
+
+/// Either a 1:1 direct conversation or a named group.
+/// Messages of both are delivered as `TextMessageChannel::GroupChannel`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Conversation {
+    pub id: GroupId,
+    /// Direct conversations don't have a name
+    pub name: Option<String>,
+    pub is_direct: bool,
+    /// Direct conversations don't have an owner
+    pub owner: Option<UserId>,
+    pub members: Vec<UserId>,
+    /// Unix timestamp of the latest message or change
+    pub last_activity_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Error, Debug)]
+pub enum GroupError {
+    #[error("Conversation does not exist")]
+    NotFound,
+    #[error("User does not exist")]
+    UserNotFound,
+    #[error("Direct conversations can't be changed")]
+    DirectConversation,
+    #[error("Group name should be between 1 and 100 characters long")]
+    InvalidName,
+    #[error("Group has too many members")]
+    TooManyMembers,
+    #[error("User is not a member of the group")]
+    NotMember,
+    #[error("One of the users blocked you or was blocked by you")]
+    Blocked,
+}
+
+// Sorted by the latest activity, the most recent go first
+#[rpc_method]
+pub struct GetConversations {
+    request: Empty,
+    response: Vec<Conversation>,
+    error: (),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OpenDirectConversationPayload {
+    pub user_id: UserId,
+}
+
+// Returns the existing conversation with the user or creates a new one
+#[rpc_method]
+pub struct OpenDirectConversation {
+    request: OpenDirectConversationPayload,
+    response: Conversation,
+    error: GroupError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateGroupPayload {
+    pub name: String,
+    /// The creator is added automatically
+    pub members: Vec<UserId>,
+}
+
+#[rpc_method]
+pub struct CreateGroup {
+    request: CreateGroupPayload,
+    response: Conversation,
+    error: GroupError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RenameGroupPayload {
+    pub id: GroupId,
+    pub name: String,
+}
+
+#[rpc_method]
+pub struct RenameGroup {
+    request: RenameGroupPayload,
+    response: (),
+    error: GroupError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AddGroupMembersPayload {
+    pub id: GroupId,
+    pub members: Vec<UserId>,
+}
+
+#[rpc_method]
+pub struct AddGroupMembers {
+    request: AddGroupMembersPayload,
+    response: (),
+    error: GroupError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GroupMemberPayload {
+    pub id: GroupId,
+    pub user_id: UserId,
+}
+
+// Members can remove themselves to leave the group,
+// only the owner can remove others
+#[rpc_method]
+pub struct RemoveGroupMember {
+    request: GroupMemberPayload,
+    response: (),
+    error: GroupError,
+}
+
+#[rpc_method]
+pub struct TransferGroupOwnership {
+    request: GroupMemberPayload,
+    response: (),
+    error: GroupError,
+}
+
+/// Sent to members whenever a conversation is created or changed
+#[derive(Serialize, Deserialize, Debug, RPCNotification)]
+pub struct ConversationUpdated {
+    pub conversation: Conversation,
+}
+
+/// Sent to a user who left or was removed from a group
+#[derive(Serialize, Deserialize, Debug, RPCNotification)]
+pub struct ConversationRemoved {
+    pub id: GroupId,
+}
@@ -0,0 +1,39 @@
+use rpc_macros::rpc_method;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::models::markers::InviteId;
+
+/// Code that lets someone register on an invite only server
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Invite {
+    pub id: InviteId,
+    pub code: String,
+    /// Unlimited if not set
+    pub max_uses: Option<u32>,
+    pub uses: u32,
+    pub expires_at: Option<i64>,
+    pub created_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Error, Debug)]
+pub enum InviteError {
+    #[error("Invite should allow at least one use and last at least a day")]
+    InvalidLimits,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CreateInvitePayload {
+    /// Number of accounts it can register, unlimited if not set
+    pub max_uses: Option<u32>,
+    /// It never expires if not set
+    pub expires_in_days: Option<u32>,
+}
+
+// Requires the create invite permission
+#[rpc_method]
+pub struct CreateInvite {
+    request: CreateInvitePayload,
+    response: Invite,
+    error: InviteError,
+}
@@ -55,3 +55,30 @@ pub type TextChannelId = Id<TextChannel>;
 #[derive(Hash, PartialEq, Eq, Debug, Clone, Copy)]
 pub struct Group;
 pub type GroupId = Id<Group>;
+
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Session;
+pub type SessionId = Id<Session>;
+
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Role;
+pub type RoleId = Id<Role>;
+
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Invite;
+pub type InviteId = Id<Invite>;
+
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Emoji;
+pub type EmojiId = Id<Emoji>;
+
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct AuditLogEntry;
+pub type AuditLogEntryId = Id<AuditLogEntry>;
+
+/// ID of either a text or a voice channel
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum ChannelId {
+    Text(TextChannelId),
+    Voice(VoiceChannelId),
+}
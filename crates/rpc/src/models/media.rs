@@ -0,0 +1,134 @@
+use rpc_macros::{RPCNotification, rpc_method};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::models::markers::{MediaId, UserId};
+
+/// Largest piece of a file sent in a single frame in either direction.
+/// Keeps frames small so transfers don't hold up other calls on the connection
+pub const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Handle of an upload that was started but not finished yet
+pub type UploadId = u64;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Media {
+    pub id: MediaId,
+    pub file_name: String,
+    pub mime_type: String,
+    /// Size in bytes
+    pub size: u64,
+    pub uploaded_by: UserId,
+    /// Images get a thumbnail that can be downloaded separately
+    pub has_thumbnail: bool,
+}
+
+#[derive(Serialize, Deserialize, Error, Debug)]
+pub enum MediaError {
+    #[error("File name should be between 1 and 255 characters long")]
+    InvalidFileName,
+    #[error("File is larger than the server allows")]
+    FileTooLarge,
+    #[error("Upload would exceed the storage quota")]
+    QuotaExceeded,
+    #[error("Too many unfinished uploads")]
+    TooManyUploads,
+    #[error("Upload does not exist or has expired")]
+    UploadNotFound,
+    #[error("Chunk is larger than `MAX_CHUNK_SIZE`")]
+    ChunkTooLarge,
+    #[error("Chunk does not continue the uploaded data")]
+    UnexpectedOffset,
+    #[error("Uploaded data does not match the announced size")]
+    SizeMismatch,
+    #[error("Media does not exist")]
+    MediaNotFound,
+    #[error("Media has no thumbnail")]
+    ThumbnailNotFound,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BeginUploadPayload {
+    pub file_name: String,
+    pub mime_type: String,
+    /// Total size in bytes
+    pub size: u64,
+}
+
+#[rpc_method]
+pub struct BeginUpload {
+    request: BeginUploadPayload,
+    response: UploadId,
+    error: MediaError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UploadChunkPayload {
+    pub upload: UploadId,
+    /// Chunks have to be sent in order, so this is
+    /// the amount of bytes that were sent before
+    pub offset: u64,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+}
+
+#[rpc_method]
+pub struct UploadChunk {
+    request: UploadChunkPayload,
+    response: (),
+    error: MediaError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FinishUploadPayload {
+    pub upload: UploadId,
+}
+
+// Stores the file and returns the media that can be attached to messages
+#[rpc_method]
+pub struct FinishUpload {
+    request: FinishUploadPayload,
+    response: Media,
+    error: MediaError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CancelUploadPayload {
+    pub upload: UploadId,
+}
+
+#[rpc_method]
+pub struct CancelUpload {
+    request: CancelUploadPayload,
+    response: (),
+    error: MediaError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DownloadPayload {
+    pub id: MediaId,
+    /// Download the thumbnail instead of the original file
+    pub thumbnail: bool,
+}
+
+// Returns the media info, the content follows in `MediaChunk` notifications.
+// Subscribe to them before calling, the first chunk can outrun the response
+#[rpc_method]
+pub struct Download {
+    request: DownloadPayload,
+    response: Media,
+    error: MediaError,
+}
+
+/// Piece of a requested download
+#[derive(Serialize, Deserialize, Debug, RPCNotification)]
+pub struct MediaChunk {
+    pub id: MediaId,
+    pub thumbnail: bool,
+    pub offset: u64,
+    /// Size of the whole file, the download is complete
+    /// when `offset + data.len()` reaches it
+    pub total_size: u64,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+}
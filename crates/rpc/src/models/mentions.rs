@@ -0,0 +1,60 @@
+use rpc_macros::{RPCNotification, rpc_method};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    markers::{MsgId, RoleId},
+    messages::Message,
+};
+
+/// Prefix of a mention in the message text, followed by
+/// a username, a role name or `here`
+pub const MENTION_PREFIX: char = '@';
+
+/// Mentions everyone who is online and can see the channel
+pub const HERE_MENTION: &str = "here";
+
+/// How the message reached the user. When several mentions
+/// target the same user, the most specific one is kept
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MentionKind {
+    User,
+    Role(RoleId),
+    Here,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Mention {
+    pub message: Message,
+    pub kind: MentionKind,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetMentionsPayload {
+    /// Mentions in messages older than the given one
+    pub before: Option<MsgId>,
+    /// Page size, the server picks a default for 0
+    pub limit: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MentionsPage {
+    /// Ordered from the newest to the oldest
+    pub mentions: Vec<Mention>,
+    pub has_more: bool,
+}
+
+// Inbox of the current user: mentions in messages that weren't
+// acknowledged with `AckChannel` yet, across all visible channels
+#[rpc_method]
+pub struct GetMentions {
+    request: GetMentionsPayload,
+    response: MentionsPage,
+    error: (),
+}
+
+/// Sent to every session of the mentioned user,
+/// even when the channel isn't open on the client
+#[derive(Serialize, Deserialize, Debug, Clone, RPCNotification)]
+pub struct Mentioned {
+    pub mention: Mention,
+}
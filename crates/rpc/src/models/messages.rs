@@ -1,16 +1,18 @@
+use rpc_macros::{RPCNotification, rpc_method};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::models::markers::{GroupId, MediaId, MsgId, TextChannelId, UserId};
+use crate::models::markers::{EmojiId, GroupId, MediaId, MsgId, TextChannelId, UserId};
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TextMessageChannel {
 	TextChannel(TextChannelId),
 	Direct(UserId),
 	GroupChannel(GroupId),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MessageReply {
 	pub reply_to: MsgId,
 	/// Full copy of the quoting part because
@@ -18,7 +20,7 @@ pub struct MessageReply {
 	pub reply_text: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MessageContent {
 	pub attached_media: Vec<MediaId>,
 	pub reply: Option<MessageReply>,
@@ -31,3 +33,269 @@ pub struct SendMessagePayload {
 	pub content: MessageContent,
 	pub destination: TextMessageChannel,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Emoji {
+	Unicode(String),
+	/// Emoji uploaded to the server
+	Custom(EmojiId),
+}
+
+/// Reactions with the same emoji
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Reaction {
+	pub emoji: Emoji,
+	pub count: u32,
+	/// Whether the current user is one of the reactors
+	pub me: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Message {
+	pub id: MsgId,
+	pub channel: TextMessageChannel,
+	pub author: UserId,
+	pub content: MessageContent,
+	/// Unix timestamp
+	pub sent_at: i64,
+	/// Unix timestamp of the latest edit
+	pub edited_at: Option<i64>,
+	/// Ordered by the first reaction. Only filled in `GetMessages` responses,
+	/// later changes arrive as `ReactionUpdate`
+	pub reactions: Vec<Reaction>,
+	/// Whether the message mentions the current user. Only filled in
+	/// `GetMessages` responses, new mentions arrive as `Mentioned`
+	pub mentions_me: bool,
+}
+
+#[derive(Serialize, Deserialize, Error, Debug)]
+pub enum MessageError {
+	#[error("Channel does not exist")]
+	ChannelNotFound,
+	#[error("Messages can't be sent to this kind of channel yet")]
+	UnsupportedChannel,
+	#[error("Message has neither text nor attachments")]
+	EmptyMessage,
+	#[error("Message is too long")]
+	TooLong,
+	#[error("Replied message does not exist in this channel")]
+	ReplyNotFound,
+	#[error("Attached media does not exist")]
+	MediaNotFound,
+	#[error("Message does not exist")]
+	MessageNotFound,
+	#[error("Search query has no words")]
+	EmptyQuery,
+	#[error("Emoji does not exist")]
+	InvalidEmoji,
+	#[error("Message has too many different reactions")]
+	TooManyReactions,
+	#[error("One of the users blocked the other")]
+	Blocked,
+}
+
+#[rpc_method]
+pub struct SendMessage {
+	request: SendMessagePayload,
+	response: Message,
+	error: MessageError,
+}
+
+/// Sent to everyone who can see the channel
+#[derive(Serialize, Deserialize, Debug, RPCNotification)]
+pub struct MessageCreated {
+	pub message: Message,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum MessageCursor {
+	/// Messages older than the given one
+	Before(MsgId),
+	/// Messages newer than the given one
+	After(MsgId),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetMessagesPayload {
+	pub channel: TextMessageChannel,
+	/// The latest messages are returned if it's not set
+	pub cursor: Option<MessageCursor>,
+	/// Clamped by the server
+	pub limit: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MessagesPage {
+	/// Ordered from the oldest to the newest
+	pub messages: Vec<Message>,
+	/// Whether there are more messages in the direction of the cursor
+	pub has_more: bool,
+}
+
+#[rpc_method]
+pub struct GetMessages {
+	request: GetMessagesPayload,
+	response: MessagesPage,
+	error: MessageError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EditMessagePayload {
+	pub id: MsgId,
+	/// Only the text can be edited, attachments and the reply stay the same
+	pub content: String,
+}
+
+#[rpc_method]
+pub struct EditMessage {
+	request: EditMessagePayload,
+	response: Message,
+	error: MessageError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteMessagePayload {
+	pub id: MsgId,
+}
+
+#[rpc_method]
+pub struct DeleteMessage {
+	request: DeleteMessagePayload,
+	response: (),
+	error: MessageError,
+}
+
+#[derive(Serialize, Deserialize, Debug, RPCNotification)]
+pub struct MessageUpdated {
+	pub message: Message,
+}
+
+#[derive(Serialize, Deserialize, Debug, RPCNotification)]
+pub struct MessageDeleted {
+	pub channel: TextMessageChannel,
+	pub id: MsgId,
+}
+
+/// Content of a message before one of its edits
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MessageRevision {
+	pub content: MessageContent,
+	pub edited_by: UserId,
+	/// Unix timestamp of the edit that replaced this content
+	pub replaced_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetMessageRevisionsPayload {
+	pub id: MsgId,
+}
+
+// Only available to users who can manage messages in the channel
+#[rpc_method]
+pub struct GetMessageRevisions {
+	request: GetMessageRevisionsPayload,
+	response: Vec<MessageRevision>,
+	error: MessageError,
+}
+
+/// Marks the start of a matched term in a search snippet
+pub const HIGHLIGHT_START: &str = "\u{2}";
+/// Marks the end of a matched term in a search snippet
+pub const HIGHLIGHT_END: &str = "\u{3}";
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchMessagesPayload {
+	/// Words that should be present in the message text
+	pub query: String,
+	/// Every channel the user can see is searched if it's not set
+	pub channel: Option<TextMessageChannel>,
+	pub author: Option<UserId>,
+	/// Unix timestamp, inclusive
+	pub after: Option<i64>,
+	/// Unix timestamp, exclusive
+	pub before: Option<i64>,
+	/// Only messages with attachments
+	pub has_attachments: bool,
+	/// Amount of results to skip
+	pub offset: u32,
+	/// Clamped by the server
+	pub limit: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchResult {
+	pub message: Message,
+	/// Part of the text around the match, matched terms are wrapped
+	/// in `HIGHLIGHT_START` and `HIGHLIGHT_END`
+	pub snippet: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchResults {
+	/// The most relevant go first
+	pub results: Vec<SearchResult>,
+	pub has_more: bool,
+}
+
+#[rpc_method]
+pub struct SearchMessages {
+	request: SearchMessagesPayload,
+	response: SearchResults,
+	error: MessageError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReactionPayload {
+	pub message: MsgId,
+	pub emoji: Emoji,
+}
+
+#[rpc_method]
+pub struct AddReaction {
+	request: ReactionPayload,
+	response: (),
+	error: MessageError,
+}
+
+#[rpc_method]
+pub struct RemoveReaction {
+	request: ReactionPayload,
+	response: (),
+	error: MessageError,
+}
+
+/// Sent to everyone who can see the channel
+#[derive(Serialize, Deserialize, Debug, Clone, RPCNotification)]
+pub struct ReactionUpdate {
+	pub channel: TextMessageChannel,
+	pub message: MsgId,
+	pub user: UserId,
+	pub emoji: Emoji,
+	/// The reaction was removed otherwise
+	pub added: bool,
+}
+
+/// How often clients should repeat `StartTyping` while the user keeps typing
+pub const TYPING_INTERVAL_SECS: u64 = 5;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StartTypingPayload {
+	pub channel: TextMessageChannel,
+}
+
+#[rpc_method]
+pub struct StartTyping {
+	request: StartTypingPayload,
+	response: (),
+	error: MessageError,
+}
+
+/// Sent to everyone who can see the channel when a user starts typing
+/// or stops without sending anything. Sending a message stops typing
+/// silently, so clients should also clear the state on `MessageCreated`
+#[derive(Serialize, Deserialize, Debug, Clone, RPCNotification)]
+pub struct TypingUpdate {
+	pub channel: TextMessageChannel,
+	pub user: UserId,
+	pub is_typing: bool,
+}
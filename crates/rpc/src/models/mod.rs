@@ -3,4 +3,17 @@ pub mod messages;
 pub mod auth;
 pub mod voice;
 pub mod markers;
-pub mod general;
+pub mod permissions;
+pub mod moderation;
+pub mod channels;
+pub mod groups;
+pub mod media;
+pub mod emojis;
+pub mod read_state;
+pub mod mentions;
+pub mod presence;
+pub mod profiles;
+pub mod relationships;
+pub mod audit_log;
+pub mod motd;
+pub mod invites;
@@ -0,0 +1,110 @@
+use rpc_macros::rpc_method;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    common::Empty,
+    models::{
+        markers::{UserId, VoiceChannelId},
+        voice::VoiceServerState,
+    },
+};
+
+#[derive(Serialize, Deserialize, Error, Debug)]
+pub enum ModerationError {
+    #[error("User does not exist")]
+    UserNotFound,
+    #[error("Moderators can't apply actions to themselves")]
+    SelfModeration,
+    #[error("User is not connected to a voice channel")]
+    NotInVoiceChannel,
+    #[error("Voice channel does not exist")]
+    ChannelNotFound,
+    #[error("User has a role at or above yours")]
+    HigherRole,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KickUserPayload {
+    pub user_id: UserId,
+    /// Stored in the audit log
+    pub reason: Option<String>,
+}
+
+// Closes every connection of the user, they can log in again right away
+#[rpc_method]
+pub struct KickUser {
+    request: KickUserPayload,
+    response: (),
+    error: ModerationError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BanUserPayload {
+    pub user_id: UserId,
+    pub reason: Option<String>,
+    /// Unix timestamp, the ban is permanent if it's not set
+    pub expires_at: Option<i64>,
+}
+
+// Bans the user, revokes all of their sessions and closes their connections
+#[rpc_method]
+pub struct BanUser {
+    request: BanUserPayload,
+    response: (),
+    error: ModerationError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UnbanUserPayload {
+    pub user_id: UserId,
+}
+
+#[rpc_method]
+pub struct UnbanUser {
+    request: UnbanUserPayload,
+    response: (),
+    error: ModerationError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Ban {
+    pub user_id: UserId,
+    pub username: String,
+    pub reason: Option<String>,
+    pub expires_at: Option<i64>,
+}
+
+#[rpc_method]
+pub struct GetBans {
+    request: Empty,
+    response: Vec<Ban>,
+    error: (),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetVoiceServerStatePayload {
+    pub user_id: UserId,
+    pub state: VoiceServerState,
+}
+
+// Server mute and deafen persist until they are lifted, even if the user rejoins
+#[rpc_method]
+pub struct SetVoiceServerState {
+    request: SetVoiceServerStatePayload,
+    response: (),
+    error: ModerationError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MoveVoiceMemberPayload {
+    pub user_id: UserId,
+    pub channel_id: VoiceChannelId,
+}
+
+#[rpc_method]
+pub struct MoveVoiceMember {
+    request: MoveVoiceMemberPayload,
+    response: (),
+    error: ModerationError,
+}
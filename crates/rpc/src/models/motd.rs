@@ -0,0 +1,18 @@
+use rpc_macros::{RPCNotification, rpc_method};
+use serde::{Deserialize, Serialize};
+
+use crate::common::Empty;
+
+// Message of the day set in the server config, `None` if there's none
+#[rpc_method]
+pub struct GetMotd {
+    request: Empty,
+    response: Option<String>,
+    error: (),
+}
+
+/// Sent to everyone when the server config is reloaded with a new message
+#[derive(Serialize, Deserialize, Debug, RPCNotification)]
+pub struct MotdUpdate {
+    pub motd: Option<String>,
+}
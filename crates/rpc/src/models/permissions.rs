@@ -0,0 +1,228 @@
+use std::ops::{BitAnd, BitOr, Not};
+
+use rpc_macros::rpc_method;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    common::Empty,
+    models::markers::{ChannelId, RoleId, UserId},
+};
+
+/// Set of actions a user is allowed to perform
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct Permissions(pub u64);
+
+impl Permissions {
+    pub const NONE: Self = Self(0);
+    pub const ALL: Self = Self(u64::MAX);
+
+    /// Grants every permission and ignores channel overrides
+    pub const ADMINISTRATOR: Self = Self(1 << 0);
+    pub const MANAGE_CHANNELS: Self = Self(1 << 1);
+    pub const MANAGE_ROLES: Self = Self(1 << 2);
+    pub const MANAGE_MESSAGES: Self = Self(1 << 3);
+    pub const BAN_MEMBERS: Self = Self(1 << 4);
+    pub const CREATE_INVITE: Self = Self(1 << 5);
+
+    pub const VIEW_CHANNEL: Self = Self(1 << 6);
+    pub const SEND_MESSAGES: Self = Self(1 << 7);
+
+    pub const CONNECT: Self = Self(1 << 8);
+    pub const SPEAK: Self = Self(1 << 9);
+    pub const KICK_MEMBERS: Self = Self(1 << 10);
+    pub const MOVE_MEMBERS: Self = Self(1 << 11);
+    pub const MUTE_MEMBERS: Self = Self(1 << 12);
+    pub const MANAGE_EMOJIS: Self = Self(1 << 13);
+    /// Allows `@here` and role mentions in text channels
+    pub const MENTION_ROLES: Self = Self(1 << 14);
+
+    /// Permissions of the default role on a fresh server
+    pub const DEFAULT: Self = Self(
+        Self::VIEW_CHANNEL.0
+            | Self::SEND_MESSAGES.0
+            | Self::CONNECT.0
+            | Self::SPEAK.0
+            | Self::CREATE_INVITE.0,
+    );
+
+    pub fn contains(self, other: Self) -> bool {
+        self.is_admin() || self.0 & other.0 == other.0
+    }
+
+    pub fn is_admin(self) -> bool {
+        self.0 & Self::ADMINISTRATOR.0 != 0
+    }
+
+    /// Applies a channel override on top of the current permissions
+    pub fn apply_override(self, allow: Self, deny: Self) -> Self {
+        (self & !deny) | allow
+    }
+}
+
+impl BitOr for Permissions {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for Permissions {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl Not for Permissions {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(!self.0)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetPermissionsPayload {
+    /// Permissions are calculated with the channel overrides if it's set
+    pub channel: Option<ChannelId>,
+}
+
+#[rpc_method]
+pub struct GetPermissions {
+    request: GetPermissionsPayload,
+    response: Permissions,
+    error: (),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Role {
+    pub id: RoleId,
+    pub name: String,
+    pub permissions: Permissions,
+    pub position: i32,
+
+    /// The default role is implicitly assigned to every user
+    pub is_default: bool,
+    pub members: Vec<UserId>,
+}
+
+#[rpc_method]
+pub struct GetRoles {
+    request: Empty,
+    response: Vec<Role>,
+    error: (),
+}
+
+#[derive(Serialize, Deserialize, Error, Debug)]
+pub enum RoleError {
+    #[error("Role does not exist")]
+    NotFound,
+    #[error("Default role can't be deleted or assigned")]
+    DefaultRole,
+    #[error("User does not exist")]
+    UserNotFound,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateRolePayload {
+    pub name: String,
+    pub permissions: Permissions,
+    pub position: i32,
+}
+
+// Roles, their members and overrides can only be managed below the highest
+// role of the manager, and can't grant permissions the manager doesn't have.
+// Both are rejected with `APIError::Forbidden`
+#[rpc_method]
+pub struct CreateRole {
+    request: CreateRolePayload,
+    response: RoleId,
+    error: RoleError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateRolePayload {
+    pub id: RoleId,
+    pub name: String,
+    pub permissions: Permissions,
+    pub position: i32,
+}
+
+#[rpc_method]
+pub struct UpdateRole {
+    request: UpdateRolePayload,
+    response: (),
+    error: RoleError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteRolePayload {
+    pub id: RoleId,
+}
+
+#[rpc_method]
+pub struct DeleteRole {
+    request: DeleteRolePayload,
+    response: (),
+    error: RoleError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RoleAssignmentPayload {
+    pub role_id: RoleId,
+    pub user_id: UserId,
+}
+
+#[rpc_method]
+pub struct AssignRole {
+    request: RoleAssignmentPayload,
+    response: (),
+    error: RoleError,
+}
+
+#[rpc_method]
+pub struct UnassignRole {
+    request: RoleAssignmentPayload,
+    response: (),
+    error: RoleError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChannelPermissionOverride {
+    pub channel: ChannelId,
+    pub role_id: RoleId,
+
+    pub allow: Permissions,
+    pub deny: Permissions,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetChannelOverridesPayload {
+    pub channel: ChannelId,
+}
+
+#[rpc_method]
+pub struct GetChannelOverrides {
+    request: GetChannelOverridesPayload,
+    response: Vec<ChannelPermissionOverride>,
+    error: (),
+}
+
+#[derive(Serialize, Deserialize, Error, Debug)]
+pub enum SetChannelOverrideError {
+    #[error("Channel does not exist")]
+    ChannelNotFound,
+    #[error("Role does not exist")]
+    RoleNotFound,
+}
+
+// An override with empty `allow` and `deny` is removed
+#[rpc_method]
+pub struct SetChannelOverride {
+    request: ChannelPermissionOverride,
+    response: (),
+    error: SetChannelOverrideError,
+}
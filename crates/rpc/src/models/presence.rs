@@ -0,0 +1,96 @@
+use rpc_macros::{RPCNotification, rpc_method};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::models::markers::UserId;
+
+/// Clients report the user as idle after this much time without input
+pub const IDLE_AFTER_SECS: u64 = 5 * 60;
+
+/// Maximum length of a custom status in characters
+pub const MAX_CUSTOM_STATUS_LENGTH: usize = 128;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PresenceStatus {
+    #[default]
+    Online,
+    /// Set automatically when every session of the user is idle
+    Idle,
+    DoNotDisturb,
+    /// Only the user sees it, everyone else sees `Offline`
+    Invisible,
+    Offline,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CustomStatus {
+    pub text: String,
+    /// Unix timestamp, the status is cleared after it
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Presence {
+    pub user: UserId,
+    pub status: PresenceStatus,
+    /// Hidden while the user is offline
+    pub custom_status: Option<CustomStatus>,
+}
+
+#[derive(Serialize, Deserialize, Error, Debug)]
+pub enum PresenceError {
+    #[error("Idle and offline can't be picked by the user")]
+    InvalidStatus,
+    #[error("Custom status should be between 1 and 128 characters long")]
+    InvalidCustomStatus,
+    #[error("Custom status expires in the past")]
+    CustomStatusExpired,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetPresencePayload {
+    /// Either `Online`, `DoNotDisturb` or `Invisible`
+    pub status: PresenceStatus,
+    pub custom_status: Option<CustomStatus>,
+}
+
+// Picks the status for all sessions of the user, it's kept between logins
+#[rpc_method]
+pub struct SetPresence {
+    request: SetPresencePayload,
+    response: Presence,
+    error: PresenceError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetIdlePayload {
+    pub is_idle: bool,
+}
+
+// Reports whether the user is away from this session, see `IDLE_AFTER_SECS`
+#[rpc_method]
+pub struct SetIdle {
+    request: SetIdlePayload,
+    response: (),
+    error: (),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetPresencesPayload {
+    /// Empty list asks for everyone who is online
+    pub users: Vec<UserId>,
+}
+
+#[rpc_method]
+pub struct GetPresences {
+    request: GetPresencesPayload,
+    response: Vec<Presence>,
+    error: (),
+}
+
+/// Sent to everyone when the presence of a user changes,
+/// including connecting and disconnecting
+#[derive(Serialize, Deserialize, Debug, Clone, RPCNotification)]
+pub struct PresenceUpdate {
+    pub presence: Presence,
+}
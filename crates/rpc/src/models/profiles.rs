@@ -0,0 +1,67 @@
+use rpc_macros::{RPCNotification, rpc_method};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::models::{
+    auth::UserInfo,
+    markers::{MediaId, UserId},
+};
+
+/// Maximum length of a display name in characters
+pub const MAX_DISPLAY_NAME_LENGTH: usize = 32;
+
+/// Maximum length of a bio in characters
+pub const MAX_BIO_LENGTH: usize = 190;
+
+/// Maximum number of users in a single `GetUsers` call
+pub const MAX_USERS_PER_REQUEST: usize = 100;
+
+#[derive(Serialize, Deserialize, Error, Debug)]
+pub enum ProfileError {
+    #[error("Display name should be between 1 and 32 characters long")]
+    InvalidDisplayName,
+    #[error("Bio is longer than 190 characters")]
+    BioTooLong,
+    #[error("Avatar should be an image uploaded by the user")]
+    InvalidAvatar,
+    #[error("Accent color should be an RGB value")]
+    InvalidAccentColor,
+    #[error("Too many users requested at once")]
+    TooManyUsers,
+}
+
+/// Replaces the whole profile, fields that are `None` are cleared
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateProfilePayload {
+    pub display_name: Option<String>,
+    pub avatar: Option<MediaId>,
+    pub bio: Option<String>,
+    /// RGB color
+    pub accent_color: Option<u32>,
+}
+
+#[rpc_method]
+pub struct UpdateProfile {
+    request: UpdateProfilePayload,
+    response: UserInfo,
+    error: ProfileError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetUsersPayload {
+    pub users: Vec<UserId>,
+}
+
+// Users that don't exist are left out of the response
+#[rpc_method]
+pub struct GetUsers {
+    request: GetUsersPayload,
+    response: Vec<UserInfo>,
+    error: ProfileError,
+}
+
+/// Sent to everyone when a user changes their profile
+#[derive(Serialize, Deserialize, Debug, Clone, RPCNotification)]
+pub struct ProfileUpdated {
+    pub user: UserInfo,
+}
@@ -0,0 +1,65 @@
+use rpc_macros::{RPCNotification, rpc_method};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::Empty,
+    models::{
+        markers::MsgId,
+        messages::{MessageError, TextMessageChannel},
+    },
+};
+
+/// Read state of a channel or a conversation for the current user
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelUnread {
+    pub channel: TextMessageChannel,
+    pub last_read: Option<MsgId>,
+    /// Messages of other users after `last_read`, always 0 in muted channels
+    pub unread_count: u32,
+    /// Unread messages mentioning the user, counted even in muted channels
+    pub mention_count: u32,
+    pub is_muted: bool,
+}
+
+// Read state of every channel and conversation the user can see
+#[rpc_method]
+pub struct GetUnreadSummary {
+    request: Empty,
+    response: Vec<ChannelUnread>,
+    error: (),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AckChannelPayload {
+    pub channel: TextMessageChannel,
+    /// The latest message the user has seen, acks never move back
+    pub message: MsgId,
+}
+
+#[rpc_method]
+pub struct AckChannel {
+    request: AckChannelPayload,
+    response: (),
+    error: MessageError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetChannelMutedPayload {
+    pub channel: TextMessageChannel,
+    pub is_muted: bool,
+}
+
+#[rpc_method]
+pub struct SetChannelMuted {
+    request: SetChannelMutedPayload,
+    response: (),
+    error: MessageError,
+}
+
+/// Sent to the other sessions of the user when the read state changes
+#[derive(Serialize, Deserialize, Debug, Clone, RPCNotification)]
+pub struct ReadStateUpdate {
+    pub channel: TextMessageChannel,
+    pub last_read: Option<MsgId>,
+    pub is_muted: bool,
+}
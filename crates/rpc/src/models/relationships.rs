@@ -0,0 +1,118 @@
+use rpc_macros::{RPCNotification, rpc_method};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    common::Empty,
+    models::{markers::UserId, presence::Presence},
+};
+
+/// Relationship of the current user to another user
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RelationshipKind {
+    Friend,
+    /// The other user sent a friend request
+    IncomingRequest,
+    /// The current user sent a friend request
+    OutgoingRequest,
+    /// Only the user who blocked sees it
+    Blocked,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Relationship {
+    pub user: UserId,
+    pub kind: RelationshipKind,
+    /// Only filled for friends
+    pub presence: Option<Presence>,
+}
+
+#[derive(Serialize, Deserialize, Error, Debug)]
+pub enum RelationshipError {
+    #[error("User does not exist")]
+    UserNotFound,
+    #[error("Users can't have a relationship with themselves")]
+    SelfRelationship,
+    #[error("Users are already friends")]
+    AlreadyFriends,
+    #[error("Friend request does not exist")]
+    RequestNotFound,
+    #[error("Users are not friends")]
+    NotFriends,
+    #[error("User is not blocked")]
+    NotBlocked,
+    #[error("One of the users blocked the other")]
+    Blocked,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RelationshipPayload {
+    pub user: UserId,
+}
+
+#[rpc_method]
+pub struct GetRelationships {
+    request: Empty,
+    response: Vec<Relationship>,
+    error: (),
+}
+
+// Accepts the request right away if the other user already sent one
+#[rpc_method]
+pub struct SendFriendRequest {
+    request: RelationshipPayload,
+    response: RelationshipKind,
+    error: RelationshipError,
+}
+
+#[rpc_method]
+pub struct AcceptFriendRequest {
+    request: RelationshipPayload,
+    response: (),
+    error: RelationshipError,
+}
+
+#[rpc_method]
+pub struct DeclineFriendRequest {
+    request: RelationshipPayload,
+    response: (),
+    error: RelationshipError,
+}
+
+#[rpc_method]
+pub struct CancelFriendRequest {
+    request: RelationshipPayload,
+    response: (),
+    error: RelationshipError,
+}
+
+#[rpc_method]
+pub struct RemoveFriend {
+    request: RelationshipPayload,
+    response: (),
+    error: RelationshipError,
+}
+
+// Removes the friendship and pending requests between the users.
+// Blocked users can't send friend requests or direct messages to the user
+#[rpc_method]
+pub struct BlockUser {
+    request: RelationshipPayload,
+    response: (),
+    error: RelationshipError,
+}
+
+#[rpc_method]
+pub struct UnblockUser {
+    request: RelationshipPayload,
+    response: (),
+    error: RelationshipError,
+}
+
+/// Sent to every session of the user when a relationship changes,
+/// `kind` is `None` when it was removed
+#[derive(Serialize, Deserialize, Debug, Clone, RPCNotification)]
+pub struct RelationshipUpdate {
+    pub user: UserId,
+    pub kind: Option<RelationshipKind>,
+}
@@ -11,18 +11,37 @@ pub struct VoiceChannelMember {
 
     pub is_muted: bool,
     pub is_sound_off: bool,
+    /// The member's client has an open UDP stream
+    pub is_streaming: bool,
+
+    pub server_state: VoiceServerState,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct VoiceChannel {
     pub id: VoiceChannelId,
     pub name: String,
+    pub position: i32,
+    pub category: Option<String>,
+    pub user_limit: u32,
+    pub bitrate: u32,
 
     pub members: Vec<VoiceChannelMember>
 }
 
+/// Voice channels along with their members at a moment in time
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VoiceChannelsSnapshot {
+    /// Version of the last update reflected in the snapshot
+    pub version: u64,
+    pub channels: Vec<VoiceChannel>,
+}
+
 #[derive(Serialize, Deserialize, Debug, RPCNotification)]
 pub struct VoiceChannelUpdate {
+    /// Every update increments the version by one. An update that skips
+    /// a version means one was missed and the snapshot has to be fetched again
+    pub version: u64,
     pub channel_id: VoiceChannelId,
     pub message: VoiceChannelUpdateMessage,
 }
@@ -65,11 +84,21 @@ pub struct UpdateVoiceUserState {
     error: (),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Restrictions put on a voice member by a moderator
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VoiceServerState {
+    pub is_server_muted: bool,
+    pub is_server_deafened: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum VoiceChannelUpdateMessage {
     UserConnected(UserId),
     UserDisconnected(UserId),
-    UserStateUpdated((UserId, VoiceUserState))
+    UserStateUpdated((UserId, VoiceUserState)),
+    /// The user was moved by a moderator from this channel to another one
+    UserMoved((UserId, VoiceChannelId)),
+    UserServerStateUpdated((UserId, VoiceServerState)),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -82,6 +111,6 @@ pub enum GetVoiceChannelsError {
 #[rpc_method]
 pub struct GetVoiceChannels {
     request: Empty,
-    response: Vec<VoiceChannel>,
+    response: VoiceChannelsSnapshot,
     error: (),
 }
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, pin::Pin, sync::Arc};
+use std::{collections::HashMap, net::SocketAddr, pin::Pin, sync::Arc};
 
 use serde::{Serialize, de::DeserializeOwned};
 
@@ -6,7 +6,7 @@ use castaway::cast;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream, tcp::OwnedReadHalf},
-    sync::mpsc,
+    sync::{Notify, mpsc},
 };
 
 use anyhow::Result as AResult;
@@ -33,44 +33,70 @@ pub type DynHandler<C> = Box<
 #[derive(Clone, Debug)]
 pub struct RpcWriter {
     inner: mpsc::Sender<Vec<u8>>,
+
+    /// Signals the connection loop to stop serving the client
+    closed: Arc<Notify>,
 }
 
 impl RpcWriter {
     fn new(sender: mpsc::Sender<Vec<u8>>) -> Self {
-        Self { inner: sender }
+        Self {
+            inner: sender,
+            closed: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Stops processing requests from the client and drops the connection.
+    /// Already queued responses and notifications are still delivered
+    pub fn close(&self) {
+        self.closed.notify_one();
     }
 
     pub async fn write<T: Response>(&self, key: String, value: T, uuid: Option<Uuid>) {
-        if let Some(body_bytes) = value.bytes() {
-            let key_bytes = key.as_bytes();
+        if let Some(response) = Self::encode(key, value, uuid) {
+            let _ = self.inner.send(response).await;
+        }
+    }
 
-            let key_len = u8::try_from(key_bytes.len()).expect("Key is way too big"); // TODO: Do not fail
+    /// Queues the message without waiting for the client to catch up.
+    /// Returns false if the queue is full or the connection is gone
+    pub fn try_write<T: Response>(&self, key: String, value: T, uuid: Option<Uuid>) -> bool {
+        match Self::encode(key, value, uuid) {
+            Some(response) => self.inner.try_send(response).is_ok(),
+            None => true,
+        }
+    }
 
-            let body_len = u32::try_from(body_bytes.len()).expect("Body is way too big"); // TODO: Do not fail
+    fn encode<T: Response>(key: String, value: T, uuid: Option<Uuid>) -> Option<Vec<u8>> {
+        let body_bytes = value.bytes()?;
+        let key_bytes = key.as_bytes();
 
-            let mut response = Vec::<u8>::with_capacity(body_len as usize);
+        let key_len = u8::try_from(key_bytes.len()).expect("Key is way too big"); // TODO: Do not fail
 
-            response.push(key_len);
-            response.extend_from_slice(key_bytes);
+        let body_len = u32::try_from(body_bytes.len()).expect("Body is way too big"); // TODO: Do not fail
 
-            if let Some(value) = uuid {
-                response.push(true as u8);
-                response.extend_from_slice(value.as_bytes())
-            } else {
-                response.push(false as u8);
-            }
+        let mut response = Vec::<u8>::with_capacity(body_len as usize);
 
-            response.extend_from_slice(&body_len.to_le_bytes());
-            response.extend_from_slice(&body_bytes);
+        response.push(key_len);
+        response.extend_from_slice(key_bytes);
 
-            let _ = self.inner.send(response).await;
+        if let Some(value) = uuid {
+            response.push(true as u8);
+            response.extend_from_slice(value.as_bytes())
+        } else {
+            response.push(false as u8);
         }
+
+        response.extend_from_slice(&body_len.to_le_bytes());
+        response.extend_from_slice(&body_bytes);
+
+        Some(response)
     }
 }
 
 pub struct RpcRouter<AppState, ConnState> {
     state: AppState,
-    on_connect_hook: Arc<dyn Fn(RpcWriter) -> ConnState + Send + Sync + 'static>,
+    on_connect_hook: Arc<dyn Fn(RpcWriter, SocketAddr) -> ConnState + Send + Sync + 'static>,
     routing_table: HashMap<String, DynHandler<ConnState>>,
 }
 
@@ -99,7 +125,7 @@ where
 {
     pub fn new<F>(state: AppState, f: F) -> Self
     where
-        F: Fn(RpcWriter) -> ConnState + Send + Sync + 'static,
+        F: Fn(RpcWriter, SocketAddr) -> ConnState + Send + Sync + 'static,
     {
         Self {
             state,
@@ -148,9 +174,53 @@ where
     }
 }
 
+async fn process_request<AppState, ConnState>(
+    router: &RpcRouter<AppState, ConnState>,
+    buf: &mut BytesMut,
+    reader: &mut OwnedReadHalf,
+    conn_state: &ConnState,
+    rpc_writer: &RpcWriter,
+) -> AResult<bool>
+where
+    AppState: Clone + Send + Sync + 'static,
+    ConnState: Clone + Send + Sync + 'static,
+{
+    if buf.is_empty() {
+        match reader.read_buf(buf).await {
+            Err(_) => return Ok(false),
+            Ok(0) => return Ok(false),
+            _ => {}
+        }
+    }
+
+    let (method, bytes_read) = parse_rpc_method(buf, reader).await?;
+    let (uuid, bytes_read) = parse_uuid(buf, reader, bytes_read + 1).await?;
+
+    let f = router.routing_table.get(&method).unwrap(); // TODO: Do not fail and report incorrect endpoint name
+
+    let bytes_read = (f)(
+        uuid,
+        buf,
+        reader,
+        conn_state.clone(),
+        rpc_writer.clone(),
+        bytes_read,
+    )
+    .await?;
+
+    if buf.len() > bytes_read {
+        *buf = buf.split_off(bytes_read);
+    } else {
+        buf.clear();
+    }
+
+    Ok(true)
+}
+
 async fn process_connection<AppState, ConnState>(
     router: Arc<RpcRouter<AppState, ConnState>>,
     stream: TcpStream,
+    addr: SocketAddr,
 ) -> AResult<ConnState>
 where
     AppState: Clone + Send + Sync + 'static,
@@ -170,40 +240,21 @@ where
     });
 
     let rpc_writer = RpcWriter::new(tx);
-    let conn_state = (router.on_connect_hook)(rpc_writer.clone());
+    let conn_state = (router.on_connect_hook)(rpc_writer.clone(), addr);
+
+    let closed = rpc_writer.closed.clone();
 
     loop {
-        if buf.is_empty() {
-            match reader.read_buf(&mut buf).await {
-                Err(_) => return Ok(conn_state),
-                Ok(bytes_read) if bytes_read == 0 => return Ok(conn_state),
-                _ => {}
+        tokio::select! {
+            biased;
+
+            _ = closed.notified() => return Ok(conn_state),
+            is_alive = process_request(&router, &mut buf, &mut reader, &conn_state, &rpc_writer) => {
+                if !is_alive? {
+                    return Ok(conn_state);
+                }
             }
         }
-
-        let (method, bytes_read) = parse_rpc_method(&mut buf, &mut reader).await?;
-        let (uuid, bytes_read) = parse_uuid(&mut buf, &mut reader, bytes_read + 1).await?;
-
-        let f = router.routing_table.get(&method).unwrap(); // TODO: Do not fail and report incorrect endpoint name
-
-        let conn_state = conn_state.clone();
-        let rpc_writer = rpc_writer.clone();
-
-        let bytes_read = (f)(
-            uuid,
-            &mut buf,
-            &mut reader,
-            conn_state,
-            rpc_writer,
-            bytes_read,
-        )
-        .await?;
-
-        if buf.len() > bytes_read {
-            buf = buf.split_off(bytes_read);
-        } else {
-            buf.clear();
-        }
     }
 }
 
@@ -236,7 +287,7 @@ pub async fn serve<AppState, ConnState>(
                 tokio::spawn(async move {
                     let state = router.state.clone();
 
-                    let conn_state = process_connection(router, stream).await.unwrap();
+                    let conn_state = process_connection(router, stream, addr).await.unwrap();
 
                     on_disconnect(state, conn_state).await;
                 });
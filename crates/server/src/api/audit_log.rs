@@ -0,0 +1,301 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use rpc::{
+    check_auth,
+    models::{
+        audit_log::{
+            AuditAction, AuditLogEntry, AuditLogPage, AuditTarget, GetAuditLog, GetAuditLogPayload,
+        },
+        common::{APIError, APIResult, RPCMethod as _},
+        markers::{ChannelId, Id, TaggedEntity as _},
+        permissions::Permissions,
+    },
+};
+use serde::Serialize;
+
+use sea_orm::{DbErr, entity::*, query::*};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        common::{DbErrReponseCompat as _, RPCHandle},
+        permissions::require_permission,
+    },
+    entity::audit_log::{self, Entity as AuditLog},
+    register_endpoints,
+};
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// How often entries past the retention period are removed
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+fn action_from_db(value: i32) -> Option<AuditAction> {
+    let action = match value {
+        0 => AuditAction::ChannelCreate,
+        1 => AuditAction::ChannelUpdate,
+        2 => AuditAction::ChannelDelete,
+        3 => AuditAction::ChannelReorder,
+        4 => AuditAction::ChannelOverrideUpdate,
+        5 => AuditAction::RoleCreate,
+        6 => AuditAction::RoleUpdate,
+        7 => AuditAction::RoleDelete,
+        8 => AuditAction::RoleAssign,
+        9 => AuditAction::RoleUnassign,
+        10 => AuditAction::MemberKick,
+        11 => AuditAction::MemberBan,
+        12 => AuditAction::MemberUnban,
+        13 => AuditAction::VoiceServerStateUpdate,
+        14 => AuditAction::VoiceMemberMove,
+        15 => AuditAction::SessionRevoke,
+        16 => AuditAction::InviteCreate,
+        _ => return None,
+    };
+
+    Some(action)
+}
+
+fn action_to_db(action: AuditAction) -> i32 {
+    match action {
+        AuditAction::ChannelCreate => 0,
+        AuditAction::ChannelUpdate => 1,
+        AuditAction::ChannelDelete => 2,
+        AuditAction::ChannelReorder => 3,
+        AuditAction::ChannelOverrideUpdate => 4,
+        AuditAction::RoleCreate => 5,
+        AuditAction::RoleUpdate => 6,
+        AuditAction::RoleDelete => 7,
+        AuditAction::RoleAssign => 8,
+        AuditAction::RoleUnassign => 9,
+        AuditAction::MemberKick => 10,
+        AuditAction::MemberBan => 11,
+        AuditAction::MemberUnban => 12,
+        AuditAction::VoiceServerStateUpdate => 13,
+        AuditAction::VoiceMemberMove => 14,
+        AuditAction::SessionRevoke => 15,
+        AuditAction::InviteCreate => 16,
+    }
+}
+
+fn target_from_db(kind: i32, id: i32) -> Option<AuditTarget> {
+    let target = match kind {
+        0 => AuditTarget::User(Id::new(id)),
+        1 => AuditTarget::Channel(ChannelId::Text(Id::new(id))),
+        2 => AuditTarget::Channel(ChannelId::Voice(Id::new(id))),
+        3 => AuditTarget::Role(Id::new(id)),
+        4 => AuditTarget::Session(Id::new(id)),
+        5 => AuditTarget::Invite(Id::new(id)),
+        _ => return None,
+    };
+
+    Some(target)
+}
+
+/// Kind and ID columns of the target
+fn target_to_db(target: AuditTarget) -> (i32, i32) {
+    match target {
+        AuditTarget::User(id) => (0, id.value),
+        AuditTarget::Channel(ChannelId::Text(id)) => (1, id.value),
+        AuditTarget::Channel(ChannelId::Voice(id)) => (2, id.value),
+        AuditTarget::Role(id) => (3, id.value),
+        AuditTarget::Session(id) => (4, id.value),
+        AuditTarget::Invite(id) => (5, id.value),
+    }
+}
+
+fn entry_from_model(model: audit_log::Model) -> Option<AuditLogEntry> {
+    let target = match (model.target_kind, model.target_id) {
+        (Some(kind), Some(id)) => Some(target_from_db(kind, id)?),
+        _ => None,
+    };
+
+    Some(AuditLogEntry {
+        id: model.tagged_id(),
+        actor: Id::new(model.actor_id),
+        action: action_from_db(model.action)?,
+        target,
+        reason: model.reason,
+        before: model.before.map(|state| state.to_string()),
+        after: model.after.map(|state| state.to_string()),
+        created_at: model.created_at.and_utc().timestamp(),
+    })
+}
+
+/// Missing state (`None`) isn't stored
+fn to_json(state: &impl Serialize) -> Option<serde_json::Value> {
+    serde_json::to_value(state)
+        .inspect_err(|err| log::error!("Failed to serialize audit log state: {err}"))
+        .ok()
+        .filter(|state| !state.is_null())
+}
+
+/// Entry of the audit log that is about to be recorded
+pub struct AuditEntry {
+    action: AuditAction,
+    target: Option<AuditTarget>,
+    reason: Option<String>,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+}
+
+impl AuditEntry {
+    pub fn new(action: AuditAction) -> Self {
+        Self {
+            action,
+            target: None,
+            reason: None,
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn target(mut self, target: impl Into<AuditTarget>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn reason(mut self, reason: Option<String>) -> Self {
+        self.reason = reason;
+        self
+    }
+
+    pub fn before(mut self, state: &impl Serialize) -> Self {
+        self.before = to_json(state);
+        self
+    }
+
+    pub fn after(mut self, state: &impl Serialize) -> Self {
+        self.after = to_json(state);
+        self
+    }
+
+    /// Stores the entry with the connected user as the actor. The action
+    /// has already happened at this point, so failures are only logged
+    pub async fn record(self, app_state: &AppState, connection_state: &ConnectionState) {
+        let Some(actor) = connection_state.read().unwrap().get_user_id() else {
+            return;
+        };
+
+        let target = self.target.map(target_to_db);
+
+        let result = audit_log::ActiveModel {
+            actor_id: Set(actor.value),
+            action: Set(action_to_db(self.action)),
+            target_kind: Set(target.map(|(kind, _)| kind)),
+            target_id: Set(target.map(|(_, id)| id)),
+            reason: Set(self.reason),
+            before: Set(self.before),
+            after: Set(self.after),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&app_state.db)
+        .await;
+
+        if let Err(err) = result {
+            log::error!("Failed to record {:?} in the audit log: {err}", self.action);
+        }
+    }
+}
+
+/// Periodically removes entries older than the configured retention period
+pub async fn prune_old_entries(app_state: AppState) {
+    let retention_days = app_state.config.audit_log_retention_days;
+
+    // Entries are kept forever
+    if retention_days <= 0 {
+        return;
+    }
+
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let threshold = Utc::now() - chrono::Duration::days(retention_days);
+
+        let result = AuditLog::delete_many()
+            .filter(audit_log::Column::CreatedAt.lt(threshold.naive_utc()))
+            .exec(&app_state.db)
+            .await;
+
+        match result {
+            Ok(result) if result.rows_affected > 0 => {
+                log::info!("Pruned {} audit log entries", result.rows_affected);
+            }
+            Ok(_) => {}
+            Err(err) => log::error!("Failed to prune the audit log: {err}"),
+        }
+    }
+}
+
+impl RPCHandle for GetAuditLog {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        GetAuditLogPayload {
+            before,
+            limit,
+            actor,
+            action,
+            target,
+        }: GetAuditLogPayload,
+    ) -> APIResult<AuditLogPage, ()> {
+        check_auth!(connection_state);
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            None,
+            Permissions::ADMINISTRATOR,
+        )
+        .await?;
+
+        let mut query = AuditLog::find().order_by_desc(audit_log::Column::Id);
+
+        if let Some(before) = before {
+            query = query.filter(audit_log::Column::Id.lt(before.value));
+        }
+
+        if let Some(actor) = actor {
+            query = query.filter(audit_log::Column::ActorId.eq(actor.value));
+        }
+
+        if let Some(action) = action {
+            query = query.filter(audit_log::Column::Action.eq(action_to_db(action)));
+        }
+
+        if let Some(target) = target {
+            let (kind, id) = target_to_db(target);
+
+            query = query
+                .filter(audit_log::Column::TargetKind.eq(kind))
+                .filter(audit_log::Column::TargetId.eq(id));
+        }
+
+        let limit = match limit {
+            0 => DEFAULT_PAGE_SIZE,
+            limit => Ord::min(limit, MAX_PAGE_SIZE),
+        };
+
+        let mut entries = query
+            .limit(limit as u64 + 1)
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let has_more = entries.len() > limit as usize;
+        entries.truncate(limit as usize);
+
+        Ok(AuditLogPage {
+            entries: entries.into_iter().filter_map(entry_from_model).collect(),
+            has_more,
+        })
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(router, GetAuditLog)
+}
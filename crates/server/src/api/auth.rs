@@ -1,37 +1,66 @@
 use chrono::Utc;
+use rpc::check_auth;
 use rpc::models::{
     auth::{
-        GetSessionKey, GetSessionKeyError, GetSessionKeyPayload, GetSessionKeyResponse,
-        GetUserInfo, GetUserPayload, Login, LoginError, LoginPayload, SessionKey, UserInfo,
+        ChangePassword, ChangePasswordError, ChangePasswordPayload, GetSessionKey,
+        GetSessionKeyError, GetSessionKeyPayload, GetSessionKeyResponse, GetUserInfo,
+        GetUserPayload, Login, LoginError, LoginPayload, RotateSessionKey, RotateSessionKeyError,
+        RotateSessionKeyPayload, SessionTerminationReason,
     },
-    common::{APIError, RPCMethod as _, RPCNotification},
-    general::{UserConnectionUpdate, UserConnectionUpdateMessage},
+    common::{APIError, RPCMethod as _},
     markers::TaggedEntity,
+    permissions::Permissions,
 };
 
 use sha2::{Digest, Sha256};
 
 use crate::{
     AppState, ConnectionState, GlobalRouter,
-    api::common::{DbErrReponseCompat as _, RPCHandle},
+    api::{
+        common::{DbErrReponseCompat as _, RPCHandle},
+        invites::redeem_invite,
+        permissions::require_permission,
+        presence::broadcast_presence,
+        profiles::user_info,
+        sessions::{create_session, renew_session, terminate_connections},
+    },
 };
 use crate::{
-    entity::user::{self, Entity as User},
+    entity::{
+        session,
+        user::{self, Entity as User},
+    },
+    rate_limit::rate_limited,
     register_endpoints,
 };
 
-use sea_orm::{DbErr, entity::*, query::*};
+use sea_orm::{DbErr, TransactionTrait as _, entity::*, query::*};
 
-const KEY: &[u8] = b"TODO";
+/// Passwords are stored as hex encoded SHA-256 digests
+pub fn hash_password(password: &str) -> String {
+    format!("{:x}", Sha256::digest(password.as_bytes()))
+}
 
 impl RPCHandle for GetSessionKey {
     async fn handle(
         app_state: AppState,
-        _connection_state: ConnectionState,
-        GetSessionKeyPayload { login, password }: GetSessionKeyPayload,
+        connection_state: ConnectionState,
+        GetSessionKeyPayload {
+            login,
+            password,
+            device_name,
+            invite,
+        }: GetSessionKeyPayload,
     ) -> Self::Response {
-        let password = Sha256::digest(password.as_bytes());
-        let password = format!("{:x}", password);
+        let ip = connection_state.read().unwrap().addr.ip();
+        let ip_address = ip.to_string();
+
+        app_state
+            .rate_limiter
+            .check_login(ip, &login)
+            .map_err(rate_limited)?;
+
+        let password = hash_password(&password);
 
         let user = User::find()
             .filter(user::Column::Username.eq(&login))
@@ -42,30 +71,62 @@ impl RPCHandle for GetSessionKey {
         match user {
             Some(user) => {
                 if user.password == password {
-                    let key = SessionKey::new(user.id, KEY);
+                    app_state.rate_limiter.login_succeeded(ip, &login);
+
+                    if user.is_banned() {
+                        return Err(APIError::Err(GetSessionKeyError::UserBanned));
+                    }
+
+                    let key = create_session(&app_state, user.id, device_name, ip_address)
+                        .await
+                        .map_err(DbErr::into_api_error)?;
 
                     Ok(GetSessionKeyResponse::ExistingUser(key))
                 } else {
+                    app_state.rate_limiter.login_failed(ip, &login);
+
                     Err(APIError::Err(GetSessionKeyError::UserAlreadyExists))
                 }
             }
             None => {
+                let txn = app_state.db.begin().await.map_err(DbErr::into_api_error)?;
+
+                // The invite is only used up if the account gets created
+                if app_state.config.invite_only {
+                    let Some(invite) = invite else {
+                        return Err(APIError::Err(GetSessionKeyError::InviteRequired));
+                    };
+
+                    if !redeem_invite(&txn, &invite)
+                        .await
+                        .map_err(DbErr::into_api_error)?
+                    {
+                        return Err(APIError::Err(GetSessionKeyError::InviteRequired));
+                    }
+                }
+
                 let user = user::ActiveModel {
                     username: Set(login),
                     password: Set(password),
                     banned: Set(false),
+                    ban_reason: Set(None),
+                    banned_until: Set(None),
                     created_at: Set(Utc::now().naive_utc()),
                     ..Default::default()
                 };
 
-                let user = user.insert(&app_state.db).await.map_err(|err| match err {
+                let user = user.insert(&txn).await.map_err(|err| match err {
                     DbErr::RecordNotInserted => {
                         APIError::Err(GetSessionKeyError::UserAlreadyExists)
                     }
                     _ => err.into_api_error(),
                 })?;
 
-                let key = SessionKey::new(user.id, KEY);
+                txn.commit().await.map_err(DbErr::into_api_error)?;
+
+                let key = create_session(&app_state, user.id, device_name, ip_address)
+                    .await
+                    .map_err(DbErr::into_api_error)?;
 
                 Ok(GetSessionKeyResponse::NewUser(key))
             }
@@ -79,7 +140,7 @@ impl RPCHandle for Login {
         connection_state: ConnectionState,
         LoginPayload { session_key }: LoginPayload,
     ) -> Self::Response {
-        if !session_key.verify(b"TODO") {
+        if !app_state.session_keys.read().unwrap().verify(&session_key) {
             return Err(APIError::Err(LoginError::InvalidSesssionKey));
         }
 
@@ -95,32 +156,35 @@ impl RPCHandle for Login {
             .ok_or(APIError::Err(LoginError::UserNotFound))?;
         let user_id = user.tagged_id();
 
+        if user.is_banned() {
+            return Err(APIError::Err(LoginError::UserBanned));
+        }
+
+        let ip_address = connection_state.read().unwrap().addr.ip().to_string();
+
+        let (session, session_key) = renew_session(&app_state, &session_key, ip_address)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::Err(LoginError::SessionRevoked))?;
+
         {
             let mut state = connection_state.write().unwrap();
 
             state.user = Some(user);
-        }
-
-        let writers = app_state
-            .connected_clients
-            .iter()
-            .map(|user| user.read().unwrap().writer.clone())
-            .collect::<Vec<_>>();
-
-        for writer in writers {
-            UserConnectionUpdate {
-                user_id,
-                message: UserConnectionUpdateMessage::UserConnected,
-            }
-            .notify(&writer)
-            .await;
+            state.session_id = Some(session.tagged_id());
         }
 
         app_state
             .connected_clients
-            .insert(user_id, connection_state);
+            .entry(user_id)
+            .or_default()
+            .push(connection_state);
 
-        Ok(())
+        broadcast_presence(&app_state, user_id)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        Ok(session_key)
     }
 }
 
@@ -135,13 +199,132 @@ impl RPCHandle for GetUserInfo {
             .await
             .map_err(DbErr::into_api_error)?;
 
-        Ok(user.map(|user| UserInfo {
-            id: user.tagged_id(),
-            username: user.username,
-        }))
+        Ok(user.map(user_info))
+    }
+}
+
+impl RPCHandle for ChangePassword {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        ChangePasswordPayload {
+            current_password,
+            new_password,
+        }: ChangePasswordPayload,
+    ) -> Self::Response {
+        check_auth!(connection_state);
+
+        if new_password.is_empty() {
+            return Err(APIError::Err(ChangePasswordError::InvalidPassword));
+        }
+
+        let (user_id, session_id, addr) = {
+            let conn = connection_state.read().unwrap();
+
+            (
+                conn.get_user_id().expect("We checked auth above"),
+                conn.session_id,
+                conn.addr,
+            )
+        };
+
+        let user = User::find_by_id(user_id.value)
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::ServerError)?;
+
+        if user.password != hash_password(&current_password) {
+            return Err(APIError::Err(ChangePasswordError::WrongPassword));
+        }
+
+        let mut model: user::ActiveModel = user.into();
+        model.password = Set(hash_password(&new_password));
+
+        model
+            .update(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let mut other_sessions =
+            session::Entity::delete_many().filter(session::Column::UserId.eq(user_id.value));
+
+        if let Some(session_id) = session_id {
+            other_sessions = other_sessions.filter(session::Column::Id.ne(session_id.value));
+        }
+
+        other_sessions
+            .exec(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        terminate_connections(&app_state, SessionTerminationReason::Revoked, |conn| {
+            conn.get_user_id() == Some(user_id) && conn.addr != addr
+        })
+        .await;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for RotateSessionKey {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        RotateSessionKeyPayload { retire_previous }: RotateSessionKeyPayload,
+    ) -> Self::Response {
+        require_permission(
+            &app_state,
+            &connection_state,
+            None,
+            Permissions::ADMINISTRATOR,
+        )
+        .await?;
+
+        let key_id = {
+            let mut session_keys = app_state.session_keys.write().unwrap();
+
+            if session_keys.is_managed_by_config() {
+                return Err(APIError::Err(RotateSessionKeyError::ManagedByConfig));
+            }
+
+            session_keys.rotate(retire_previous).map_err(|err| {
+                log::error!("Failed to rotate session keys: {err:?}");
+
+                APIError::ServerError
+            })?
+        };
+
+        log::info!(
+            "Session keys rotated, current key: {key_id}, retired previous: {retire_previous}"
+        );
+
+        if retire_previous {
+            // Sessions signed with retired keys can't be used anymore
+            session::Entity::delete_many()
+                .exec(&app_state.db)
+                .await
+                .map_err(DbErr::into_api_error)?;
+
+            let current = connection_state.read().unwrap().addr;
+
+            terminate_connections(&app_state, SessionTerminationReason::Revoked, |conn| {
+                conn.addr != current
+            })
+            .await;
+        }
+
+        Ok(key_id)
     }
 }
 
 pub fn merge(router: GlobalRouter) -> GlobalRouter {
-    register_endpoints!(router, Login, GetUserInfo, GetSessionKey)
+    register_endpoints!(
+        router,
+        Login,
+        GetUserInfo,
+        GetSessionKey,
+        RotateSessionKey,
+        ChangePassword,
+    )
 }
@@ -0,0 +1,709 @@
+use std::ops::RangeInclusive;
+
+use chrono::Utc;
+use rpc::{
+    check_auth,
+    common::Empty,
+    models::{
+        audit_log::AuditAction,
+        channels::{
+            ChannelError, ChannelListUpdate, ChannelListUpdateMessage, ChannelPosition,
+            CreateTextChannel, CreateVoiceChannel, DeleteChannel, DeleteChannelPayload,
+            GetTextChannels, ReorderChannels, ReorderChannelsPayload, TextChannel,
+            TextChannelSettings, UpdateTextChannel, UpdateTextChannelPayload, UpdateVoiceChannel,
+            UpdateVoiceChannelPayload, VoiceChannelInfo, VoiceChannelSettings,
+        },
+        common::{APIError, APIResult, RPCMethod as _, RPCNotification as _},
+        markers::{ChannelId, TaggedEntity as _, TextChannelId, VoiceChannelId},
+        messages::TextMessageChannel,
+        permissions::Permissions,
+        voice::VoiceChannelUpdateMessage,
+    },
+    server::RpcWriter,
+};
+
+use sea_orm::{DatabaseConnection, DbErr, TransactionTrait as _, entity::*, query::*};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        audit_log::AuditEntry,
+        common::{DbErrReponseCompat as _, RPCHandle},
+        messages::StoredChannel,
+        permissions::{
+            PermissionScope, PermissionSnapshot, channel_viewers, delete_channel_overrides,
+            require_permission,
+        },
+        voice::broadcast_voice_update,
+    },
+    config::Config,
+    entity::{
+        mention::{self, Entity as Mention},
+        message::{self, Entity as Message},
+        message_revision::{self, Entity as MessageRevision},
+        reaction::{self, Entity as Reaction},
+        read_state::{self, Entity as ReadState},
+        text_channel::{self, Entity as TextChannelEntity},
+        voice_channel::{self, Entity as VoiceChannelEntity},
+    },
+    register_endpoints,
+};
+
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Range of bitrates supported by Opus
+const BITRATE_RANGE: RangeInclusive<u32> = 6_000..=510_000;
+
+/// Bitrate of voice channels created from the config
+pub const DEFAULT_BITRATE: u32 = 128_000;
+
+fn text_channel_info(channel: text_channel::Model) -> TextChannel {
+    TextChannel {
+        id: channel.tagged_id(),
+        name: channel.name,
+        position: channel.position,
+        category: channel.category,
+    }
+}
+
+fn voice_channel_info(channel: voice_channel::Model) -> VoiceChannelInfo {
+    VoiceChannelInfo {
+        id: channel.tagged_id(),
+        name: channel.name,
+        position: channel.position,
+        category: channel.category,
+        user_limit: Ord::max(channel.max_participants, 0) as u32,
+        bitrate: Ord::max(channel.bitrate, 0) as u32,
+    }
+}
+
+pub fn validate_name(name: &str) -> Result<String, ChannelError> {
+    let name = name.trim();
+
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(ChannelError::InvalidName);
+    }
+
+    Ok(name.to_owned())
+}
+
+pub fn validate_voice_settings(settings: &VoiceChannelSettings) -> Result<String, ChannelError> {
+    if !BITRATE_RANGE.contains(&settings.bitrate) {
+        return Err(ChannelError::InvalidBitrate);
+    }
+
+    validate_name(&settings.name)
+}
+
+/// Deletes the channel along with its messages and everything attached
+/// to them. Returns `false` if the channel doesn't exist
+pub async fn delete_channel_rows(
+    db: &DatabaseConnection,
+    channel: ChannelId,
+) -> Result<bool, DbErr> {
+    let txn = db.begin().await?;
+
+    let result = match channel {
+        ChannelId::Text(id) => {
+            let messages = Message::find()
+                .select_only()
+                .column(message::Column::Id)
+                .filter(StoredChannel::Text(id).condition())
+                .into_query();
+
+            MessageRevision::delete_many()
+                .filter(message_revision::Column::MessageId.in_subquery(messages.clone()))
+                .exec(&txn)
+                .await?;
+
+            Mention::delete_many()
+                .filter(mention::Column::MessageId.in_subquery(messages.clone()))
+                .exec(&txn)
+                .await?;
+
+            Reaction::delete_many()
+                .filter(reaction::Column::MessageId.in_subquery(messages))
+                .exec(&txn)
+                .await?;
+
+            ReadState::delete_many()
+                .filter(read_state::Column::ChannelId.eq(id.value))
+                .filter(read_state::Column::InGroup.eq(false))
+                .exec(&txn)
+                .await?;
+
+            Message::delete_many()
+                .filter(StoredChannel::Text(id).condition())
+                .exec(&txn)
+                .await?;
+
+            TextChannelEntity::delete_by_id(id.value).exec(&txn).await?
+        }
+        ChannelId::Voice(id) => {
+            VoiceChannelEntity::delete_by_id(id.value)
+                .exec(&txn)
+                .await?
+        }
+    };
+
+    if result.rows_affected == 0 {
+        return Ok(false);
+    }
+
+    delete_channel_overrides(&txn, channel).await?;
+
+    txn.commit().await?;
+
+    Ok(true)
+}
+
+/// Creates channels listed in the config that don't exist yet, matching
+/// them by name. Channels missing from the config are never deleted
+pub async fn create_configured_channels(
+    app_state: &AppState,
+    config: &Config,
+) -> Result<(), DbErr> {
+    let text_channels = TextChannelEntity::find().all(&app_state.db).await?;
+    let mut position = text_channels
+        .iter()
+        .map(|channel| channel.position + 1)
+        .max()
+        .unwrap_or(0);
+
+    for configured in &config.text_channels {
+        let name = configured.name.trim();
+
+        if text_channels.iter().any(|channel| channel.name == name) {
+            continue;
+        }
+
+        let channel = text_channel::ActiveModel {
+            name: Set(name.to_owned()),
+            position: Set(position),
+            category: Set(None),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&app_state.db)
+        .await?;
+
+        position += 1;
+        log::info!("Created text channel {name} from the config");
+
+        broadcast(
+            app_state,
+            ChannelListUpdateMessage::TextChannelCreated(text_channel_info(channel)),
+        )
+        .await;
+    }
+
+    let voice_channels = VoiceChannelEntity::find().all(&app_state.db).await?;
+    let mut position = voice_channels
+        .iter()
+        .map(|channel| channel.position + 1)
+        .max()
+        .unwrap_or(0);
+
+    for configured in &config.voice_channels {
+        let name = configured.name.trim();
+
+        if voice_channels.iter().any(|channel| channel.name == name) {
+            continue;
+        }
+
+        let channel = voice_channel::ActiveModel {
+            name: Set(name.to_owned()),
+            position: Set(position),
+            category: Set(None),
+            max_participants: Set(Ord::min(configured.max_participants, i32::MAX as u32) as i32),
+            bitrate: Set(DEFAULT_BITRATE as i32),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&app_state.db)
+        .await?;
+
+        position += 1;
+        log::info!("Created voice channel {name} from the config");
+
+        broadcast(
+            app_state,
+            ChannelListUpdateMessage::VoiceChannelCreated(voice_channel_info(channel)),
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// Sends the update to everyone who can see the channel
+async fn broadcast(app_state: &AppState, message: ChannelListUpdateMessage) {
+    let channel = match &message {
+        ChannelListUpdateMessage::TextChannelCreated(channel)
+        | ChannelListUpdateMessage::TextChannelUpdated(channel) => ChannelId::Text(channel.id),
+        ChannelListUpdateMessage::TextChannelDeleted(id) => ChannelId::Text(*id),
+        ChannelListUpdateMessage::VoiceChannelCreated(channel)
+        | ChannelListUpdateMessage::VoiceChannelUpdated(channel) => ChannelId::Voice(channel.id),
+        ChannelListUpdateMessage::VoiceChannelDeleted(id) => ChannelId::Voice(*id),
+    };
+
+    match channel_viewers(app_state, channel).await {
+        Ok(viewers) => notify_viewers(viewers, message).await,
+        Err(err) => log::error!("Failed to load viewers of the channel: {err}"),
+    }
+}
+
+async fn notify_viewers(viewers: Vec<RpcWriter>, message: ChannelListUpdateMessage) {
+    for writer in viewers {
+        ChannelListUpdate {
+            message: message.clone(),
+        }
+        .notify(&writer)
+        .await;
+    }
+}
+
+/// Detaches everyone from the voice channel, used when it's deleted.
+/// Members are disconnected before the channel itself goes away
+fn clear_voice_channel(app_state: &AppState, channel_id: VoiceChannelId) {
+    let Some((_, users)) = app_state.channels.voice_channels.remove(&channel_id) else {
+        return;
+    };
+
+    for user in users {
+        let connections = app_state
+            .connected_clients
+            .get(&user.id)
+            .map(|connections| connections.clone())
+            .unwrap_or_default();
+
+        for connection in connections {
+            let mut connection = connection.write().unwrap();
+
+            if connection.active_voice_channel == Some(channel_id) {
+                connection.active_voice_channel = None;
+                connection.active_stream = None;
+            }
+        }
+
+        broadcast_voice_update(
+            app_state,
+            channel_id,
+            VoiceChannelUpdateMessage::UserDisconnected(user.id),
+        );
+    }
+}
+
+impl RPCHandle for GetTextChannels {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        _req: Empty,
+    ) -> APIResult<Vec<TextChannel>, ()> {
+        check_auth!(connection_state);
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let snapshot = PermissionSnapshot::load(
+            &app_state.db,
+            &[user_id.value],
+            PermissionScope::AllChannels,
+        )
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+        let channels = TextChannelEntity::find()
+            .order_by_asc(text_channel::Column::Position)
+            .order_by_asc(text_channel::Column::Id)
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        Ok(channels
+            .into_iter()
+            .filter(|channel| {
+                snapshot.can_view(user_id.value, ChannelId::Text(channel.tagged_id()))
+            })
+            .map(text_channel_info)
+            .collect())
+    }
+}
+
+impl RPCHandle for CreateTextChannel {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        settings: TextChannelSettings,
+    ) -> APIResult<TextChannelId, ChannelError> {
+        check_auth!(connection_state);
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            None,
+            Permissions::MANAGE_CHANNELS,
+        )
+        .await?;
+
+        let name = validate_name(&settings.name).map_err(APIError::Err)?;
+
+        let channel = text_channel::ActiveModel {
+            name: Set(name),
+            position: Set(settings.position),
+            category: Set(settings.category),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+        let id = channel.tagged_id();
+        let info = text_channel_info(channel);
+
+        AuditEntry::new(AuditAction::ChannelCreate)
+            .target(ChannelId::Text(id))
+            .after(&info)
+            .record(&app_state, &connection_state)
+            .await;
+
+        broadcast(
+            &app_state,
+            ChannelListUpdateMessage::TextChannelCreated(info),
+        )
+        .await;
+
+        Ok(id)
+    }
+}
+
+impl RPCHandle for UpdateTextChannel {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        UpdateTextChannelPayload { id, settings }: UpdateTextChannelPayload,
+    ) -> APIResult<(), ChannelError> {
+        check_auth!(connection_state);
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            Some(ChannelId::Text(id)),
+            Permissions::MANAGE_CHANNELS,
+        )
+        .await?;
+
+        let name = validate_name(&settings.name).map_err(APIError::Err)?;
+
+        let channel = TextChannelEntity::find_by_id(id.value)
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::Err(ChannelError::NotFound))?;
+
+        let before = text_channel_info(channel.clone());
+
+        let mut channel: text_channel::ActiveModel = channel.into();
+        channel.name = Set(name);
+        channel.position = Set(settings.position);
+        channel.category = Set(settings.category);
+
+        let channel = channel
+            .update(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let info = text_channel_info(channel);
+
+        AuditEntry::new(AuditAction::ChannelUpdate)
+            .target(ChannelId::Text(id))
+            .before(&before)
+            .after(&info)
+            .record(&app_state, &connection_state)
+            .await;
+
+        broadcast(
+            &app_state,
+            ChannelListUpdateMessage::TextChannelUpdated(info),
+        )
+        .await;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for CreateVoiceChannel {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        settings: VoiceChannelSettings,
+    ) -> APIResult<VoiceChannelId, ChannelError> {
+        check_auth!(connection_state);
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            None,
+            Permissions::MANAGE_CHANNELS,
+        )
+        .await?;
+
+        let name = validate_voice_settings(&settings).map_err(APIError::Err)?;
+
+        let channel = voice_channel::ActiveModel {
+            name: Set(name),
+            position: Set(settings.position),
+            category: Set(settings.category),
+            max_participants: Set(Ord::min(settings.user_limit, i32::MAX as u32) as i32),
+            bitrate: Set(settings.bitrate as i32),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+        let id = channel.tagged_id();
+        let info = voice_channel_info(channel);
+
+        AuditEntry::new(AuditAction::ChannelCreate)
+            .target(ChannelId::Voice(id))
+            .after(&info)
+            .record(&app_state, &connection_state)
+            .await;
+
+        broadcast(
+            &app_state,
+            ChannelListUpdateMessage::VoiceChannelCreated(info),
+        )
+        .await;
+
+        Ok(id)
+    }
+}
+
+impl RPCHandle for UpdateVoiceChannel {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        UpdateVoiceChannelPayload { id, settings }: UpdateVoiceChannelPayload,
+    ) -> APIResult<(), ChannelError> {
+        check_auth!(connection_state);
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            Some(ChannelId::Voice(id)),
+            Permissions::MANAGE_CHANNELS,
+        )
+        .await?;
+
+        let name = validate_voice_settings(&settings).map_err(APIError::Err)?;
+
+        let channel = VoiceChannelEntity::find_by_id(id.value)
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::Err(ChannelError::NotFound))?;
+
+        let before = voice_channel_info(channel.clone());
+
+        // Lowering the limit doesn't kick anyone, it only affects new joins
+        let mut channel: voice_channel::ActiveModel = channel.into();
+        channel.name = Set(name);
+        channel.position = Set(settings.position);
+        channel.category = Set(settings.category);
+        channel.max_participants = Set(Ord::min(settings.user_limit, i32::MAX as u32) as i32);
+        channel.bitrate = Set(settings.bitrate as i32);
+
+        let channel = channel
+            .update(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let info = voice_channel_info(channel);
+
+        AuditEntry::new(AuditAction::ChannelUpdate)
+            .target(ChannelId::Voice(id))
+            .before(&before)
+            .after(&info)
+            .record(&app_state, &connection_state)
+            .await;
+
+        broadcast(
+            &app_state,
+            ChannelListUpdateMessage::VoiceChannelUpdated(info),
+        )
+        .await;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for DeleteChannel {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        DeleteChannelPayload { channel }: DeleteChannelPayload,
+    ) -> APIResult<(), ChannelError> {
+        check_auth!(connection_state);
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            Some(channel),
+            Permissions::MANAGE_CHANNELS,
+        )
+        .await?;
+
+        // Settings of the deleted channel are kept in the audit log
+        let entry = AuditEntry::new(AuditAction::ChannelDelete).target(channel);
+        let entry = match channel {
+            ChannelId::Text(id) => TextChannelEntity::find_by_id(id.value)
+                .one(&app_state.db)
+                .await
+                .map_err(DbErr::into_api_error)?
+                .map(|channel| entry.before(&text_channel_info(channel))),
+            ChannelId::Voice(id) => VoiceChannelEntity::find_by_id(id.value)
+                .one(&app_state.db)
+                .await
+                .map_err(DbErr::into_api_error)?
+                .map(|channel| entry.before(&voice_channel_info(channel))),
+        }
+        .ok_or(APIError::Err(ChannelError::NotFound))?;
+
+        // Overrides are deleted along with the channel, so viewers are found beforehand
+        let viewers = channel_viewers(&app_state, channel)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let deleted = delete_channel_rows(&app_state.db, channel)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if !deleted {
+            return Err(APIError::Err(ChannelError::NotFound));
+        }
+
+        entry.record(&app_state, &connection_state).await;
+
+        let message = match channel {
+            ChannelId::Text(id) => {
+                app_state
+                    .channels
+                    .text_channels
+                    .remove(&TextMessageChannel::TextChannel(id));
+
+                ChannelListUpdateMessage::TextChannelDeleted(id)
+            }
+            ChannelId::Voice(id) => {
+                clear_voice_channel(&app_state, id);
+
+                ChannelListUpdateMessage::VoiceChannelDeleted(id)
+            }
+        };
+
+        notify_viewers(viewers, message).await;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for ReorderChannels {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        ReorderChannelsPayload { positions }: ReorderChannelsPayload,
+    ) -> APIResult<(), ChannelError> {
+        check_auth!(connection_state);
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            None,
+            Permissions::MANAGE_CHANNELS,
+        )
+        .await?;
+
+        let txn = app_state.db.begin().await.map_err(DbErr::into_api_error)?;
+
+        let mut updates = Vec::with_capacity(positions.len());
+        let mut before = Vec::with_capacity(positions.len());
+
+        for item in positions.iter() {
+            let update = match item.channel {
+                ChannelId::Text(id) => {
+                    let channel = TextChannelEntity::find_by_id(id.value)
+                        .one(&txn)
+                        .await
+                        .map_err(DbErr::into_api_error)?
+                        .ok_or(APIError::Err(ChannelError::NotFound))?;
+
+                    before.push(ChannelPosition {
+                        channel: item.channel,
+                        position: channel.position,
+                    });
+
+                    let mut channel: text_channel::ActiveModel = channel.into();
+                    channel.position = Set(item.position);
+
+                    let channel = channel.update(&txn).await.map_err(DbErr::into_api_error)?;
+
+                    ChannelListUpdateMessage::TextChannelUpdated(text_channel_info(channel))
+                }
+                ChannelId::Voice(id) => {
+                    let channel = VoiceChannelEntity::find_by_id(id.value)
+                        .one(&txn)
+                        .await
+                        .map_err(DbErr::into_api_error)?
+                        .ok_or(APIError::Err(ChannelError::NotFound))?;
+
+                    before.push(ChannelPosition {
+                        channel: item.channel,
+                        position: channel.position,
+                    });
+
+                    let mut channel: voice_channel::ActiveModel = channel.into();
+                    channel.position = Set(item.position);
+
+                    let channel = channel.update(&txn).await.map_err(DbErr::into_api_error)?;
+
+                    ChannelListUpdateMessage::VoiceChannelUpdated(voice_channel_info(channel))
+                }
+            };
+
+            updates.push(update);
+        }
+
+        txn.commit().await.map_err(DbErr::into_api_error)?;
+
+        AuditEntry::new(AuditAction::ChannelReorder)
+            .before(&before)
+            .after(&positions)
+            .record(&app_state, &connection_state)
+            .await;
+
+        for update in updates {
+            broadcast(&app_state, update).await;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(
+        router,
+        GetTextChannels,
+        CreateTextChannel,
+        UpdateTextChannel,
+        CreateVoiceChannel,
+        UpdateVoiceChannel,
+        DeleteChannel,
+        ReorderChannels,
+    )
+}
@@ -1,7 +1,7 @@
 use rpc::models::common::{APIError, RPCMethod};
 use sea_orm::DbErr;
 
-use crate::{AppState, ConnectionState};
+use crate::{AppState, ConnectionState, metrics::METRICS};
 
 pub trait DbErrReponseCompat {
     fn into_api_error<E: std::fmt::Debug>(self) -> APIError<E>;
@@ -10,6 +10,15 @@ pub trait DbErrReponseCompat {
 impl DbErrReponseCompat for DbErr {
     fn into_api_error<E: std::fmt::Debug>(self) -> APIError<E> {
         log::error!("Database Error: {self:?}");
+        METRICS.db_error();
+
+        APIError::ServerError
+    }
+}
+
+impl DbErrReponseCompat for std::io::Error {
+    fn into_api_error<E: std::fmt::Debug>(self) -> APIError<E> {
+        log::error!("IO Error: {self:?}");
 
         APIError::ServerError
     }
@@ -23,6 +32,9 @@ pub trait RPCHandle: RPCMethod {
     ) -> Self::Response;
 }
 
+/// Registers handlers of the methods, requests over
+/// the rate limit of a method are rejected before its handler runs.
+/// Calls are counted and timed for the metrics
 #[macro_export]
 macro_rules! register_endpoints {
     ($router:expr, $($endpoint:ident),+ $(,)?) => {
@@ -30,7 +42,28 @@ macro_rules! register_endpoints {
             $(
                 .register(
                     $endpoint::key(),
-                    $endpoint::handle
+                    |app_state: $crate::AppState,
+                     connection_state: $crate::ConnectionState,
+                     req: <$endpoint as rpc::models::common::RPCMethod>::Request| async move {
+                        let started_at = std::time::Instant::now();
+                        let metrics = &$crate::metrics::METRICS;
+
+                        let key = $crate::rate_limit::RateLimitKey::of(
+                            &connection_state.read().unwrap(),
+                        );
+
+                        let limit = app_state.rate_limiter.check($endpoint::key(), key);
+                        if let Err(retry_after) = limit {
+                            metrics.rpc_rate_limited($endpoint::key());
+
+                            return Err($crate::rate_limit::rate_limited(retry_after));
+                        }
+
+                        let response = $endpoint::handle(app_state, connection_state, req).await;
+                        metrics.rpc_handled($endpoint::key(), response.is_ok(), started_at.elapsed());
+
+                        response
+                    }
                 )
             )+
     };
@@ -0,0 +1,181 @@
+use std::ops::RangeInclusive;
+
+use chrono::Utc;
+use rpc::{
+    check_auth,
+    common::Empty,
+    models::{
+        common::{APIError, APIResult, RPCMethod as _},
+        emojis::{
+            CreateCustomEmoji, CreateCustomEmojiPayload, CustomEmoji, DeleteCustomEmoji,
+            DeleteCustomEmojiPayload, EmojiError, GetCustomEmojis,
+        },
+        markers::{Id, TaggedEntity as _},
+        permissions::Permissions,
+    },
+};
+
+use sea_orm::{DbErr, TransactionTrait as _, entity::*, query::*};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        common::{DbErrReponseCompat as _, RPCHandle},
+        permissions::require_permission,
+    },
+    entity::{
+        custom_emoji::{self, Entity as CustomEmojiEntity},
+        media::{self, Entity as Media},
+        reaction::{self, Entity as Reaction},
+    },
+    register_endpoints,
+};
+
+const NAME_LENGTH: RangeInclusive<usize> = 2..=32;
+
+fn emoji_from_model(model: custom_emoji::Model) -> CustomEmoji {
+    CustomEmoji {
+        id: model.tagged_id(),
+        name: model.name,
+        media: Id::new(model.media_id),
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), EmojiError> {
+    let is_valid = NAME_LENGTH.contains(&name.len())
+        && name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_');
+
+    if !is_valid {
+        return Err(EmojiError::InvalidName);
+    }
+
+    Ok(())
+}
+
+impl RPCHandle for GetCustomEmojis {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        _req: Empty,
+    ) -> APIResult<Vec<CustomEmoji>, ()> {
+        check_auth!(connection_state);
+
+        let emojis = CustomEmojiEntity::find()
+            .order_by_asc(custom_emoji::Column::Name)
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        Ok(emojis.into_iter().map(emoji_from_model).collect())
+    }
+}
+
+impl RPCHandle for CreateCustomEmoji {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        CreateCustomEmojiPayload { name, media }: CreateCustomEmojiPayload,
+    ) -> APIResult<CustomEmoji, EmojiError> {
+        check_auth!(connection_state);
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            None,
+            Permissions::MANAGE_EMOJIS,
+        )
+        .await?;
+
+        validate_name(&name).map_err(APIError::Err)?;
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let is_image = Media::find_by_id(media.value)
+            .filter(media::Column::UploadedBy.eq(user_id.value))
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .is_some_and(|media| media.mime_type.starts_with("image/"));
+
+        if !is_image {
+            return Err(APIError::Err(EmojiError::InvalidMedia));
+        }
+
+        let is_taken = CustomEmojiEntity::find()
+            .filter(custom_emoji::Column::Name.eq(&name))
+            .exists(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if is_taken {
+            return Err(APIError::Err(EmojiError::NameTaken));
+        }
+
+        let model = custom_emoji::ActiveModel {
+            name: Set(name),
+            media_id: Set(media.value),
+            created_by: Set(user_id.value),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+        Ok(emoji_from_model(model))
+    }
+}
+
+impl RPCHandle for DeleteCustomEmoji {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        DeleteCustomEmojiPayload { id }: DeleteCustomEmojiPayload,
+    ) -> APIResult<(), EmojiError> {
+        check_auth!(connection_state);
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            None,
+            Permissions::MANAGE_EMOJIS,
+        )
+        .await?;
+
+        let txn = app_state.db.begin().await.map_err(DbErr::into_api_error)?;
+
+        Reaction::delete_many()
+            .filter(reaction::Column::CustomEmojiId.eq(id.value))
+            .exec(&txn)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let result = CustomEmojiEntity::delete_by_id(id.value)
+            .exec(&txn)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if result.rows_affected == 0 {
+            return Err(APIError::Err(EmojiError::NotFound));
+        }
+
+        txn.commit().await.map_err(DbErr::into_api_error)?;
+
+        Ok(())
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(
+        router,
+        GetCustomEmojis,
+        CreateCustomEmoji,
+        DeleteCustomEmoji,
+    )
+}
@@ -0,0 +1,632 @@
+use std::collections::HashSet;
+
+use chrono::Utc;
+use rpc::{
+    check_auth,
+    common::Empty,
+    models::{
+        common::{APIError, APIResult, RPCMethod as _, RPCNotification as _},
+        groups::{
+            AddGroupMembers, AddGroupMembersPayload, Conversation, ConversationRemoved,
+            ConversationUpdated, CreateGroup, CreateGroupPayload, GetConversations, GroupError,
+            GroupMemberPayload, OpenDirectConversation, OpenDirectConversationPayload,
+            RemoveGroupMember, RenameGroup, RenameGroupPayload, TransferGroupOwnership,
+        },
+        markers::{GroupId, Id, TaggedEntity as _, UserId},
+    },
+};
+
+use sea_orm::{
+    ConnectionTrait, DbErr, SqlErr, TransactionTrait as _, entity::*, query::*, sea_query::Expr,
+};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        common::{DbErrReponseCompat as _, RPCHandle},
+        relationships,
+    },
+    entity::{
+        conversation::{self, Entity as ConversationEntity},
+        conversation_member::{self, Entity as ConversationMember},
+        user::{self, Entity as User},
+    },
+    register_endpoints,
+};
+
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Maximum number of members in a group including the owner
+const MAX_GROUP_MEMBERS: usize = 10;
+
+fn validate_name(name: &str) -> Result<String, GroupError> {
+    let name = name.trim();
+
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(GroupError::InvalidName);
+    }
+
+    Ok(name.to_owned())
+}
+
+pub async fn member_ids(
+    db: &impl ConnectionTrait,
+    conversation_id: i32,
+) -> Result<Vec<i32>, DbErr> {
+    let members = ConversationMember::find()
+        .filter(conversation_member::Column::ConversationId.eq(conversation_id))
+        .order_by_asc(conversation_member::Column::Id)
+        .all(db)
+        .await?;
+
+    Ok(members.into_iter().map(|member| member.user_id).collect())
+}
+
+pub async fn is_member(
+    db: &impl ConnectionTrait,
+    conversation_id: i32,
+    user_id: UserId,
+) -> Result<bool, DbErr> {
+    ConversationMember::find()
+        .filter(conversation_member::Column::ConversationId.eq(conversation_id))
+        .filter(conversation_member::Column::UserId.eq(user_id.value))
+        .exists(db)
+        .await
+}
+
+/// The other member of a direct conversation, `None` for groups
+pub async fn direct_peer(
+    db: &impl ConnectionTrait,
+    conversation_id: i32,
+    user_id: UserId,
+) -> Result<Option<i32>, DbErr> {
+    let is_direct = ConversationEntity::find_by_id(conversation_id)
+        .filter(conversation::Column::IsDirect.eq(true))
+        .exists(db)
+        .await?;
+
+    if !is_direct {
+        return Ok(None);
+    }
+
+    let members = member_ids(db, conversation_id).await?;
+
+    Ok(members.into_iter().find(|member| *member != user_id.value))
+}
+
+/// Bumps the conversation to the top of the list
+pub async fn touch(db: &impl ConnectionTrait, conversation_id: i32) -> Result<(), DbErr> {
+    ConversationEntity::update_many()
+        .col_expr(
+            conversation::Column::LastActivityAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .filter(conversation::Column::Id.eq(conversation_id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+async fn add_members(
+    db: &impl ConnectionTrait,
+    conversation_id: i32,
+    members: impl IntoIterator<Item = i32>,
+) -> Result<(), DbErr> {
+    let now = Utc::now().naive_utc();
+
+    for user_id in members {
+        conversation_member::ActiveModel {
+            conversation_id: Set(conversation_id),
+            user_id: Set(user_id),
+            joined_at: Set(now),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+    }
+
+    Ok(())
+}
+
+async fn to_rpc(
+    db: &impl ConnectionTrait,
+    conversation: conversation::Model,
+) -> Result<Conversation, DbErr> {
+    let members = member_ids(db, conversation.id).await?;
+
+    Ok(Conversation {
+        id: conversation.tagged_id(),
+        name: conversation.name,
+        is_direct: conversation.is_direct,
+        owner: conversation.owner_id.map(Id::new),
+        members: members.into_iter().map(Id::new).collect(),
+        last_activity_at: conversation.last_activity_at.and_utc().timestamp(),
+    })
+}
+
+/// Unique key of the direct conversation between two users
+fn direct_key(user_id: UserId, other_id: UserId) -> (i32, i32, String) {
+    let (low, high) = if user_id.value < other_id.value {
+        (user_id.value, other_id.value)
+    } else {
+        (other_id.value, user_id.value)
+    };
+
+    (low, high, format!("{low}:{high}"))
+}
+
+/// Finds the direct conversation between two users without creating it
+pub async fn find_direct_conversation(
+    db: &impl ConnectionTrait,
+    user_id: UserId,
+    other_id: UserId,
+) -> Result<Option<conversation::Model>, DbErr> {
+    let (_, _, key) = direct_key(user_id, other_id);
+
+    ConversationEntity::find()
+        .filter(conversation::Column::DirectKey.eq(key))
+        .one(db)
+        .await
+}
+
+/// Finds the direct conversation between two users or creates it
+pub async fn open_direct_conversation(
+    app_state: &AppState,
+    user_id: UserId,
+    other_id: UserId,
+) -> Result<conversation::Model, DbErr> {
+    if let Some(existing) = find_direct_conversation(&app_state.db, user_id, other_id).await? {
+        return Ok(existing);
+    }
+
+    let (low, high, key) = direct_key(user_id, other_id);
+    let now = Utc::now().naive_utc();
+    let txn = app_state.db.begin().await?;
+
+    let created = conversation::ActiveModel {
+        name: Set(None),
+        owner_id: Set(None),
+        is_direct: Set(true),
+        direct_key: Set(Some(key.clone())),
+        created_at: Set(now),
+        last_activity_at: Set(now),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await;
+
+    let created = match created {
+        Ok(created) => created,
+        // Somebody opened the same conversation concurrently
+        Err(err) if matches!(err.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+            drop(txn);
+
+            return find_direct_conversation(&app_state.db, user_id, other_id)
+                .await?
+                .ok_or(DbErr::RecordNotFound(key));
+        }
+        Err(err) => return Err(err),
+    };
+
+    add_members(&txn, created.id, [low, high]).await?;
+    txn.commit().await?;
+
+    Ok(created)
+}
+
+/// Sends the current state of the conversation to all of its members
+async fn broadcast_conversation(app_state: &AppState, conversation: &Conversation) {
+    for member in conversation.members.iter() {
+        for writer in app_state.user_writers(*member) {
+            ConversationUpdated {
+                conversation: conversation.clone(),
+            }
+            .notify(&writer)
+            .await;
+        }
+    }
+}
+
+async fn notify_removed(app_state: &AppState, user_id: UserId, id: GroupId) {
+    for writer in app_state.user_writers(user_id) {
+        ConversationRemoved { id }.notify(&writer).await;
+    }
+}
+
+fn current_user_id(connection_state: &ConnectionState) -> UserId {
+    connection_state
+        .read()
+        .unwrap()
+        .get_user_id()
+        .expect("Group handlers check auth first")
+}
+
+/// Looks up a group the current user is a member of
+async fn find_group(
+    app_state: &AppState,
+    user_id: UserId,
+    id: GroupId,
+) -> APIResult<conversation::Model, GroupError> {
+    let conversation = ConversationEntity::find_by_id(id.value)
+        .one(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?
+        .ok_or(APIError::Err(GroupError::NotFound))?;
+
+    let is_member = is_member(&app_state.db, conversation.id, user_id)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+    if !is_member {
+        return Err(APIError::Err(GroupError::NotFound));
+    }
+
+    if conversation.is_direct {
+        return Err(APIError::Err(GroupError::DirectConversation));
+    }
+
+    Ok(conversation)
+}
+
+/// Same as [`find_group`], but only the owner passes
+async fn find_owned_group(
+    app_state: &AppState,
+    user_id: UserId,
+    id: GroupId,
+) -> APIResult<conversation::Model, GroupError> {
+    let conversation = find_group(app_state, user_id, id).await?;
+
+    if conversation.owner_id != Some(user_id.value) {
+        return Err(APIError::Forbidden);
+    }
+
+    Ok(conversation)
+}
+
+async fn ensure_users_exist(app_state: &AppState, users: &[i32]) -> APIResult<(), GroupError> {
+    let found = User::find()
+        .filter(user::Column::Id.is_in(users.iter().copied()))
+        .count(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+    if found as usize != users.len() {
+        return Err(APIError::Err(GroupError::UserNotFound));
+    }
+
+    Ok(())
+}
+
+/// Blocks work both ways, so nobody is put into a group with someone
+/// they blocked or were blocked by
+async fn ensure_not_blocked(
+    app_state: &AppState,
+    user_id: UserId,
+    users: &[i32],
+) -> APIResult<(), GroupError> {
+    for &other_id in users {
+        if other_id != user_id.value
+            && relationships::is_blocked(&app_state.db, user_id.value, other_id)
+                .await
+                .map_err(DbErr::into_api_error)?
+        {
+            return Err(APIError::Err(GroupError::Blocked));
+        }
+    }
+
+    Ok(())
+}
+
+async fn load_and_broadcast(app_state: &AppState, id: i32) -> APIResult<Conversation, GroupError> {
+    let conversation = ConversationEntity::find_by_id(id)
+        .one(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?
+        .ok_or(APIError::Err(GroupError::NotFound))?;
+
+    let conversation = to_rpc(&app_state.db, conversation)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+    broadcast_conversation(app_state, &conversation).await;
+
+    Ok(conversation)
+}
+
+impl RPCHandle for GetConversations {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        _req: Empty,
+    ) -> APIResult<Vec<Conversation>, ()> {
+        check_auth!(connection_state);
+
+        let user_id = current_user_id(&connection_state);
+
+        let memberships = ConversationMember::find()
+            .filter(conversation_member::Column::UserId.eq(user_id.value))
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let conversations = ConversationEntity::find()
+            .filter(
+                conversation::Column::Id
+                    .is_in(memberships.into_iter().map(|item| item.conversation_id)),
+            )
+            .order_by_desc(conversation::Column::LastActivityAt)
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let mut result = Vec::with_capacity(conversations.len());
+        for conversation in conversations {
+            result.push(
+                to_rpc(&app_state.db, conversation)
+                    .await
+                    .map_err(DbErr::into_api_error)?,
+            );
+        }
+
+        Ok(result)
+    }
+}
+
+impl RPCHandle for OpenDirectConversation {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        OpenDirectConversationPayload { user_id: other_id }: OpenDirectConversationPayload,
+    ) -> APIResult<Conversation, GroupError> {
+        check_auth!(connection_state);
+
+        let user_id = current_user_id(&connection_state);
+
+        if user_id == other_id {
+            return Err(APIError::Err(GroupError::UserNotFound));
+        }
+
+        ensure_users_exist(&app_state, &[other_id.value]).await?;
+
+        let conversation = open_direct_conversation(&app_state, user_id, other_id)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        to_rpc(&app_state.db, conversation)
+            .await
+            .map_err(DbErr::into_api_error)
+    }
+}
+
+impl RPCHandle for CreateGroup {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        CreateGroupPayload { name, members }: CreateGroupPayload,
+    ) -> APIResult<Conversation, GroupError> {
+        check_auth!(connection_state);
+
+        let user_id = current_user_id(&connection_state);
+        let name = validate_name(&name).map_err(APIError::Err)?;
+
+        let mut unique = HashSet::from([user_id.value]);
+        let mut all_members = vec![user_id.value];
+
+        for member in members {
+            if unique.insert(member.value) {
+                all_members.push(member.value);
+            }
+        }
+
+        if all_members.len() > MAX_GROUP_MEMBERS {
+            return Err(APIError::Err(GroupError::TooManyMembers));
+        }
+
+        ensure_users_exist(&app_state, &all_members).await?;
+        ensure_not_blocked(&app_state, user_id, &all_members).await?;
+
+        let now = Utc::now().naive_utc();
+
+        let txn = app_state.db.begin().await.map_err(DbErr::into_api_error)?;
+
+        let conversation = conversation::ActiveModel {
+            name: Set(Some(name)),
+            owner_id: Set(Some(user_id.value)),
+            is_direct: Set(false),
+            direct_key: Set(None),
+            created_at: Set(now),
+            last_activity_at: Set(now),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+        add_members(&txn, conversation.id, all_members)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        txn.commit().await.map_err(DbErr::into_api_error)?;
+
+        load_and_broadcast(&app_state, conversation.id).await
+    }
+}
+
+impl RPCHandle for RenameGroup {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        RenameGroupPayload { id, name }: RenameGroupPayload,
+    ) -> APIResult<(), GroupError> {
+        check_auth!(connection_state);
+
+        let user_id = current_user_id(&connection_state);
+        let name = validate_name(&name).map_err(APIError::Err)?;
+
+        let conversation = find_owned_group(&app_state, user_id, id).await?;
+
+        let mut conversation: conversation::ActiveModel = conversation.into();
+        conversation.name = Set(Some(name));
+        conversation.last_activity_at = Set(Utc::now().naive_utc());
+
+        let conversation = conversation
+            .update(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        load_and_broadcast(&app_state, conversation.id).await?;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for AddGroupMembers {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        AddGroupMembersPayload { id, members }: AddGroupMembersPayload,
+    ) -> APIResult<(), GroupError> {
+        check_auth!(connection_state);
+
+        let user_id = current_user_id(&connection_state);
+        let conversation = find_owned_group(&app_state, user_id, id).await?;
+
+        let existing = member_ids(&app_state.db, conversation.id)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let mut unique = existing.iter().copied().collect::<HashSet<_>>();
+        let new_members = members
+            .into_iter()
+            .map(|member| member.value)
+            .filter(|member| unique.insert(*member))
+            .collect::<Vec<_>>();
+
+        if existing.len() + new_members.len() > MAX_GROUP_MEMBERS {
+            return Err(APIError::Err(GroupError::TooManyMembers));
+        }
+
+        ensure_users_exist(&app_state, &new_members).await?;
+        ensure_not_blocked(&app_state, user_id, &new_members).await?;
+
+        add_members(&app_state.db, conversation.id, new_members)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        touch(&app_state.db, conversation.id)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        load_and_broadcast(&app_state, conversation.id).await?;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for RemoveGroupMember {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        GroupMemberPayload {
+            id,
+            user_id: target_id,
+        }: GroupMemberPayload,
+    ) -> APIResult<(), GroupError> {
+        check_auth!(connection_state);
+
+        let user_id = current_user_id(&connection_state);
+
+        let conversation = if user_id == target_id {
+            find_group(&app_state, user_id, id).await?
+        } else {
+            find_owned_group(&app_state, user_id, id).await?
+        };
+
+        let result = ConversationMember::delete_many()
+            .filter(conversation_member::Column::ConversationId.eq(conversation.id))
+            .filter(conversation_member::Column::UserId.eq(target_id.value))
+            .exec(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if result.rows_affected == 0 {
+            return Err(APIError::Err(GroupError::NotMember));
+        }
+
+        // The oldest remaining member inherits the group from a leaving owner
+        if conversation.owner_id == Some(target_id.value) {
+            let next_owner = member_ids(&app_state.db, conversation.id)
+                .await
+                .map_err(DbErr::into_api_error)?
+                .first()
+                .copied();
+
+            let mut conversation: conversation::ActiveModel = conversation.clone().into();
+            conversation.owner_id = Set(next_owner);
+
+            conversation
+                .update(&app_state.db)
+                .await
+                .map_err(DbErr::into_api_error)?;
+        }
+
+        touch(&app_state.db, conversation.id)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        notify_removed(&app_state, target_id, id).await;
+        load_and_broadcast(&app_state, conversation.id).await?;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for TransferGroupOwnership {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        GroupMemberPayload {
+            id,
+            user_id: target_id,
+        }: GroupMemberPayload,
+    ) -> APIResult<(), GroupError> {
+        check_auth!(connection_state);
+
+        let user_id = current_user_id(&connection_state);
+        let conversation = find_owned_group(&app_state, user_id, id).await?;
+
+        let is_member = is_member(&app_state.db, conversation.id, target_id)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if !is_member {
+            return Err(APIError::Err(GroupError::NotMember));
+        }
+
+        let mut conversation: conversation::ActiveModel = conversation.into();
+        conversation.owner_id = Set(Some(target_id.value));
+
+        let conversation = conversation
+            .update(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        load_and_broadcast(&app_state, conversation.id).await?;
+
+        Ok(())
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(
+        router,
+        GetConversations,
+        OpenDirectConversation,
+        CreateGroup,
+        RenameGroup,
+        AddGroupMembers,
+        RemoveGroupMember,
+        TransferGroupOwnership,
+    )
+}
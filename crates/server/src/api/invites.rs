@@ -0,0 +1,176 @@
+use chrono::{Duration, Utc};
+use rand::RngCore as _;
+use rpc::{
+    check_auth,
+    models::{
+        audit_log::AuditAction,
+        common::{APIError, APIResult, RPCMethod as _},
+        invites::{CreateInvite, CreateInvitePayload, Invite as InviteInfo, InviteError},
+        markers::TaggedEntity as _,
+        permissions::Permissions,
+    },
+};
+
+use sea_orm::{ConnectionTrait, DbErr, entity::*, query::*, sea_query::Expr};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        audit_log::AuditEntry,
+        common::{DbErrReponseCompat as _, RPCHandle},
+        permissions::require_permission,
+    },
+    entity::invite::{self, Entity as Invite},
+    register_endpoints,
+};
+
+/// Number of random bytes in a code, it's hex encoded
+const CODE_BYTES: usize = 8;
+
+fn invite_info(invite: invite::Model) -> InviteInfo {
+    InviteInfo {
+        id: invite.tagged_id(),
+        code: invite.code,
+        max_uses: invite.max_uses.map(|uses| Ord::max(uses, 0) as u32),
+        uses: Ord::max(invite.uses, 0) as u32,
+        expires_at: invite
+            .expires_at
+            .map(|expires_at| expires_at.and_utc().timestamp()),
+        created_at: invite.created_at.and_utc().timestamp(),
+    }
+}
+
+/// Stores a new invite with a random code. Without a limit, it can be used
+/// any number of times and never expires
+pub async fn create_invite(
+    db: &impl ConnectionTrait,
+    created_by: Option<i32>,
+    max_uses: Option<u32>,
+    expires_in_days: Option<u32>,
+) -> Result<invite::Model, DbErr> {
+    let mut code = [0u8; CODE_BYTES];
+    rand::rng().fill_bytes(&mut code);
+
+    let now = Utc::now();
+
+    invite::ActiveModel {
+        code: Set(hex::encode(code)),
+        created_by: Set(created_by),
+        max_uses: Set(max_uses.map(|uses| Ord::min(uses, i32::MAX as u32) as i32)),
+        uses: Set(0),
+        expires_at: Set(expires_in_days.map(|days| (now + Duration::days(days.into())).naive_utc())),
+        created_at: Set(now.naive_utc()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+/// Uses up the invite if it's still valid. Returns `false` if it doesn't
+/// exist, has expired or has no uses left
+pub async fn redeem_invite(db: &impl ConnectionTrait, code: &str) -> Result<bool, DbErr> {
+    // Checked and counted in one statement, so concurrent registrations
+    // can't use it more times than allowed
+    let result = Invite::update_many()
+        .col_expr(invite::Column::Uses, Expr::col(invite::Column::Uses).add(1))
+        .filter(invite::Column::Code.eq(code))
+        .filter(
+            Condition::any()
+                .add(invite::Column::MaxUses.is_null())
+                .add(Expr::col(invite::Column::Uses).lt(Expr::col(invite::Column::MaxUses))),
+        )
+        .filter(
+            Condition::any()
+                .add(invite::Column::ExpiresAt.is_null())
+                .add(invite::Column::ExpiresAt.gt(Utc::now().naive_utc())),
+        )
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected > 0)
+}
+
+impl RPCHandle for CreateInvite {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        CreateInvitePayload {
+            max_uses,
+            expires_in_days,
+        }: CreateInvitePayload,
+    ) -> APIResult<InviteInfo, InviteError> {
+        check_auth!(connection_state);
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            None,
+            Permissions::CREATE_INVITE,
+        )
+        .await?;
+
+        if max_uses == Some(0) || expires_in_days == Some(0) {
+            return Err(APIError::Err(InviteError::InvalidLimits));
+        }
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let invite = create_invite(
+            &app_state.db,
+            Some(user_id.value),
+            max_uses,
+            expires_in_days,
+        )
+        .await
+        .map_err(DbErr::into_api_error)?;
+        let invite = invite_info(invite);
+
+        // The code itself isn't kept, anyone who can read the log could use it
+        AuditEntry::new(AuditAction::InviteCreate)
+            .target(invite.id)
+            .after(&CreateInvitePayload {
+                max_uses,
+                expires_in_days,
+            })
+            .record(&app_state, &connection_state)
+            .await;
+
+        Ok(invite)
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(router, CreateInvite)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_invites() {
+        let db = crate::migration::tests::sqlite_database().await;
+
+        let limited = create_invite(&db, None, Some(2), None).await.unwrap();
+        assert!(redeem_invite(&db, &limited.code).await.unwrap());
+        assert!(redeem_invite(&db, &limited.code).await.unwrap());
+        assert!(!redeem_invite(&db, &limited.code).await.unwrap());
+
+        let unlimited = create_invite(&db, None, None, Some(1)).await.unwrap();
+        for _ in 0..3 {
+            assert!(redeem_invite(&db, &unlimited.code).await.unwrap());
+        }
+
+        let mut expired: invite::ActiveModel = unlimited.into();
+        expired.expires_at = Set(Some((Utc::now() - Duration::days(1)).naive_utc()));
+        let expired = expired.update(&db).await.unwrap();
+        assert!(!redeem_invite(&db, &expired.code).await.unwrap());
+
+        assert!(!redeem_invite(&db, "missing").await.unwrap());
+    }
+}
@@ -0,0 +1,414 @@
+use std::{path::PathBuf, sync::Arc};
+
+use chrono::Utc;
+use rpc::{
+    check_auth,
+    models::{
+        common::{APIError, APIResult, RPCMethod as _, RPCNotification as _},
+        markers::{Id, MediaId, TaggedEntity as _, UserId},
+        media::{
+            BeginUpload, BeginUploadPayload, CancelUpload, CancelUploadPayload, Download,
+            DownloadPayload, FinishUpload, FinishUploadPayload, MAX_CHUNK_SIZE, Media, MediaChunk,
+            MediaError, UploadChunk, UploadChunkPayload, UploadId,
+        },
+        permissions::Permissions,
+    },
+    server::RpcWriter,
+};
+
+use sea_orm::{
+    DbBackend, DbErr,
+    entity::*,
+    query::*,
+    sea_query::{Expr, SimpleExpr},
+};
+use tokio::io::AsyncReadExt as _;
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        common::{DbErrReponseCompat as _, RPCHandle},
+        messages::{StoredChannel, authorize_channel},
+    },
+    entity::{
+        custom_emoji::{self, Entity as CustomEmoji},
+        media::{self, Entity as MediaEntity},
+        message::{self, Entity as Message},
+        user::{self, Entity as User},
+    },
+    media_storage::PendingUpload,
+    register_endpoints,
+};
+
+const MAX_FILE_NAME_LENGTH: usize = 255;
+
+/// Unfinished uploads a single user can have at once
+const MAX_PENDING_UPLOADS: usize = 4;
+
+const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+pub fn media_from_model(model: media::Model) -> Media {
+    Media {
+        id: model.tagged_id(),
+        file_name: model.file_name,
+        mime_type: model.mime_type,
+        size: model.size as u64,
+        uploaded_by: Id::new(model.uploaded_by),
+        has_thumbnail: model.has_thumbnail,
+    }
+}
+
+fn current_user_id(connection_state: &ConnectionState) -> UserId {
+    connection_state
+        .read()
+        .unwrap()
+        .get_user_id()
+        .expect("We checked auth above")
+}
+
+/// Upload started by the current user
+fn find_upload(
+    app_state: &AppState,
+    connection_state: &ConnectionState,
+    id: UploadId,
+) -> APIResult<Arc<PendingUpload>, MediaError> {
+    app_state
+        .media
+        .get(id, current_user_id(connection_state))
+        .ok_or(APIError::Err(MediaError::UploadNotFound))
+}
+
+/// Bytes already stored or being uploaded right now,
+/// only by the user if it's set
+async fn used_storage(app_state: &AppState, user_id: Option<UserId>) -> Result<u64, DbErr> {
+    let stored = MediaEntity::find()
+        .select_only()
+        .column_as(media::Column::Size.sum(), "total")
+        .apply_if(user_id, |query, user_id| {
+            query.filter(media::Column::UploadedBy.eq(user_id.value))
+        })
+        .into_tuple::<Option<i64>>()
+        .one(&app_state.db)
+        .await?
+        .flatten()
+        .unwrap_or(0);
+
+    Ok(stored as u64 + app_state.media.pending_size(user_id))
+}
+
+/// Media can be downloaded by its uploader, by everyone if it's an avatar
+/// or a custom emoji, and otherwise only by users who can see a message
+/// it's attached to
+async fn can_download(
+    app_state: &AppState,
+    connection_state: &ConnectionState,
+    model: &media::Model,
+) -> APIResult<bool, MediaError> {
+    if model.uploaded_by == current_user_id(connection_state).value {
+        return Ok(true);
+    }
+
+    let is_avatar = User::find()
+        .filter(user::Column::AvatarId.eq(model.id))
+        .exists(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+    let is_emoji = CustomEmoji::find()
+        .filter(custom_emoji::Column::MediaId.eq(model.id))
+        .exists(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+    if is_avatar || is_emoji {
+        return Ok(true);
+    }
+
+    // Attachments are always sent by their uploader
+    let channels = Message::find()
+        .select_only()
+        .columns([message::Column::ChannelId, message::Column::InGroup])
+        .distinct()
+        .filter(message::Column::SentBy.eq(model.uploaded_by))
+        .filter(message::Column::DeletedAt.is_null())
+        .filter(attachment_condition(app_state, model.id))
+        .into_tuple::<(i32, bool)>()
+        .all(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+    for (channel_id, in_group) in channels {
+        let channel = StoredChannel::from_columns(channel_id, in_group).channel();
+
+        match authorize_channel(
+            app_state,
+            connection_state,
+            channel,
+            Permissions::VIEW_CHANNEL,
+        )
+        .await
+        {
+            Ok(_) => return Ok(true),
+            Err(APIError::Err(_) | APIError::Forbidden) => {}
+            Err(_) => return Err(APIError::ServerError),
+        }
+    }
+
+    Ok(false)
+}
+
+/// Matches messages with the media in `attached_media`
+fn attachment_condition(app_state: &AppState, media_id: i32) -> SimpleExpr {
+    match app_state.db.get_database_backend() {
+        DbBackend::Postgres => Expr::cust_with_values(
+            "(message.content->'attached_media')::jsonb @> ?::jsonb",
+            [serde_json::json!([{ "value": media_id }]).to_string()],
+        ),
+        _ => Expr::cust_with_values(
+            "EXISTS (SELECT 1 FROM json_each(message.content, '$.attached_media') \
+             WHERE json_extract(json_each.value, '$.value') = ?)",
+            [media_id],
+        ),
+    }
+}
+
+impl RPCHandle for BeginUpload {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        BeginUploadPayload {
+            file_name,
+            mime_type,
+            size,
+        }: BeginUploadPayload,
+    ) -> APIResult<UploadId, MediaError> {
+        check_auth!(connection_state);
+
+        let user_id = current_user_id(&connection_state);
+
+        let file_name = file_name.trim();
+        if file_name.is_empty() || file_name.chars().count() > MAX_FILE_NAME_LENGTH {
+            return Err(APIError::Err(MediaError::InvalidFileName));
+        }
+
+        let mime_type = match mime_type.trim() {
+            "" => DEFAULT_MIME_TYPE.to_owned(),
+            mime_type => mime_type.to_owned(),
+        };
+
+        let config = &app_state.config.media;
+        if size > config.max_file_size {
+            return Err(APIError::Err(MediaError::FileTooLarge));
+        }
+
+        app_state.media.remove_expired().await;
+
+        if app_state.media.user_uploads(user_id).len() >= MAX_PENDING_UPLOADS {
+            return Err(APIError::Err(MediaError::TooManyUploads));
+        }
+
+        // The upload has to be registered before anybody else checks the quota
+        let _quota = app_state.media.lock_quota().await;
+
+        let used_by_user = used_storage(&app_state, Some(user_id))
+            .await
+            .map_err(DbErr::into_api_error)?;
+        let used = used_storage(&app_state, None)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if used_by_user + size > config.user_quota || used + size > config.server_quota {
+            return Err(APIError::Err(MediaError::QuotaExceeded));
+        }
+
+        app_state
+            .media
+            .begin(user_id, file_name.to_owned(), mime_type, size)
+            .await
+            .map_err(std::io::Error::into_api_error)
+    }
+}
+
+impl RPCHandle for UploadChunk {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        UploadChunkPayload {
+            upload,
+            offset,
+            data,
+        }: UploadChunkPayload,
+    ) -> APIResult<(), MediaError> {
+        check_auth!(connection_state);
+
+        if data.len() > MAX_CHUNK_SIZE {
+            return Err(APIError::Err(MediaError::ChunkTooLarge));
+        }
+
+        let upload = find_upload(&app_state, &connection_state, upload)?;
+        let mut state = upload.state.lock().await;
+
+        if offset != state.received {
+            return Err(APIError::Err(MediaError::UnexpectedOffset));
+        }
+
+        if state.received + data.len() as u64 > upload.size {
+            return Err(APIError::Err(MediaError::SizeMismatch));
+        }
+
+        state
+            .append(&data)
+            .await
+            .map_err(std::io::Error::into_api_error)
+    }
+}
+
+impl RPCHandle for FinishUpload {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        FinishUploadPayload { upload: id }: FinishUploadPayload,
+    ) -> APIResult<Media, MediaError> {
+        check_auth!(connection_state);
+
+        let upload = find_upload(&app_state, &connection_state, id)?;
+
+        if upload.state.lock().await.received != upload.size {
+            return Err(APIError::Err(MediaError::SizeMismatch));
+        }
+
+        // Size of the upload is pending until the row is inserted
+        let _quota = app_state.media.lock_quota().await;
+
+        let stored = app_state
+            .media
+            .finish(id)
+            .await
+            .map_err(std::io::Error::into_api_error)?
+            .ok_or(APIError::Err(MediaError::UploadNotFound))?;
+
+        let model = media::ActiveModel {
+            file_name: Set(upload.file_name.clone()),
+            mime_type: Set(upload.mime_type.clone()),
+            size: Set(upload.size as i64),
+            sha256: Set(stored.sha256),
+            uploaded_by: Set(upload.user_id.value),
+            uploaded_at: Set(Utc::now().naive_utc()),
+            has_thumbnail: Set(stored.has_thumbnail),
+            ..Default::default()
+        }
+        .insert(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+        Ok(media_from_model(model))
+    }
+}
+
+impl RPCHandle for CancelUpload {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        CancelUploadPayload { upload }: CancelUploadPayload,
+    ) -> APIResult<(), MediaError> {
+        check_auth!(connection_state);
+
+        find_upload(&app_state, &connection_state, upload)?;
+
+        app_state
+            .media
+            .cancel(upload)
+            .await
+            .map_err(std::io::Error::into_api_error)
+    }
+}
+
+/// Sends the file to the client piece by piece
+async fn send_chunks(
+    writer: RpcWriter,
+    path: PathBuf,
+    id: MediaId,
+    thumbnail: bool,
+) -> std::io::Result<()> {
+    let mut file = tokio::fs::File::open(&path).await?;
+    let total_size = file.metadata().await?.len();
+
+    let mut offset = 0;
+    loop {
+        let mut data = Vec::with_capacity(MAX_CHUNK_SIZE);
+        (&mut file)
+            .take(MAX_CHUNK_SIZE as u64)
+            .read_to_end(&mut data)
+            .await?;
+
+        let len = data.len() as u64;
+
+        MediaChunk {
+            id,
+            thumbnail,
+            offset,
+            total_size,
+            data,
+        }
+        .notify(&writer)
+        .await;
+
+        offset += len;
+
+        // Empty files still get a single chunk
+        if len == 0 || offset >= total_size {
+            return Ok(());
+        }
+    }
+}
+
+impl RPCHandle for Download {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        DownloadPayload { id, thumbnail }: DownloadPayload,
+    ) -> APIResult<Media, MediaError> {
+        check_auth!(connection_state);
+
+        let model = MediaEntity::find_by_id(id.value)
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::Err(MediaError::MediaNotFound))?;
+
+        if !can_download(&app_state, &connection_state, &model).await? {
+            return Err(APIError::Err(MediaError::MediaNotFound));
+        }
+
+        if thumbnail && !model.has_thumbnail {
+            return Err(APIError::Err(MediaError::ThumbnailNotFound));
+        }
+
+        let path = if thumbnail {
+            app_state.media.thumbnail_path(&model.sha256)
+        } else {
+            app_state.media.blob_path(&model.sha256)
+        };
+
+        let writer = connection_state.read().unwrap().writer.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = send_chunks(writer, path, id, thumbnail).await {
+                log::error!("Failed to send media (ID {}): {err}", id.value);
+            }
+        });
+
+        Ok(media_from_model(model))
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(
+        router,
+        BeginUpload,
+        UploadChunk,
+        FinishUpload,
+        CancelUpload,
+        Download,
+    )
+}
@@ -0,0 +1,441 @@
+use std::collections::{HashMap, HashSet, hash_map::Entry};
+
+use rpc::{
+    check_auth,
+    models::{
+        common::{APIError, APIResult, RPCMethod as _, RPCNotification as _},
+        markers::{ChannelId, Id, UserId},
+        mentions::{
+            GetMentions, GetMentionsPayload, HERE_MENTION, MENTION_PREFIX, Mention, MentionKind,
+            Mentioned, MentionsPage,
+        },
+        messages::Message,
+        permissions::Permissions,
+    },
+};
+
+use sea_orm::{ConnectionTrait, DbErr, TransactionTrait as _, entity::*, query::*};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        common::{DbErrReponseCompat as _, RPCHandle},
+        groups,
+        messages::{StoredChannel, message_from_model, visible_channels},
+        permissions::{PermissionScope, PermissionSnapshot, user_permissions},
+        reactions, relationships,
+    },
+    entity::{
+        mention::{self, Entity as MentionEntity},
+        message::{self, Entity as MessageEntity},
+        read_state::{self, Entity as ReadState},
+        role::{self, Entity as RoleEntity},
+        user::{self, Entity as User},
+        user_role::{self, Entity as UserRole},
+    },
+    register_endpoints,
+};
+
+const DEFAULT_PAGE_SIZE: u32 = 25;
+const MAX_PAGE_SIZE: u32 = 50;
+
+/// Mentions loaded at once while looking for unread ones
+const SCAN_BATCH_SIZE: u64 = 200;
+
+/// Names following the mention prefix, without trailing punctuation
+fn mention_tokens(text: &str) -> HashSet<&str> {
+    text.split_whitespace()
+        .filter_map(|word| word.strip_prefix(MENTION_PREFIX))
+        .map(|name| name.trim_end_matches(|char: char| char.is_ascii_punctuation() && char != '_'))
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn kind_from_model(model: &mention::Model) -> MentionKind {
+    match model.role_id {
+        Some(id) => MentionKind::Role(Id::new(id)),
+        None if model.is_here => MentionKind::Here,
+        None => MentionKind::User,
+    }
+}
+
+/// Lower is more specific
+fn kind_rank(kind: MentionKind) -> u8 {
+    match kind {
+        MentionKind::User => 0,
+        MentionKind::Role(_) => 1,
+        MentionKind::Here => 2,
+    }
+}
+
+fn add_target(targets: &mut HashMap<i32, MentionKind>, user_id: i32, kind: MentionKind) {
+    match targets.entry(user_id) {
+        Entry::Occupied(mut entry) => {
+            if kind_rank(kind) < kind_rank(*entry.get()) {
+                entry.insert(kind);
+            }
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(kind);
+        }
+    }
+}
+
+/// Resolves mentions in the text into users who can see the channel.
+/// The author is never mentioned by their own message
+async fn resolve_mentions(
+    app_state: &AppState,
+    author: &user::Model,
+    channel: StoredChannel,
+    text: &str,
+) -> Result<HashMap<i32, MentionKind>, DbErr> {
+    let tokens = mention_tokens(text);
+    let mut targets = HashMap::new();
+
+    if tokens.is_empty() {
+        return Ok(targets);
+    }
+
+    let users = User::find()
+        .filter(user::Column::Username.is_in(tokens.iter().copied()))
+        .all(&app_state.db)
+        .await?;
+
+    for user in users {
+        add_target(&mut targets, user.id, MentionKind::User);
+    }
+
+    // Conversations are small enough to let every member ping the others
+    let can_mention_roles = match channel {
+        StoredChannel::Text(id) => user_permissions(app_state, author, Some(ChannelId::Text(id)))
+            .await?
+            .contains(Permissions::MENTION_ROLES),
+        StoredChannel::Group(_) => true,
+    };
+
+    if can_mention_roles && tokens.contains(HERE_MENTION) {
+        let online = app_state
+            .connected_clients
+            .iter()
+            .map(|entry| entry.key().value)
+            .collect::<Vec<_>>();
+
+        for user_id in online {
+            add_target(&mut targets, user_id, MentionKind::Here);
+        }
+    }
+
+    // The default role is everyone, mentioning it would ping offline users as well
+    if can_mention_roles && matches!(channel, StoredChannel::Text(_)) {
+        let roles = RoleEntity::find()
+            .filter(role::Column::Name.is_in(tokens.iter().copied()))
+            .filter(role::Column::IsDefault.eq(false))
+            .all(&app_state.db)
+            .await?;
+
+        let assigned = UserRole::find()
+            .filter(user_role::Column::RoleId.is_in(roles.iter().map(|role| role.id)))
+            .all(&app_state.db)
+            .await?;
+
+        for item in assigned {
+            add_target(
+                &mut targets,
+                item.user_id,
+                MentionKind::Role(Id::new(item.role_id)),
+            );
+        }
+    }
+
+    targets.remove(&author.id);
+
+    // Users who blocked the author aren't pinged by them
+    for user_id in relationships::blocked_by(&app_state.db, author.id).await? {
+        targets.remove(&user_id);
+    }
+
+    let visible: HashSet<i32> = match channel {
+        StoredChannel::Text(id) => {
+            let user_ids = targets.keys().copied().collect::<Vec<_>>();
+
+            let channel = ChannelId::Text(id);
+            let snapshot = PermissionSnapshot::load(
+                &app_state.db,
+                &user_ids,
+                PermissionScope::Channel(channel),
+            )
+            .await?;
+
+            user_ids
+                .into_iter()
+                .filter(|user_id| {
+                    snapshot
+                        .permissions(*user_id, Some(channel))
+                        .contains(Permissions::VIEW_CHANNEL)
+                })
+                .collect()
+        }
+        StoredChannel::Group(id) => groups::member_ids(&app_state.db, id.value)
+            .await?
+            .into_iter()
+            .collect(),
+    };
+
+    targets.retain(|user_id, _| visible.contains(user_id));
+
+    Ok(targets)
+}
+
+/// Replaces stored mentions of a new or edited message and notifies
+/// users who weren't mentioned by it before
+pub async fn update_mentions(
+    app_state: &AppState,
+    channel: StoredChannel,
+    message: &Message,
+) -> Result<(), DbErr> {
+    let Some(author) = User::find_by_id(message.author.value)
+        .one(&app_state.db)
+        .await?
+    else {
+        return Ok(());
+    };
+
+    let targets = resolve_mentions(app_state, &author, channel, &message.content.content).await?;
+
+    let previous = MentionEntity::find()
+        .filter(mention::Column::MessageId.eq(message.id.value))
+        .all(&app_state.db)
+        .await?
+        .into_iter()
+        .map(|item| item.user_id)
+        .collect::<HashSet<_>>();
+
+    if targets.is_empty() && previous.is_empty() {
+        return Ok(());
+    }
+
+    let txn = app_state.db.begin().await?;
+
+    MentionEntity::delete_many()
+        .filter(mention::Column::MessageId.eq(message.id.value))
+        .exec(&txn)
+        .await?;
+
+    if !targets.is_empty() {
+        MentionEntity::insert_many(targets.iter().map(|(user_id, kind)| mention::ActiveModel {
+            message_id: Set(message.id.value),
+            user_id: Set(*user_id),
+            role_id: Set(match kind {
+                MentionKind::Role(id) => Some(id.value),
+                _ => None,
+            }),
+            is_here: Set(*kind == MentionKind::Here),
+            ..Default::default()
+        }))
+        .exec(&txn)
+        .await?;
+    }
+
+    txn.commit().await?;
+
+    for (user_id, kind) in targets {
+        if previous.contains(&user_id) {
+            continue;
+        }
+
+        let notification = Mentioned {
+            mention: Mention {
+                message: Message {
+                    mentions_me: true,
+                    ..message.clone()
+                },
+                kind,
+            },
+        };
+
+        for writer in app_state.user_writers(Id::new(user_id)) {
+            notification.clone().notify(&writer).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// IDs of the messages that mention the user
+pub async fn mentioned_messages(
+    db: &impl ConnectionTrait,
+    message_ids: &[i32],
+    user_id: UserId,
+) -> Result<HashSet<i32>, DbErr> {
+    let mentions = MentionEntity::find()
+        .filter(mention::Column::MessageId.is_in(message_ids.iter().copied()))
+        .filter(mention::Column::UserId.eq(user_id.value))
+        .all(db)
+        .await?;
+
+    Ok(mentions.into_iter().map(|item| item.message_id).collect())
+}
+
+/// Query for mentions of the user in the given messages
+pub fn user_mentions(user_id: i32, messages: Select<MessageEntity>) -> Select<MentionEntity> {
+    MentionEntity::find()
+        .filter(mention::Column::UserId.eq(user_id))
+        .filter(
+            mention::Column::MessageId.in_subquery(
+                messages
+                    .select_only()
+                    .column(message::Column::Id)
+                    .into_query(),
+            ),
+        )
+}
+
+impl RPCHandle for GetMentions {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        GetMentionsPayload { before, limit }: GetMentionsPayload,
+    ) -> APIResult<MentionsPage, ()> {
+        check_auth!(connection_state);
+
+        let user = connection_state
+            .read()
+            .unwrap()
+            .user
+            .clone()
+            .expect("We checked auth above");
+
+        let last_read = ReadState::find()
+            .filter(read_state::Column::UserId.eq(user.id))
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .into_iter()
+            .filter_map(|state| {
+                Some((
+                    (state.channel_id, state.in_group),
+                    state.last_read_message_id?,
+                ))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let channels = visible_channels(&app_state, &user)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .into_iter()
+            .map(StoredChannel::columns)
+            .collect::<HashSet<_>>();
+
+        let limit = match limit {
+            0 => DEFAULT_PAGE_SIZE,
+            limit => Ord::min(limit, MAX_PAGE_SIZE),
+        };
+
+        // Mentions of messages after the ack of their channel are picked in
+        // memory, a condition per channel wouldn't fit the limit of bound
+        // variables. One more mention tells whether there are more of them
+        let mut mentions = vec![];
+        let mut cursor = before.map(|before| before.value);
+
+        'scan: loop {
+            let batch = MentionEntity::find()
+                .filter(mention::Column::UserId.eq(user.id))
+                .apply_if(cursor, |query, cursor| {
+                    query.filter(mention::Column::MessageId.lt(cursor))
+                })
+                .order_by_desc(mention::Column::MessageId)
+                .limit(SCAN_BATCH_SIZE)
+                .all(&app_state.db)
+                .await
+                .map_err(DbErr::into_api_error)?;
+
+            let Some(last) = batch.last() else {
+                break;
+            };
+            cursor = Some(last.message_id);
+
+            let message_channels = MessageEntity::find()
+                .select_only()
+                .columns([
+                    message::Column::Id,
+                    message::Column::ChannelId,
+                    message::Column::InGroup,
+                ])
+                .filter(message::Column::Id.is_in(batch.iter().map(|item| item.message_id)))
+                .filter(message::Column::DeletedAt.is_null())
+                .into_tuple::<(i32, i32, bool)>()
+                .all(&app_state.db)
+                .await
+                .map_err(DbErr::into_api_error)?
+                .into_iter()
+                .map(|(id, channel_id, in_group)| (id, (channel_id, in_group)))
+                .collect::<HashMap<_, _>>();
+
+            let is_last_batch = batch.len() < SCAN_BATCH_SIZE as usize;
+
+            for item in batch {
+                let Some(channel) = message_channels.get(&item.message_id) else {
+                    continue;
+                };
+
+                let is_unread = last_read
+                    .get(channel)
+                    .is_none_or(|last_read| item.message_id > *last_read);
+
+                if channels.contains(channel) && is_unread {
+                    mentions.push(item);
+
+                    if mentions.len() > limit as usize {
+                        break 'scan;
+                    }
+                }
+            }
+
+            if is_last_batch {
+                break;
+            }
+        }
+
+        let has_more = mentions.len() > limit as usize;
+        mentions.truncate(limit as usize);
+
+        let ids = mentions
+            .iter()
+            .map(|item| item.message_id)
+            .collect::<Vec<_>>();
+
+        let mut messages = MessageEntity::find()
+            .filter(message::Column::Id.is_in(ids.iter().copied()))
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .into_iter()
+            .filter_map(message_from_model)
+            .map(|message| (message.id.value, message))
+            .collect::<HashMap<_, _>>();
+
+        let mut reactions = reactions::message_reactions(&app_state.db, &ids, Id::new(user.id))
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let mentions = mentions
+            .iter()
+            .filter_map(|item| {
+                let mut message = messages.remove(&item.message_id)?;
+                message.reactions = reactions.remove(&item.message_id).unwrap_or_default();
+                message.mentions_me = true;
+
+                Some(Mention {
+                    message,
+                    kind: kind_from_model(item),
+                })
+            })
+            .collect();
+
+        Ok(MentionsPage { mentions, has_more })
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(router, GetMentions)
+}
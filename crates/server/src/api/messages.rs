@@ -1,18 +1,733 @@
-use rpc::{models::messages::SendMessagePayload, server::RpcRouter};
+use std::collections::HashSet;
 
-use crate::{AppState, ConnectionState};
+use chrono::Utc;
+use rpc::{
+    check_auth,
+    models::{
+        common::{APIError, APIResult, RPCMethod as _, RPCNotification as _},
+        markers::{ChannelId, GroupId, Id, MsgId, TaggedEntity as _, TextChannelId, UserId},
+        messages::{
+            DeleteMessage, DeleteMessagePayload, EditMessage, EditMessagePayload,
+            GetMessageRevisions, GetMessageRevisionsPayload, GetMessages, GetMessagesPayload,
+            Message, MessageContent, MessageCreated, MessageCursor, MessageDeleted, MessageError,
+            MessageRevision, MessageUpdated, MessagesPage, SendMessage, SendMessagePayload,
+            TextMessageChannel,
+        },
+        permissions::Permissions,
+    },
+    server::RpcWriter,
+};
 
-async fn send_message(
-    state: AppState,
-    conn_state: ConnectionState,
-    SendMessagePayload {
+use sea_orm::{DbErr, TransactionTrait as _, entity::*, query::*};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        common::{DbErrReponseCompat as _, RPCHandle},
+        groups, mentions,
+        permissions::{self, PermissionScope, PermissionSnapshot, require_permission},
+        reactions, relationships,
+    },
+    entity::{
+        conversation_member::{self, Entity as ConversationMember},
+        media::{self, Entity as MediaEntity},
+        message::{self, Entity as MessageEntity},
+        message_revision::{self, Entity as MessageRevisionEntity},
+        text_channel::Entity as TextChannel,
+        user::{self, Entity as User},
+    },
+    register_endpoints,
+};
+
+/// Maximum length of a message in characters
+const MAX_MESSAGE_LENGTH: usize = 4000;
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// Converts a stored message into its RPC representation
+pub fn message_from_model(model: message::Model) -> Option<Message> {
+    let id = model.tagged_id();
+    let channel = StoredChannel::from_model(&model).channel();
+    let content = match serde_json::from_value::<MessageContent>(model.content) {
+        Ok(content) => content,
+        Err(err) => {
+            log::error!("Message (ID {}) has malformed content: {err}", model.id);
+
+            return None;
+        }
+    };
+
+    Some(Message {
+        id,
+        channel,
+        author: Id::new(model.sent_by),
         content,
-        destination,
-    }: SendMessagePayload,
-) -> Result<(), String> {
+        sent_at: model.sent_at.and_utc().timestamp(),
+        edited_at: model
+            .edited_at
+            .map(|edited_at| edited_at.and_utc().timestamp()),
+        reactions: vec![],
+        mentions_me: false,
+    })
+}
+
+/// Channel as it's stored in the `message` table.
+/// Direct channels are resolved into their conversations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoredChannel {
+    Text(TextChannelId),
+    Group(GroupId),
+}
+
+impl StoredChannel {
+    pub fn from_model(model: &message::Model) -> Self {
+        Self::from_columns(model.channel_id, model.in_group)
+    }
+
+    pub fn from_columns(channel_id: i32, in_group: bool) -> Self {
+        if in_group {
+            Self::Group(Id::new(channel_id))
+        } else {
+            Self::Text(Id::new(channel_id))
+        }
+    }
+
+    /// Representation sent to clients
+    pub fn channel(self) -> TextMessageChannel {
+        match self {
+            Self::Text(id) => TextMessageChannel::TextChannel(id),
+            Self::Group(id) => TextMessageChannel::GroupChannel(id),
+        }
+    }
+
+    pub fn columns(self) -> (i32, bool) {
+        match self {
+            Self::Text(id) => (id.value, false),
+            Self::Group(id) => (id.value, true),
+        }
+    }
+
+    pub fn condition(self) -> Condition {
+        let (channel_id, in_group) = self.columns();
+
+        Condition::all()
+            .add(message::Column::ChannelId.eq(channel_id))
+            .add(message::Column::InGroup.eq(in_group))
+    }
+}
+
+/// Checks that the channel exists and the current user can access it.
+/// The permission only applies to text channels, conversations are
+/// available to all of their members
+pub async fn authorize_channel(
+    app_state: &AppState,
+    connection_state: &ConnectionState,
+    channel: TextMessageChannel,
+    permission: Permissions,
+) -> APIResult<StoredChannel, MessageError> {
+    let user_id = connection_state
+        .read()
+        .unwrap()
+        .get_user_id()
+        .ok_or(APIError::Unauthorized)?;
+
+    match channel {
+        TextMessageChannel::TextChannel(id) => {
+            let exists = TextChannel::find_by_id(id.value)
+                .exists(&app_state.db)
+                .await
+                .map_err(DbErr::into_api_error)?;
+
+            if !exists {
+                return Err(APIError::Err(MessageError::ChannelNotFound));
+            }
+
+            require_permission(
+                app_state,
+                connection_state,
+                Some(ChannelId::Text(id)),
+                permission,
+            )
+            .await?;
+
+            Ok(StoredChannel::Text(id))
+        }
+        TextMessageChannel::Direct(other_id) => {
+            // Only `SendMessage` and `OpenDirectConversation` create the conversation
+            let conversation = groups::find_direct_conversation(&app_state.db, user_id, other_id)
+                .await
+                .map_err(DbErr::into_api_error)?
+                .ok_or(APIError::Err(MessageError::ChannelNotFound))?;
+
+            Ok(StoredChannel::Group(conversation.tagged_id()))
+        }
+        TextMessageChannel::GroupChannel(id) => {
+            let is_member = groups::is_member(&app_state.db, id.value, user_id)
+                .await
+                .map_err(DbErr::into_api_error)?;
+
+            if !is_member {
+                return Err(APIError::Err(MessageError::ChannelNotFound));
+            }
+
+            Ok(StoredChannel::Group(id))
+        }
+    }
+}
+
+/// The first message to a user opens the direct conversation with them
+async fn open_direct_channel(
+    app_state: &AppState,
+    connection_state: &ConnectionState,
+    channel: TextMessageChannel,
+) -> APIResult<TextMessageChannel, MessageError> {
+    let TextMessageChannel::Direct(other_id) = channel else {
+        return Ok(channel);
+    };
+
+    let user_id = connection_state
+        .read()
+        .unwrap()
+        .get_user_id()
+        .ok_or(APIError::Unauthorized)?;
+
+    let exists = User::find_by_id(other_id.value)
+        .exists(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+    if !exists || other_id == user_id {
+        return Err(APIError::Err(MessageError::ChannelNotFound));
+    }
+
+    let conversation = groups::open_direct_conversation(app_state, user_id, other_id)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+    Ok(TextMessageChannel::GroupChannel(conversation.tagged_id()))
+}
+
+/// Writers of all connections whose users can see the channel
+pub async fn channel_viewers(
+    app_state: &AppState,
+    channel: StoredChannel,
+) -> Result<Vec<RpcWriter>, DbErr> {
+    match channel {
+        StoredChannel::Text(id) => {
+            permissions::channel_viewers(app_state, ChannelId::Text(id)).await
+        }
+        StoredChannel::Group(id) => {
+            let members = groups::member_ids(&app_state.db, id.value).await?;
+
+            Ok(members
+                .into_iter()
+                .flat_map(|member| app_state.user_writers(Id::new(member)))
+                .collect())
+        }
+    }
+}
+
+/// Text channels the user can see and conversations the user is a member of
+pub async fn visible_channels(
+    app_state: &AppState,
+    user: &user::Model,
+) -> Result<Vec<StoredChannel>, DbErr> {
+    let snapshot =
+        PermissionSnapshot::load(&app_state.db, &[user.id], PermissionScope::AllChannels).await?;
+
+    let mut channels = TextChannel::find()
+        .all(&app_state.db)
+        .await?
+        .into_iter()
+        .map(|channel| channel.tagged_id())
+        .filter(|id| snapshot.can_view(user.id, ChannelId::Text(*id)))
+        .map(StoredChannel::Text)
+        .collect::<Vec<_>>();
+
+    let memberships = ConversationMember::find()
+        .filter(conversation_member::Column::UserId.eq(user.id))
+        .all(&app_state.db)
+        .await?;
+
+    channels.extend(
+        memberships
+            .into_iter()
+            .map(|item| StoredChannel::Group(Id::new(item.conversation_id))),
+    );
+
+    Ok(channels)
+}
+
+fn validate_text(content: &MessageContent) -> Result<(), MessageError> {
+    if content.content.trim().is_empty() && content.attached_media.is_empty() {
+        return Err(MessageError::EmptyMessage);
+    }
+
+    if content.content.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err(MessageError::TooLong);
+    }
+
+    Ok(())
+}
+
+/// Replies have to point to the same channel,
+/// attachments have to be uploaded by the author
+async fn validate_content(
+    app_state: &AppState,
+    user_id: UserId,
+    channel: StoredChannel,
+    content: &MessageContent,
+) -> APIResult<(), MessageError> {
+    validate_text(content).map_err(APIError::Err)?;
+
+    if !content.attached_media.is_empty() {
+        let media = content
+            .attached_media
+            .iter()
+            .map(|id| id.value)
+            .collect::<HashSet<_>>();
+
+        let found = MediaEntity::find()
+            .filter(media::Column::Id.is_in(media.iter().copied()))
+            .filter(media::Column::UploadedBy.eq(user_id.value))
+            .count(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if found != media.len() as u64 {
+            return Err(APIError::Err(MessageError::MediaNotFound));
+        }
+    }
+
+    if let Some(reply) = &content.reply {
+        if reply.reply_text.chars().count() > MAX_MESSAGE_LENGTH {
+            return Err(APIError::Err(MessageError::TooLong));
+        }
+
+        let exists = MessageEntity::find_by_id(reply.reply_to.value)
+            .filter(channel.condition())
+            .filter(message::Column::DeletedAt.is_null())
+            .exists(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if !exists {
+            return Err(APIError::Err(MessageError::ReplyNotFound));
+        }
+    }
+
     Ok(())
 }
 
-pub fn merge(router: RpcRouter<AppState, ConnectionState>) -> RpcRouter<AppState, ConnectionState> {
-    router.register("SendMessage", send_message)
+impl RPCHandle for SendMessage {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        SendMessagePayload {
+            content,
+            destination,
+        }: SendMessagePayload,
+    ) -> APIResult<Message, MessageError> {
+        check_auth!(connection_state);
+
+        let destination = open_direct_channel(&app_state, &connection_state, destination).await?;
+        let channel = authorize_channel(
+            &app_state,
+            &connection_state,
+            destination,
+            Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES,
+        )
+        .await?;
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        // Blocks work both ways in direct conversations
+        if let StoredChannel::Group(id) = channel
+            && let Some(peer) = groups::direct_peer(&app_state.db, id.value, user_id)
+                .await
+                .map_err(DbErr::into_api_error)?
+            && relationships::is_blocked(&app_state.db, user_id.value, peer)
+                .await
+                .map_err(DbErr::into_api_error)?
+        {
+            return Err(APIError::Err(MessageError::Blocked));
+        }
+
+        validate_content(&app_state, user_id, channel, &content).await?;
+
+        let serialized = serde_json::to_value(&content).map_err(|err| {
+            log::error!("Failed to serialize message content: {err}");
+
+            APIError::ServerError
+        })?;
+
+        let (channel_id, in_group) = channel.columns();
+
+        let model = message::ActiveModel {
+            content: Set(serialized),
+            channel_id: Set(channel_id),
+            in_group: Set(in_group),
+            sent_by: Set(user_id.value),
+            sent_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+        app_state.channels.stop_typing(channel.channel(), user_id);
+
+        if let StoredChannel::Group(id) = channel {
+            groups::touch(&app_state.db, id.value)
+                .await
+                .map_err(DbErr::into_api_error)?;
+        }
+
+        let message = Message {
+            id: model.tagged_id(),
+            channel: channel.channel(),
+            author: user_id,
+            content,
+            sent_at: model.sent_at.and_utc().timestamp(),
+            edited_at: None,
+            reactions: vec![],
+            mentions_me: false,
+        };
+
+        let viewers = channel_viewers(&app_state, channel)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        for writer in viewers {
+            MessageCreated {
+                message: message.clone(),
+            }
+            .notify(&writer)
+            .await;
+        }
+
+        // The message is already stored and delivered, failing here would
+        // make the client retry it
+        if let Err(err) = mentions::update_mentions(&app_state, channel, &message).await {
+            log::error!(
+                "Failed to update mentions of message (ID {}): {err}",
+                message.id.value
+            );
+        }
+
+        Ok(message)
+    }
+}
+
+impl RPCHandle for GetMessages {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        GetMessagesPayload {
+            channel,
+            cursor,
+            limit,
+        }: GetMessagesPayload,
+    ) -> APIResult<MessagesPage, MessageError> {
+        check_auth!(connection_state);
+
+        let channel = authorize_channel(
+            &app_state,
+            &connection_state,
+            channel,
+            Permissions::VIEW_CHANNEL,
+        )
+        .await?;
+
+        let limit = match limit {
+            0 => DEFAULT_PAGE_SIZE,
+            limit => Ord::min(limit, MAX_PAGE_SIZE),
+        };
+
+        let query = MessageEntity::find()
+            .filter(channel.condition())
+            .filter(message::Column::DeletedAt.is_null());
+
+        // One extra row tells whether there's anything past the page
+        let query = match cursor {
+            Some(MessageCursor::Before(id)) => query
+                .filter(message::Column::Id.lt(id.value))
+                .order_by_desc(message::Column::Id),
+            Some(MessageCursor::After(id)) => query
+                .filter(message::Column::Id.gt(id.value))
+                .order_by_asc(message::Column::Id),
+            None => query.order_by_desc(message::Column::Id),
+        };
+
+        let mut models = query
+            .limit(limit as u64 + 1)
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let has_more = models.len() > limit as usize;
+        models.truncate(limit as usize);
+
+        if !matches!(cursor, Some(MessageCursor::After(_))) {
+            models.reverse();
+        }
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let ids = models.iter().map(|model| model.id).collect::<Vec<_>>();
+        let mut reactions = reactions::message_reactions(&app_state.db, &ids, user_id)
+            .await
+            .map_err(DbErr::into_api_error)?;
+        let mentioned = mentions::mentioned_messages(&app_state.db, &ids, user_id)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let mut messages = models
+            .into_iter()
+            .filter_map(message_from_model)
+            .collect::<Vec<_>>();
+
+        for message in messages.iter_mut() {
+            message.reactions = reactions.remove(&message.id.value).unwrap_or_default();
+            message.mentions_me = mentioned.contains(&message.id.value);
+        }
+
+        Ok(MessagesPage { messages, has_more })
+    }
+}
+
+/// Looks up a message that wasn't deleted
+pub async fn find_message(
+    app_state: &AppState,
+    id: MsgId,
+) -> APIResult<message::Model, MessageError> {
+    MessageEntity::find_by_id(id.value)
+        .filter(message::Column::DeletedAt.is_null())
+        .one(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?
+        .ok_or(APIError::Err(MessageError::MessageNotFound))
+}
+
+/// Authors can change their own messages, moderators can change any
+/// message in the channels they manage. Conversations aren't moderated
+async fn authorize_author_or_moderator(
+    app_state: &AppState,
+    connection_state: &ConnectionState,
+    message: &message::Model,
+) -> APIResult<(), MessageError> {
+    let user_id = connection_state
+        .read()
+        .unwrap()
+        .get_user_id()
+        .expect("Message handlers check auth first");
+
+    if message.sent_by == user_id.value {
+        return Ok(());
+    }
+
+    let StoredChannel::Text(channel_id) = StoredChannel::from_model(message) else {
+        return Err(APIError::Forbidden);
+    };
+
+    require_permission(
+        app_state,
+        connection_state,
+        Some(ChannelId::Text(channel_id)),
+        Permissions::MANAGE_MESSAGES,
+    )
+    .await
+}
+
+impl RPCHandle for EditMessage {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        EditMessagePayload { id, content }: EditMessagePayload,
+    ) -> APIResult<Message, MessageError> {
+        check_auth!(connection_state);
+
+        let model = find_message(&app_state, id).await?;
+        authorize_author_or_moderator(&app_state, &connection_state, &model).await?;
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let channel = StoredChannel::from_model(&model);
+        let previous = model.content.clone();
+        let Some(mut message) = message_from_model(model.clone()) else {
+            return Err(APIError::ServerError);
+        };
+
+        message.content.content = content;
+        validate_text(&message.content).map_err(APIError::Err)?;
+
+        let serialized = serde_json::to_value(&message.content).map_err(|err| {
+            log::error!("Failed to serialize message content: {err}");
+
+            APIError::ServerError
+        })?;
+
+        let now = Utc::now().naive_utc();
+
+        let txn = app_state.db.begin().await.map_err(DbErr::into_api_error)?;
+
+        message_revision::ActiveModel {
+            message_id: Set(model.id),
+            content: Set(previous),
+            edited_by: Set(user_id.value),
+            replaced_at: Set(now),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+        let mut model: message::ActiveModel = model.into();
+        model.content = Set(serialized);
+        model.edited_at = Set(Some(now));
+
+        model.update(&txn).await.map_err(DbErr::into_api_error)?;
+
+        txn.commit().await.map_err(DbErr::into_api_error)?;
+
+        message.edited_at = Some(now.and_utc().timestamp());
+
+        let viewers = channel_viewers(&app_state, channel)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        for writer in viewers {
+            MessageUpdated {
+                message: message.clone(),
+            }
+            .notify(&writer)
+            .await;
+        }
+
+        // The message is already stored and delivered, failing here would
+        // make the client retry it
+        if let Err(err) = mentions::update_mentions(&app_state, channel, &message).await {
+            log::error!(
+                "Failed to update mentions of message (ID {}): {err}",
+                message.id.value
+            );
+        }
+
+        Ok(message)
+    }
+}
+
+impl RPCHandle for DeleteMessage {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        DeleteMessagePayload { id }: DeleteMessagePayload,
+    ) -> APIResult<(), MessageError> {
+        check_auth!(connection_state);
+
+        let model = find_message(&app_state, id).await?;
+        authorize_author_or_moderator(&app_state, &connection_state, &model).await?;
+
+        let channel = StoredChannel::from_model(&model);
+
+        let mut model: message::ActiveModel = model.into();
+        model.deleted_at = Set(Some(Utc::now().naive_utc()));
+
+        model
+            .update(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let viewers = channel_viewers(&app_state, channel)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        for writer in viewers {
+            MessageDeleted {
+                channel: channel.channel(),
+                id,
+            }
+            .notify(&writer)
+            .await;
+        }
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for GetMessageRevisions {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        GetMessageRevisionsPayload { id }: GetMessageRevisionsPayload,
+    ) -> APIResult<Vec<MessageRevision>, MessageError> {
+        check_auth!(connection_state);
+
+        // Moderators can look into the history of deleted messages as well
+        let model = MessageEntity::find_by_id(id.value)
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::Err(MessageError::MessageNotFound))?;
+
+        let StoredChannel::Text(channel_id) = StoredChannel::from_model(&model) else {
+            return Err(APIError::Forbidden);
+        };
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            Some(ChannelId::Text(channel_id)),
+            Permissions::MANAGE_MESSAGES,
+        )
+        .await?;
+
+        let revisions = MessageRevisionEntity::find()
+            .filter(message_revision::Column::MessageId.eq(model.id))
+            .order_by_asc(message_revision::Column::Id)
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        Ok(revisions
+            .into_iter()
+            .filter_map(|revision| {
+                let content = serde_json::from_value::<MessageContent>(revision.content)
+                    .inspect_err(|err| {
+                        log::error!("Revision (ID {}) has malformed content: {err}", revision.id)
+                    })
+                    .ok()?;
+
+                Some(MessageRevision {
+                    content,
+                    edited_by: Id::new(revision.edited_by),
+                    replaced_at: revision.replaced_at.and_utc().timestamp(),
+                })
+            })
+            .collect())
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(
+        router,
+        SendMessage,
+        GetMessages,
+        EditMessage,
+        DeleteMessage,
+        GetMessageRevisions,
+    )
 }
@@ -1,7 +1,25 @@
 pub mod common;
 
+pub mod audit_log;
 pub mod auth;
+pub mod channels;
+pub mod emojis;
+pub mod groups;
+pub mod invites;
+pub mod media;
+pub mod mentions;
 pub mod messages;
+pub mod moderation;
+pub mod motd;
+pub mod permissions;
+pub mod presence;
+pub mod profiles;
+pub mod reactions;
+pub mod read_state;
+pub mod relationships;
+pub mod search;
+pub mod sessions;
 
 pub mod text;
+pub mod typing;
 pub mod voice;
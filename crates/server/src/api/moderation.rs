@@ -0,0 +1,416 @@
+use chrono::{DateTime, Utc};
+use rpc::{
+    check_auth,
+    common::Empty,
+    models::{
+        audit_log::AuditAction,
+        auth::SessionTerminationReason,
+        common::{APIError, APIResult, RPCMethod as _},
+        markers::{ChannelId, TaggedEntity as _, UserId},
+        moderation::{
+            Ban, BanUser, BanUserPayload, GetBans, KickUser, KickUserPayload, ModerationError,
+            MoveVoiceMember, MoveVoiceMemberPayload, SetVoiceServerState,
+            SetVoiceServerStatePayload, UnbanUser, UnbanUserPayload,
+        },
+        permissions::Permissions,
+        voice::{VoiceChannelUpdateMessage, VoiceServerState},
+    },
+};
+
+use dashmap::DashMap;
+use sea_orm::{DatabaseConnection, DbErr, entity::*, query::*};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        audit_log::AuditEntry,
+        common::{DbErrReponseCompat as _, RPCHandle},
+        permissions::{require_permission, user_rank},
+        sessions::terminate_user_connections,
+        voice::broadcast_voice_update,
+    },
+    entity::{
+        session::{self, Entity as Session},
+        user::{self, Entity as User},
+        voice_channel::Entity as VoiceChannel,
+    },
+    register_endpoints,
+};
+
+/// Looks up the user a moderation action is applied to. Moderators can
+/// only act on users ranked below them in the role hierarchy
+async fn find_target(
+    app_state: &AppState,
+    connection_state: &ConnectionState,
+    user_id: UserId,
+) -> APIResult<user::Model, ModerationError> {
+    let current_user_id = connection_state
+        .read()
+        .unwrap()
+        .get_user_id()
+        .expect("Moderation handlers check auth first");
+
+    if current_user_id == user_id {
+        return Err(APIError::Err(ModerationError::SelfModeration));
+    }
+
+    let user = User::find_by_id(user_id.value)
+        .one(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?
+        .ok_or(APIError::Err(ModerationError::UserNotFound))?;
+
+    let rank = user_rank(app_state, current_user_id.value)
+        .await
+        .map_err(DbErr::into_api_error)?;
+    let target_rank = user_rank(app_state, user.id)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+    if target_rank >= rank {
+        return Err(APIError::Err(ModerationError::HigherRole));
+    }
+
+    Ok(user)
+}
+
+fn voice_server_state(user: &user::Model) -> VoiceServerState {
+    VoiceServerState {
+        is_server_muted: user.is_server_muted,
+        is_server_deafened: user.is_server_deafened,
+    }
+}
+
+/// Server mutes and deafens of all users, `ChannelsState` keeps
+/// them in memory so forwarding voice doesn't query the database
+pub async fn load_voice_server_states(
+    db: &DatabaseConnection,
+) -> Result<DashMap<UserId, VoiceServerState>, DbErr> {
+    let users = User::find()
+        .filter(
+            Condition::any()
+                .add(user::Column::IsServerMuted.eq(true))
+                .add(user::Column::IsServerDeafened.eq(true)),
+        )
+        .all(db)
+        .await?;
+
+    Ok(users
+        .iter()
+        .map(|user| (user.tagged_id(), voice_server_state(user)))
+        .collect())
+}
+
+fn ban_info(user: &user::Model) -> Ban {
+    Ban {
+        user_id: user.tagged_id(),
+        username: user.username.clone(),
+        reason: user.ban_reason.clone(),
+        expires_at: user.banned_until.map(|until| until.and_utc().timestamp()),
+    }
+}
+
+/// Connection the user is currently talking from
+fn voice_connection(
+    app_state: &AppState,
+    user_id: UserId,
+) -> APIResult<ConnectionState, ModerationError> {
+    app_state
+        .voice_connection(user_id)
+        .ok_or(APIError::Err(ModerationError::NotInVoiceChannel))
+}
+
+impl RPCHandle for KickUser {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        KickUserPayload { user_id, reason }: KickUserPayload,
+    ) -> APIResult<(), ModerationError> {
+        check_auth!(connection_state);
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            None,
+            Permissions::KICK_MEMBERS,
+        )
+        .await?;
+
+        let user = find_target(&app_state, &connection_state, user_id).await?;
+
+        terminate_user_connections(
+            &app_state,
+            user.tagged_id(),
+            SessionTerminationReason::Kicked,
+        )
+        .await;
+
+        AuditEntry::new(AuditAction::MemberKick)
+            .target(user_id)
+            .reason(reason)
+            .record(&app_state, &connection_state)
+            .await;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for BanUser {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        BanUserPayload {
+            user_id,
+            reason,
+            expires_at,
+        }: BanUserPayload,
+    ) -> APIResult<(), ModerationError> {
+        check_auth!(connection_state);
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            None,
+            Permissions::BAN_MEMBERS,
+        )
+        .await?;
+
+        let user = find_target(&app_state, &connection_state, user_id).await?;
+
+        let banned_until = expires_at
+            .and_then(|timestamp| DateTime::<Utc>::from_timestamp(timestamp, 0))
+            .map(|until| until.naive_utc());
+
+        let mut user: user::ActiveModel = user.into();
+        user.banned = Set(true);
+        user.ban_reason = Set(reason.clone());
+        user.banned_until = Set(banned_until);
+
+        let user = user
+            .update(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        Session::delete_many()
+            .filter(session::Column::UserId.eq(user_id.value))
+            .exec(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        terminate_user_connections(&app_state, user_id, SessionTerminationReason::Banned).await;
+
+        AuditEntry::new(AuditAction::MemberBan)
+            .target(user_id)
+            .reason(reason)
+            .after(&ban_info(&user))
+            .record(&app_state, &connection_state)
+            .await;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for UnbanUser {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        UnbanUserPayload { user_id }: UnbanUserPayload,
+    ) -> APIResult<(), ModerationError> {
+        check_auth!(connection_state);
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            None,
+            Permissions::BAN_MEMBERS,
+        )
+        .await?;
+
+        let user = find_target(&app_state, &connection_state, user_id).await?;
+        let before = user.is_banned().then(|| ban_info(&user));
+
+        let mut user: user::ActiveModel = user.into();
+        user.banned = Set(false);
+        user.ban_reason = Set(None);
+        user.banned_until = Set(None);
+
+        user.update(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        AuditEntry::new(AuditAction::MemberUnban)
+            .target(user_id)
+            .before(&before)
+            .record(&app_state, &connection_state)
+            .await;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for GetBans {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        _req: Empty,
+    ) -> APIResult<Vec<Ban>, ()> {
+        check_auth!(connection_state);
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            None,
+            Permissions::BAN_MEMBERS,
+        )
+        .await?;
+
+        let users = User::find()
+            .filter(user::Column::Banned.eq(true))
+            .order_by_asc(user::Column::Username)
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        Ok(users
+            .into_iter()
+            .filter(|user| user.is_banned())
+            .map(|user| ban_info(&user))
+            .collect())
+    }
+}
+
+impl RPCHandle for SetVoiceServerState {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        SetVoiceServerStatePayload { user_id, state }: SetVoiceServerStatePayload,
+    ) -> APIResult<(), ModerationError> {
+        check_auth!(connection_state);
+
+        let user = find_target(&app_state, &connection_state, user_id).await?;
+
+        let voice_connection = voice_connection(&app_state, user_id)?;
+        let channel_id = voice_connection
+            .read()
+            .unwrap()
+            .active_voice_channel
+            .ok_or(APIError::Err(ModerationError::NotInVoiceChannel))?;
+
+        require_permission(
+            &app_state,
+            &connection_state,
+            Some(ChannelId::Voice(channel_id)),
+            Permissions::MUTE_MEMBERS,
+        )
+        .await?;
+
+        let before = voice_server_state(&user);
+
+        let mut user: user::ActiveModel = user.into();
+        user.is_server_muted = Set(state.is_server_muted);
+        user.is_server_deafened = Set(state.is_server_deafened);
+
+        user.update(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        app_state.channels.set_voice_server_state(user_id, state);
+
+        AuditEntry::new(AuditAction::VoiceServerStateUpdate)
+            .target(user_id)
+            .before(&before)
+            .after(&state)
+            .record(&app_state, &connection_state)
+            .await;
+
+        broadcast_voice_update(
+            &app_state,
+            channel_id,
+            VoiceChannelUpdateMessage::UserServerStateUpdated((user_id, state)),
+        );
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for MoveVoiceMember {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        MoveVoiceMemberPayload {
+            user_id,
+            channel_id,
+        }: MoveVoiceMemberPayload,
+    ) -> APIResult<(), ModerationError> {
+        check_auth!(connection_state);
+
+        find_target(&app_state, &connection_state, user_id).await?;
+
+        let exists = VoiceChannel::find_by_id(channel_id.value)
+            .exists(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if !exists {
+            return Err(APIError::Err(ModerationError::ChannelNotFound));
+        }
+
+        let voice_connection = voice_connection(&app_state, user_id)?;
+        let previous_channel = voice_connection
+            .read()
+            .unwrap()
+            .active_voice_channel
+            .ok_or(APIError::Err(ModerationError::NotInVoiceChannel))?;
+
+        if previous_channel == channel_id {
+            return Ok(());
+        }
+
+        // Moderator has to be able to move users out of the channel and into the new one
+        for channel in [previous_channel, channel_id] {
+            require_permission(
+                &app_state,
+                &connection_state,
+                Some(ChannelId::Voice(channel)),
+                Permissions::MOVE_MEMBERS,
+            )
+            .await?;
+        }
+
+        if !app_state
+            .channels
+            .move_voice_user(user_id, previous_channel, channel_id)
+        {
+            return Err(APIError::Err(ModerationError::NotInVoiceChannel));
+        }
+
+        voice_connection.write().unwrap().active_voice_channel = Some(channel_id);
+
+        AuditEntry::new(AuditAction::VoiceMemberMove)
+            .target(user_id)
+            .before(&previous_channel)
+            .after(&channel_id)
+            .record(&app_state, &connection_state)
+            .await;
+
+        broadcast_voice_update(
+            &app_state,
+            previous_channel,
+            VoiceChannelUpdateMessage::UserMoved((user_id, channel_id)),
+        );
+
+        Ok(())
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(
+        router,
+        KickUser,
+        BanUser,
+        UnbanUser,
+        GetBans,
+        SetVoiceServerState,
+        MoveVoiceMember,
+    )
+}
@@ -0,0 +1,43 @@
+use rpc::{
+    check_auth,
+    common::Empty,
+    models::{
+        common::{APIError, APIResult, RPCMethod as _, RPCNotification as _},
+        motd::{GetMotd, MotdUpdate},
+    },
+};
+
+use crate::{AppState, ConnectionState, GlobalRouter, api::common::RPCHandle, register_endpoints};
+
+/// Replaces the message of the day, everyone is notified if it changed
+pub async fn set_motd(app_state: &AppState, motd: Option<String>) {
+    {
+        let mut current = app_state.motd.write().unwrap();
+
+        if *current == motd {
+            return;
+        }
+
+        *current = motd.clone();
+    }
+
+    for (_, writer) in app_state.writers() {
+        MotdUpdate { motd: motd.clone() }.notify(&writer).await;
+    }
+}
+
+impl RPCHandle for GetMotd {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        _req: Empty,
+    ) -> APIResult<Option<String>, ()> {
+        check_auth!(connection_state);
+
+        Ok(app_state.motd.read().unwrap().clone())
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(router, GetMotd)
+}
@@ -0,0 +1,922 @@
+use std::{collections::HashMap, fmt::Debug};
+
+use chrono::Utc;
+use rpc::{
+    check_auth,
+    common::Empty,
+    models::{
+        audit_log::AuditAction,
+        common::{APIError, APIResult, RPCMethod as _},
+        markers::{ChannelId, Id, TaggedEntity as _},
+        permissions::{
+            AssignRole, ChannelPermissionOverride, CreateRole, CreateRolePayload, DeleteRole,
+            DeleteRolePayload, GetChannelOverrides, GetChannelOverridesPayload, GetPermissions,
+            GetPermissionsPayload, GetRoles, Permissions, Role, RoleAssignmentPayload, RoleError,
+            SetChannelOverride, SetChannelOverrideError, UnassignRole, UpdateRole,
+            UpdateRolePayload,
+        },
+    },
+    server::RpcWriter,
+};
+
+use serde::Serialize;
+
+use sea_orm::{
+    ConnectionTrait, DatabaseConnection, DbErr, TransactionTrait as _, entity::*, query::*,
+};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        audit_log::AuditEntry,
+        common::{DbErrReponseCompat as _, RPCHandle},
+    },
+    entity::{
+        channel_permission_override::{self, Entity as ChannelPermissionOverrideEntity},
+        role::{self, Entity as RoleEntity},
+        text_channel::Entity as TextChannel,
+        user::{self, Entity as User},
+        user_role::{self, Entity as UserRole},
+        voice_channel::Entity as VoiceChannel,
+    },
+    register_endpoints,
+};
+
+fn from_db(value: i64) -> Permissions {
+    Permissions(value as u64)
+}
+
+fn to_db(value: Permissions) -> i64 {
+    value.0 as i64
+}
+
+/// State of a role stored in the audit log
+#[derive(Serialize)]
+struct RoleSettings {
+    name: String,
+    permissions: Permissions,
+    position: i32,
+}
+
+impl From<&role::Model> for RoleSettings {
+    fn from(role: &role::Model) -> Self {
+        Self {
+            name: role.name.clone(),
+            permissions: from_db(role.permissions),
+            position: role.position,
+        }
+    }
+}
+
+fn override_info(
+    channel: ChannelId,
+    item: &channel_permission_override::Model,
+) -> ChannelPermissionOverride {
+    ChannelPermissionOverride {
+        channel,
+        role_id: Id::new(item.role_id),
+        allow: from_db(item.allow),
+        deny: from_db(item.deny),
+    }
+}
+
+/// Creates the default role if the server doesn't have one yet
+pub async fn ensure_default_role(db: &DatabaseConnection) -> Result<(), DbErr> {
+    let exists = RoleEntity::find()
+        .filter(role::Column::IsDefault.eq(true))
+        .exists(db)
+        .await?;
+
+    if exists {
+        return Ok(());
+    }
+
+    role::ActiveModel {
+        name: Set("everyone".into()),
+        permissions: Set(to_db(Permissions::DEFAULT)),
+        position: Set(0),
+        is_default: Set(true),
+        created_at: Set(Utc::now().naive_utc()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Assigns a role with the administrator permission to the user, creating
+/// one above all other roles if there's none yet. Returns `false` if the
+/// user already had such a role
+pub async fn grant_administrator(db: &DatabaseConnection, user_id: i32) -> Result<bool, DbErr> {
+    let roles = RoleEntity::find()
+        .order_by_desc(role::Column::Position)
+        .all(db)
+        .await?;
+
+    let admin_role = roles
+        .iter()
+        .find(|role| !role.is_default && from_db(role.permissions).is_admin());
+
+    let role_id = match admin_role {
+        Some(role) => role.id,
+        None => {
+            let position = roles.first().map_or(0, |role| role.position) + 1;
+
+            role::ActiveModel {
+                name: Set("admin".into()),
+                permissions: Set(to_db(Permissions::ADMINISTRATOR)),
+                position: Set(position),
+                is_default: Set(false),
+                created_at: Set(Utc::now().naive_utc()),
+                ..Default::default()
+            }
+            .insert(db)
+            .await?
+            .id
+        }
+    };
+
+    let assigned = UserRole::find()
+        .filter(user_role::Column::UserId.eq(user_id))
+        .filter(user_role::Column::RoleId.eq(role_id))
+        .exists(db)
+        .await?;
+
+    if assigned {
+        return Ok(false);
+    }
+
+    user_role::ActiveModel {
+        user_id: Set(user_id),
+        role_id: Set(role_id),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(true)
+}
+
+fn channel_overrides(channel: ChannelId) -> Select<ChannelPermissionOverrideEntity> {
+    let query = ChannelPermissionOverrideEntity::find();
+
+    match channel {
+        ChannelId::Text(id) => {
+            query.filter(channel_permission_override::Column::TextChannelId.eq(id.value))
+        }
+        ChannelId::Voice(id) => {
+            query.filter(channel_permission_override::Column::VoiceChannelId.eq(id.value))
+        }
+    }
+}
+
+/// Removes all overrides of a channel, used when the channel is deleted
+pub async fn delete_channel_overrides(
+    db: &impl ConnectionTrait,
+    channel: ChannelId,
+) -> Result<(), DbErr> {
+    let query = ChannelPermissionOverrideEntity::delete_many();
+
+    let query = match channel {
+        ChannelId::Text(id) => {
+            query.filter(channel_permission_override::Column::TextChannelId.eq(id.value))
+        }
+        ChannelId::Voice(id) => {
+            query.filter(channel_permission_override::Column::VoiceChannelId.eq(id.value))
+        }
+    };
+
+    query.exec(db).await?;
+
+    Ok(())
+}
+
+async fn channel_exists(db: &DatabaseConnection, channel: ChannelId) -> Result<bool, DbErr> {
+    match channel {
+        ChannelId::Text(id) => TextChannel::find_by_id(id.value).exists(db).await,
+        ChannelId::Voice(id) => VoiceChannel::find_by_id(id.value).exists(db).await,
+    }
+}
+
+/// The default role along with the roles assigned to the user
+async fn user_roles(db: &DatabaseConnection, user_id: i32) -> Result<Vec<role::Model>, DbErr> {
+    let assigned = UserRole::find()
+        .filter(user_role::Column::UserId.eq(user_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|item| item.role_id);
+
+    RoleEntity::find()
+        .filter(
+            Condition::any()
+                .add(role::Column::IsDefault.eq(true))
+                .add(role::Column::Id.is_in(assigned)),
+        )
+        .all(db)
+        .await
+}
+
+fn roles_permissions(roles: &[role::Model]) -> Permissions {
+    roles.iter().fold(Permissions::NONE, |acc, role| {
+        acc | from_db(role.permissions)
+    })
+}
+
+/// Position of the highest role, administrators outrank every role.
+/// Roles and members can only be managed by users ranked strictly above them
+fn roles_rank(roles: &[role::Model]) -> i32 {
+    if roles_permissions(roles).is_admin() {
+        return i32::MAX;
+    }
+
+    roles
+        .iter()
+        .map(|role| role.position)
+        .max()
+        .unwrap_or(i32::MIN)
+}
+
+/// Rank of the user in the role hierarchy, see [`roles_rank`]
+pub async fn user_rank(app_state: &AppState, user_id: i32) -> Result<i32, DbErr> {
+    let roles = user_roles(&app_state.db, user_id).await?;
+
+    Ok(roles_rank(&roles))
+}
+
+/// Server-wide permissions and rank of a user managing roles
+struct RoleManager {
+    permissions: Permissions,
+    rank: i32,
+}
+
+impl RoleManager {
+    fn outranks(&self, position: i32) -> bool {
+        position < self.rank
+    }
+
+    /// Roles can't hand out permissions their manager doesn't have
+    fn can_grant(&self, permissions: Permissions) -> bool {
+        self.permissions.contains(permissions)
+    }
+
+    fn check<E: Debug>(&self, position: i32, permissions: Permissions) -> APIResult<(), E> {
+        if !self.outranks(position) || !self.can_grant(permissions) {
+            return Err(APIError::Forbidden);
+        }
+
+        Ok(())
+    }
+}
+
+/// Fails with [`APIError::Forbidden`] if the connected user
+/// can't manage roles
+async fn require_role_manager<E: Debug>(
+    app_state: &AppState,
+    connection_state: &ConnectionState,
+) -> APIResult<RoleManager, E> {
+    let user = connection_state.read().unwrap().user.clone();
+
+    let Some(user) = user else {
+        return Err(APIError::Unauthorized);
+    };
+
+    let roles = user_roles(&app_state.db, user.id)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+    let permissions = roles_permissions(&roles);
+    if !permissions.contains(Permissions::MANAGE_ROLES) {
+        return Err(APIError::Forbidden);
+    }
+
+    Ok(RoleManager {
+        permissions,
+        rank: roles_rank(&roles),
+    })
+}
+
+/// Number of IDs bound in a single `IN` clause, well below
+/// the limit of bound variables in SQLite
+const IN_CHUNK_SIZE: usize = 500;
+
+/// Channels whose overrides are loaded into a [`PermissionSnapshot`]
+#[derive(Debug, Clone, Copy)]
+pub enum PermissionScope {
+    /// Server-wide permissions only
+    Server,
+    Channel(ChannelId),
+    /// Every text and voice channel
+    AllChannels,
+}
+
+/// Roles, role assignments of some users and channel overrides loaded
+/// at once, so permissions of many users or in many channels are
+/// calculated without querying the database for each of them
+pub struct PermissionSnapshot {
+    roles: HashMap<i32, role::Model>,
+    /// Roles explicitly assigned to each user
+    assignments: HashMap<i32, Vec<i32>>,
+    overrides: HashMap<ChannelId, Vec<channel_permission_override::Model>>,
+}
+
+impl PermissionSnapshot {
+    pub async fn load(
+        db: &DatabaseConnection,
+        user_ids: &[i32],
+        scope: PermissionScope,
+    ) -> Result<Self, DbErr> {
+        let roles = RoleEntity::find()
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|role| (role.id, role))
+            .collect();
+
+        let mut assignments = HashMap::<i32, Vec<i32>>::new();
+        for chunk in user_ids.chunks(IN_CHUNK_SIZE) {
+            let items = UserRole::find()
+                .filter(user_role::Column::UserId.is_in(chunk.iter().copied()))
+                .all(db)
+                .await?;
+
+            for item in items {
+                assignments
+                    .entry(item.user_id)
+                    .or_default()
+                    .push(item.role_id);
+            }
+        }
+
+        let overrides = match scope {
+            PermissionScope::Server => vec![],
+            PermissionScope::Channel(channel) => channel_overrides(channel).all(db).await?,
+            PermissionScope::AllChannels => ChannelPermissionOverrideEntity::find().all(db).await?,
+        };
+
+        let mut by_channel = HashMap::<ChannelId, Vec<_>>::new();
+        for item in overrides {
+            let channel = match (item.text_channel_id, item.voice_channel_id) {
+                (Some(id), _) => ChannelId::Text(Id::new(id)),
+                (None, Some(id)) => ChannelId::Voice(Id::new(id)),
+                (None, None) => continue,
+            };
+
+            by_channel.entry(channel).or_default().push(item);
+        }
+
+        Ok(Self {
+            roles,
+            assignments,
+            overrides: by_channel,
+        })
+    }
+
+    /// Effective permissions of the user. If the channel is set, its
+    /// overrides are applied on top of the role permissions. The user
+    /// and the channel have to be covered by what was loaded
+    pub fn permissions(&self, user_id: i32, channel: Option<ChannelId>) -> Permissions {
+        let assigned = self
+            .assignments
+            .get(&user_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let roles = self
+            .roles
+            .values()
+            .filter(|role| role.is_default || assigned.contains(&role.id))
+            .collect::<Vec<_>>();
+
+        let permissions = roles.iter().fold(Permissions::NONE, |acc, role| {
+            acc | from_db(role.permissions)
+        });
+
+        if permissions.is_admin() {
+            return Permissions::ALL;
+        }
+
+        let Some(overrides) = channel.and_then(|channel| self.overrides.get(&channel)) else {
+            return permissions;
+        };
+
+        // Overrides of the default role go first, so overrides
+        // of explicitly assigned roles take precedence
+        let mut permissions = permissions;
+        for is_default in [true, false] {
+            let (allow, deny) = overrides
+                .iter()
+                .filter(|item| {
+                    roles
+                        .iter()
+                        .any(|role| role.id == item.role_id && role.is_default == is_default)
+                })
+                .fold(
+                    (Permissions::NONE, Permissions::NONE),
+                    |(allow, deny), item| (allow | from_db(item.allow), deny | from_db(item.deny)),
+                );
+
+            permissions = permissions.apply_override(allow, deny);
+        }
+
+        permissions
+    }
+
+    pub fn can_view(&self, user_id: i32, channel: ChannelId) -> bool {
+        self.permissions(user_id, Some(channel))
+            .contains(Permissions::VIEW_CHANNEL)
+    }
+}
+
+/// Connections of every connected user who can see the channel
+pub async fn channel_viewers(
+    app_state: &AppState,
+    channel: ChannelId,
+) -> Result<Vec<RpcWriter>, DbErr> {
+    let user_ids = app_state
+        .connected_clients
+        .iter()
+        .map(|entry| entry.key().value)
+        .collect::<Vec<_>>();
+
+    let snapshot =
+        PermissionSnapshot::load(&app_state.db, &user_ids, PermissionScope::Channel(channel))
+            .await?;
+
+    Ok(user_ids
+        .into_iter()
+        .filter(|user_id| snapshot.can_view(*user_id, channel))
+        .flat_map(|user_id| app_state.user_writers(Id::new(user_id)))
+        .collect())
+}
+
+/// Calculates effective permissions of the user. If the channel is set,
+/// its overrides are applied on top of the role permissions
+pub async fn user_permissions(
+    app_state: &AppState,
+    user: &user::Model,
+    channel: Option<ChannelId>,
+) -> Result<Permissions, DbErr> {
+    let scope = channel.map_or(PermissionScope::Server, PermissionScope::Channel);
+    let snapshot = PermissionSnapshot::load(&app_state.db, &[user.id], scope).await?;
+
+    Ok(snapshot.permissions(user.id, channel))
+}
+
+/// Fails with [`APIError::Forbidden`] if the connected user
+/// doesn't have the permission
+pub async fn require_permission<E: Debug>(
+    app_state: &AppState,
+    connection_state: &ConnectionState,
+    channel: Option<ChannelId>,
+    permission: Permissions,
+) -> APIResult<(), E> {
+    let user = connection_state.read().unwrap().user.clone();
+
+    let Some(user) = user else {
+        return Err(APIError::Unauthorized);
+    };
+
+    let permissions = user_permissions(app_state, &user, channel)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+    if !permissions.contains(permission) {
+        return Err(APIError::Forbidden);
+    }
+
+    Ok(())
+}
+
+impl RPCHandle for GetPermissions {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        GetPermissionsPayload { channel }: GetPermissionsPayload,
+    ) -> APIResult<Permissions, ()> {
+        check_auth!(connection_state);
+
+        let user = connection_state
+            .read()
+            .unwrap()
+            .user
+            .clone()
+            .expect("We checked auth above");
+
+        user_permissions(&app_state, &user, channel)
+            .await
+            .map_err(DbErr::into_api_error)
+    }
+}
+
+impl RPCHandle for GetRoles {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        _req: Empty,
+    ) -> APIResult<Vec<Role>, ()> {
+        check_auth!(connection_state);
+
+        let roles = RoleEntity::find()
+            .order_by_desc(role::Column::Position)
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let assignments = UserRole::find()
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        Ok(roles
+            .into_iter()
+            .map(|role| Role {
+                id: role.tagged_id(),
+                permissions: from_db(role.permissions),
+                position: role.position,
+                is_default: role.is_default,
+                members: assignments
+                    .iter()
+                    .filter(|item| item.role_id == role.id)
+                    .map(|item| Id::new(item.user_id))
+                    .collect(),
+
+                name: role.name,
+            })
+            .collect())
+    }
+}
+
+impl RPCHandle for CreateRole {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        CreateRolePayload {
+            name,
+            permissions,
+            position,
+        }: CreateRolePayload,
+    ) -> Self::Response {
+        let manager = require_role_manager(&app_state, &connection_state).await?;
+        manager.check(position, permissions)?;
+
+        let role = role::ActiveModel {
+            name: Set(name),
+            permissions: Set(to_db(permissions)),
+            position: Set(position),
+            is_default: Set(false),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+        AuditEntry::new(AuditAction::RoleCreate)
+            .target(role.tagged_id())
+            .after(&RoleSettings::from(&role))
+            .record(&app_state, &connection_state)
+            .await;
+
+        Ok(role.tagged_id())
+    }
+}
+
+impl RPCHandle for UpdateRole {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        UpdateRolePayload {
+            id,
+            name,
+            permissions,
+            position,
+        }: UpdateRolePayload,
+    ) -> Self::Response {
+        let manager = require_role_manager(&app_state, &connection_state).await?;
+
+        let role = RoleEntity::find_by_id(id.value)
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::Err(RoleError::NotFound))?;
+
+        // The role can't be moved above the manager either
+        if !manager.outranks(role.position) {
+            return Err(APIError::Forbidden);
+        }
+        manager.check(position, permissions)?;
+
+        let before = RoleSettings::from(&role);
+
+        let mut role: role::ActiveModel = role.into();
+
+        role.name = Set(name);
+        role.permissions = Set(to_db(permissions));
+        role.position = Set(position);
+
+        let role = role
+            .update(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        AuditEntry::new(AuditAction::RoleUpdate)
+            .target(id)
+            .before(&before)
+            .after(&RoleSettings::from(&role))
+            .record(&app_state, &connection_state)
+            .await;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for DeleteRole {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        DeleteRolePayload { id }: DeleteRolePayload,
+    ) -> Self::Response {
+        let manager = require_role_manager(&app_state, &connection_state).await?;
+
+        let role = RoleEntity::find_by_id(id.value)
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::Err(RoleError::NotFound))?;
+
+        if role.is_default {
+            return Err(APIError::Err(RoleError::DefaultRole));
+        }
+
+        if !manager.outranks(role.position) {
+            return Err(APIError::Forbidden);
+        }
+
+        let txn = app_state.db.begin().await.map_err(DbErr::into_api_error)?;
+
+        UserRole::delete_many()
+            .filter(user_role::Column::RoleId.eq(role.id))
+            .exec(&txn)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        ChannelPermissionOverrideEntity::delete_many()
+            .filter(channel_permission_override::Column::RoleId.eq(role.id))
+            .exec(&txn)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        RoleEntity::delete_by_id(role.id)
+            .exec(&txn)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        txn.commit().await.map_err(DbErr::into_api_error)?;
+
+        AuditEntry::new(AuditAction::RoleDelete)
+            .target(id)
+            .before(&RoleSettings::from(&role))
+            .record(&app_state, &connection_state)
+            .await;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for AssignRole {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        RoleAssignmentPayload { role_id, user_id }: RoleAssignmentPayload,
+    ) -> Self::Response {
+        let manager = require_role_manager(&app_state, &connection_state).await?;
+
+        let role = RoleEntity::find_by_id(role_id.value)
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::Err(RoleError::NotFound))?;
+
+        if role.is_default {
+            return Err(APIError::Err(RoleError::DefaultRole));
+        }
+
+        manager.check(role.position, from_db(role.permissions))?;
+
+        let user_exists = User::find_by_id(user_id.value)
+            .exists(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if !user_exists {
+            return Err(APIError::Err(RoleError::UserNotFound));
+        }
+
+        let is_assigned = UserRole::find()
+            .filter(user_role::Column::RoleId.eq(role.id))
+            .filter(user_role::Column::UserId.eq(user_id.value))
+            .exists(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if is_assigned {
+            return Ok(());
+        }
+
+        user_role::ActiveModel {
+            user_id: Set(user_id.value),
+            role_id: Set(role.id),
+            ..Default::default()
+        }
+        .insert(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+        AuditEntry::new(AuditAction::RoleAssign)
+            .target(user_id)
+            .after(&role_id)
+            .record(&app_state, &connection_state)
+            .await;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for UnassignRole {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        RoleAssignmentPayload { role_id, user_id }: RoleAssignmentPayload,
+    ) -> Self::Response {
+        let manager = require_role_manager(&app_state, &connection_state).await?;
+
+        let role = RoleEntity::find_by_id(role_id.value)
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::Err(RoleError::NotFound))?;
+
+        if !manager.outranks(role.position) {
+            return Err(APIError::Forbidden);
+        }
+
+        let result = UserRole::delete_many()
+            .filter(user_role::Column::RoleId.eq(role_id.value))
+            .filter(user_role::Column::UserId.eq(user_id.value))
+            .exec(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if result.rows_affected == 0 {
+            return Err(APIError::Err(RoleError::NotFound));
+        }
+
+        AuditEntry::new(AuditAction::RoleUnassign)
+            .target(user_id)
+            .before(&role_id)
+            .record(&app_state, &connection_state)
+            .await;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for GetChannelOverrides {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        GetChannelOverridesPayload { channel }: GetChannelOverridesPayload,
+    ) -> APIResult<Vec<ChannelPermissionOverride>, ()> {
+        require_permission(
+            &app_state,
+            &connection_state,
+            None,
+            Permissions::MANAGE_ROLES,
+        )
+        .await?;
+
+        let overrides = channel_overrides(channel)
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        Ok(overrides
+            .iter()
+            .map(|item| override_info(channel, item))
+            .collect())
+    }
+}
+
+impl RPCHandle for SetChannelOverride {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        ChannelPermissionOverride {
+            channel,
+            role_id,
+            allow,
+            deny,
+        }: ChannelPermissionOverride,
+    ) -> Self::Response {
+        let manager = require_role_manager(&app_state, &connection_state).await?;
+
+        let channel_exists = channel_exists(&app_state.db, channel)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if !channel_exists {
+            return Err(APIError::Err(SetChannelOverrideError::ChannelNotFound));
+        }
+
+        let role = RoleEntity::find_by_id(role_id.value)
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::Err(SetChannelOverrideError::RoleNotFound))?;
+
+        // Denying is harmless, but allowing can grant the role more than the manager has
+        manager.check(role.position, allow)?;
+
+        let existing = channel_overrides(channel)
+            .filter(channel_permission_override::Column::RoleId.eq(role_id.value))
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let is_empty = allow == Permissions::NONE && deny == Permissions::NONE;
+        let before = existing.as_ref().map(|item| override_info(channel, item));
+
+        match existing {
+            Some(existing) if is_empty => {
+                existing
+                    .delete(&app_state.db)
+                    .await
+                    .map_err(DbErr::into_api_error)?;
+            }
+            Some(existing) => {
+                let mut existing: channel_permission_override::ActiveModel = existing.into();
+
+                existing.allow = Set(to_db(allow));
+                existing.deny = Set(to_db(deny));
+
+                existing
+                    .update(&app_state.db)
+                    .await
+                    .map_err(DbErr::into_api_error)?;
+            }
+            // Nothing to remove, so there is nothing to record either
+            None if is_empty => return Ok(()),
+            None => {
+                let (text_channel_id, voice_channel_id) = match channel {
+                    ChannelId::Text(id) => (Some(id.value), None),
+                    ChannelId::Voice(id) => (None, Some(id.value)),
+                };
+
+                channel_permission_override::ActiveModel {
+                    text_channel_id: Set(text_channel_id),
+                    voice_channel_id: Set(voice_channel_id),
+                    role_id: Set(role_id.value),
+                    allow: Set(to_db(allow)),
+                    deny: Set(to_db(deny)),
+                    ..Default::default()
+                }
+                .insert(&app_state.db)
+                .await
+                .map_err(DbErr::into_api_error)?;
+            }
+        }
+
+        let after = (!is_empty).then_some(ChannelPermissionOverride {
+            channel,
+            role_id,
+            allow,
+            deny,
+        });
+
+        AuditEntry::new(AuditAction::ChannelOverrideUpdate)
+            .target(channel)
+            .before(&before)
+            .after(&after)
+            .record(&app_state, &connection_state)
+            .await;
+
+        Ok(())
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(
+        router,
+        GetPermissions,
+        GetRoles,
+        CreateRole,
+        UpdateRole,
+        DeleteRole,
+        AssignRole,
+        UnassignRole,
+        GetChannelOverrides,
+        SetChannelOverride,
+    )
+}
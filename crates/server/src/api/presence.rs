@@ -0,0 +1,282 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rpc::{
+    check_auth,
+    models::{
+        common::{APIError, APIResult, RPCMethod as _, RPCNotification as _},
+        markers::{TaggedEntity as _, UserId},
+        presence::{
+            CustomStatus, GetPresences, GetPresencesPayload, MAX_CUSTOM_STATUS_LENGTH, Presence,
+            PresenceError, PresenceStatus, PresenceUpdate, SetIdle, SetIdlePayload, SetPresence,
+            SetPresencePayload,
+        },
+    },
+};
+
+use sea_orm::{DbErr, entity::*, query::*};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::common::{DbErrReponseCompat as _, RPCHandle},
+    entity::user::{self, Entity as User},
+    register_endpoints,
+};
+
+fn status_from_db(value: i32) -> PresenceStatus {
+    match value {
+        1 => PresenceStatus::DoNotDisturb,
+        2 => PresenceStatus::Invisible,
+        _ => PresenceStatus::Online,
+    }
+}
+
+fn status_to_db(status: PresenceStatus) -> i32 {
+    match status {
+        PresenceStatus::DoNotDisturb => 1,
+        PresenceStatus::Invisible => 2,
+        _ => 0,
+    }
+}
+
+fn custom_status(user: &user::Model) -> Option<CustomStatus> {
+    let expires_at = user.custom_status_expires_at;
+
+    if expires_at.is_some_and(|expires_at| expires_at <= Utc::now().naive_utc()) {
+        return None;
+    }
+
+    Some(CustomStatus {
+        text: user.custom_status.clone()?,
+        expires_at: expires_at.map(|expires_at| expires_at.and_utc().timestamp()),
+    })
+}
+
+/// Presence of the user as seen by others or by the user themself.
+/// The user is idle when every connected session is idle
+pub fn user_presence(app_state: &AppState, user: &user::Model, is_self: bool) -> Presence {
+    let is_idle = app_state
+        .connected_clients
+        .get(&user.tagged_id())
+        .map(|connections| connections.iter().all(|conn| conn.read().unwrap().is_idle));
+
+    let status = match (is_idle, status_from_db(user.status)) {
+        (None, _) => PresenceStatus::Offline,
+        (Some(_), PresenceStatus::Invisible) if !is_self => PresenceStatus::Offline,
+        (Some(true), PresenceStatus::Online) => PresenceStatus::Idle,
+        (Some(_), status) => status,
+    };
+
+    Presence {
+        user: user.tagged_id(),
+        status,
+        custom_status: (status != PresenceStatus::Offline)
+            .then(|| custom_status(user))
+            .flatten(),
+    }
+}
+
+async fn send_presence(app_state: &AppState, user: &user::Model) {
+    let user_id = user.tagged_id();
+    let public = user_presence(app_state, user, false);
+    let own = user_presence(app_state, user, true);
+
+    for (owner, writer) in app_state.writers() {
+        let presence = if owner == user_id {
+            own.clone()
+        } else {
+            public.clone()
+        };
+
+        PresenceUpdate { presence }.notify(&writer).await;
+    }
+}
+
+/// Lets everyone know the current presence of the user
+pub async fn broadcast_presence(app_state: &AppState, user_id: UserId) -> Result<(), DbErr> {
+    if let Some(user) = User::find_by_id(user_id.value).one(&app_state.db).await? {
+        send_presence(app_state, &user).await;
+    }
+
+    Ok(())
+}
+
+/// Clears the custom status once it expires, unless it was replaced in the meantime
+async fn expire_custom_status(app_state: AppState, user_id: UserId, expires_at: NaiveDateTime) {
+    let delay = (expires_at - Utc::now().naive_utc())
+        .to_std()
+        .unwrap_or_default();
+    tokio::time::sleep(delay).await;
+
+    let user = match User::find_by_id(user_id.value).one(&app_state.db).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return,
+        Err(err) => {
+            log::error!("Failed to load user (ID {}): {err}", user_id.value);
+
+            return;
+        }
+    };
+
+    if user.custom_status_expires_at != Some(expires_at) {
+        return;
+    }
+
+    let mut model: user::ActiveModel = user.into();
+    model.custom_status = Set(None);
+    model.custom_status_expires_at = Set(None);
+
+    match model.update(&app_state.db).await {
+        Ok(user) => send_presence(&app_state, &user).await,
+        Err(err) => log::error!("Failed to clear custom status: {err}"),
+    }
+}
+
+fn validate_custom_status(
+    status: Option<CustomStatus>,
+) -> Result<(Option<String>, Option<NaiveDateTime>), PresenceError> {
+    let Some(CustomStatus { text, expires_at }) = status else {
+        return Ok((None, None));
+    };
+
+    let text = text.trim();
+    if text.is_empty() || text.chars().count() > MAX_CUSTOM_STATUS_LENGTH {
+        return Err(PresenceError::InvalidCustomStatus);
+    }
+
+    let expires_at = match expires_at {
+        Some(timestamp) => {
+            let expires_at = DateTime::<Utc>::from_timestamp(timestamp, 0)
+                .ok_or(PresenceError::InvalidCustomStatus)?;
+
+            if expires_at <= Utc::now() {
+                return Err(PresenceError::CustomStatusExpired);
+            }
+
+            Some(expires_at.naive_utc())
+        }
+        None => None,
+    };
+
+    Ok((Some(text.to_owned()), expires_at))
+}
+
+impl RPCHandle for SetPresence {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        SetPresencePayload {
+            status,
+            custom_status,
+        }: SetPresencePayload,
+    ) -> APIResult<Presence, PresenceError> {
+        check_auth!(connection_state);
+
+        if matches!(status, PresenceStatus::Idle | PresenceStatus::Offline) {
+            return Err(APIError::Err(PresenceError::InvalidStatus));
+        }
+
+        let (text, expires_at) = validate_custom_status(custom_status).map_err(APIError::Err)?;
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let user = User::find_by_id(user_id.value)
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::ServerError)?;
+
+        let mut model: user::ActiveModel = user.into();
+        model.status = Set(status_to_db(status));
+        model.custom_status = Set(text);
+        model.custom_status_expires_at = Set(expires_at);
+
+        let user = model
+            .update(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if let Some(expires_at) = expires_at {
+            tokio::spawn(expire_custom_status(app_state.clone(), user_id, expires_at));
+        }
+
+        send_presence(&app_state, &user).await;
+
+        Ok(user_presence(&app_state, &user, true))
+    }
+}
+
+impl RPCHandle for SetIdle {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        SetIdlePayload { is_idle }: SetIdlePayload,
+    ) -> APIResult<(), ()> {
+        check_auth!(connection_state);
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let user = User::find_by_id(user_id.value)
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::ServerError)?;
+
+        let before = user_presence(&app_state, &user, true);
+        connection_state.write().unwrap().is_idle = is_idle;
+
+        // Other sessions may still be active
+        if user_presence(&app_state, &user, true) != before {
+            send_presence(&app_state, &user).await;
+        }
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for GetPresences {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        GetPresencesPayload { users }: GetPresencesPayload,
+    ) -> APIResult<Vec<Presence>, ()> {
+        check_auth!(connection_state);
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let ids = if users.is_empty() {
+            app_state
+                .connected_clients
+                .iter()
+                .map(|entry| entry.key().value)
+                .collect()
+        } else {
+            users.iter().map(|user| user.value).collect::<Vec<_>>()
+        };
+
+        let users = User::find()
+            .filter(user::Column::Id.is_in(ids))
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        Ok(users
+            .iter()
+            .map(|user| user_presence(&app_state, user, user.id == user_id.value))
+            .collect())
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(router, SetPresence, SetIdle, GetPresences)
+}
@@ -0,0 +1,157 @@
+use rpc::{
+    check_auth,
+    models::{
+        auth::UserInfo,
+        common::{APIError, APIResult, RPCMethod as _, RPCNotification as _},
+        markers::{Id, TaggedEntity as _},
+        profiles::{
+            GetUsers, GetUsersPayload, MAX_BIO_LENGTH, MAX_DISPLAY_NAME_LENGTH,
+            MAX_USERS_PER_REQUEST, ProfileError, ProfileUpdated, UpdateProfile,
+            UpdateProfilePayload,
+        },
+    },
+};
+
+use sea_orm::{DbErr, entity::*, query::*};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::common::{DbErrReponseCompat as _, RPCHandle},
+    entity::{
+        media::{self, Entity as Media},
+        user::{self, Entity as User},
+    },
+    register_endpoints,
+};
+
+/// Largest value of an RGB color
+const MAX_ACCENT_COLOR: u32 = 0xFF_FF_FF;
+
+pub fn user_info(user: user::Model) -> UserInfo {
+    UserInfo {
+        id: user.tagged_id(),
+        username: user.username,
+        display_name: user.display_name,
+        avatar: user.avatar_id.map(Id::new),
+        bio: user.bio,
+        accent_color: user.accent_color.map(|color| color as u32),
+    }
+}
+
+/// Trims the text and drops it if nothing is left
+fn normalize(text: Option<String>) -> Option<String> {
+    text.map(|text| text.trim().to_owned())
+        .filter(|text| !text.is_empty())
+}
+
+impl RPCHandle for UpdateProfile {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        UpdateProfilePayload {
+            display_name,
+            avatar,
+            bio,
+            accent_color,
+        }: UpdateProfilePayload,
+    ) -> APIResult<UserInfo, ProfileError> {
+        check_auth!(connection_state);
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let display_name = normalize(display_name);
+        if display_name
+            .as_ref()
+            .is_some_and(|name| name.chars().count() > MAX_DISPLAY_NAME_LENGTH)
+        {
+            return Err(APIError::Err(ProfileError::InvalidDisplayName));
+        }
+
+        let bio = normalize(bio);
+        if bio
+            .as_ref()
+            .is_some_and(|bio| bio.chars().count() > MAX_BIO_LENGTH)
+        {
+            return Err(APIError::Err(ProfileError::BioTooLong));
+        }
+
+        if accent_color.is_some_and(|color| color > MAX_ACCENT_COLOR) {
+            return Err(APIError::Err(ProfileError::InvalidAccentColor));
+        }
+
+        if let Some(avatar) = avatar {
+            let is_valid = Media::find_by_id(avatar.value)
+                .filter(media::Column::UploadedBy.eq(user_id.value))
+                .one(&app_state.db)
+                .await
+                .map_err(DbErr::into_api_error)?
+                .is_some_and(|media| media.mime_type.starts_with("image/"));
+
+            if !is_valid {
+                return Err(APIError::Err(ProfileError::InvalidAvatar));
+            }
+        }
+
+        let user = User::find_by_id(user_id.value)
+            .one(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::ServerError)?;
+
+        let mut model: user::ActiveModel = user.into();
+        model.display_name = Set(display_name);
+        model.avatar_id = Set(avatar.map(|avatar| avatar.value));
+        model.bio = Set(bio);
+        model.accent_color = Set(accent_color.map(|color| color as i32));
+
+        let user = model
+            .update(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        // Connections keep a copy of the user
+        if let Some(connections) = app_state.connected_clients.get(&user_id) {
+            for conn in connections.iter() {
+                conn.write().unwrap().user = Some(user.clone());
+            }
+        }
+
+        let info = user_info(user);
+
+        for (_, writer) in app_state.writers() {
+            ProfileUpdated { user: info.clone() }.notify(&writer).await;
+        }
+
+        Ok(info)
+    }
+}
+
+impl RPCHandle for GetUsers {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        GetUsersPayload { users }: GetUsersPayload,
+    ) -> APIResult<Vec<UserInfo>, ProfileError> {
+        check_auth!(connection_state);
+
+        if users.len() > MAX_USERS_PER_REQUEST {
+            return Err(APIError::Err(ProfileError::TooManyUsers));
+        }
+
+        let users = User::find()
+            .filter(user::Column::Id.is_in(users.iter().map(|user| user.value)))
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        Ok(users.into_iter().map(user_info).collect())
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(router, UpdateProfile, GetUsers)
+}
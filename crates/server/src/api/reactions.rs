@@ -0,0 +1,283 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+use rpc::{
+    check_auth,
+    models::{
+        common::{APIError, APIResult, RPCMethod as _, RPCNotification as _},
+        markers::{Id, UserId},
+        messages::{
+            AddReaction, Emoji, MessageError, Reaction, ReactionPayload, ReactionUpdate,
+            RemoveReaction,
+        },
+        permissions::Permissions,
+    },
+};
+
+use sea_orm::{ConnectionTrait, DbErr, SqlErr, entity::*, query::*};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        common::{DbErrReponseCompat as _, RPCHandle},
+        messages::{StoredChannel, authorize_channel, channel_viewers, find_message},
+    },
+    entity::{
+        custom_emoji::Entity as CustomEmoji,
+        reaction::{self, Entity as ReactionEntity},
+    },
+    register_endpoints,
+};
+
+/// Longest Unicode emoji in characters, sequences joined
+/// with ZWJ take several of them
+const MAX_EMOJI_LENGTH: usize = 16;
+
+/// Different emoji a single message can have
+const MAX_REACTIONS_PER_MESSAGE: usize = 20;
+
+fn emoji_from_model(model: &reaction::Model) -> Option<Emoji> {
+    match (&model.emoji, model.custom_emoji_id) {
+        (Some(emoji), None) => Some(Emoji::Unicode(emoji.clone())),
+        (None, Some(id)) => Some(Emoji::Custom(Id::new(id))),
+        _ => {
+            log::error!("Reaction (ID {}) has malformed emoji", model.id);
+
+            None
+        }
+    }
+}
+
+fn emoji_condition(emoji: &Emoji) -> Condition {
+    match emoji {
+        Emoji::Unicode(emoji) => Condition::all().add(reaction::Column::Emoji.eq(emoji.as_str())),
+        Emoji::Custom(id) => Condition::all().add(reaction::Column::CustomEmojiId.eq(id.value)),
+    }
+}
+
+/// Reactions of the messages grouped by emoji, as seen by the user
+pub async fn message_reactions(
+    db: &impl ConnectionTrait,
+    message_ids: &[i32],
+    user_id: UserId,
+) -> Result<HashMap<i32, Vec<Reaction>>, DbErr> {
+    let reactions = ReactionEntity::find()
+        .filter(reaction::Column::MessageId.is_in(message_ids.iter().copied()))
+        .order_by_asc(reaction::Column::Id)
+        .all(db)
+        .await?;
+
+    let mut result = HashMap::<i32, Vec<Reaction>>::new();
+    for item in reactions {
+        let Some(emoji) = emoji_from_model(&item) else {
+            continue;
+        };
+
+        let me = item.user_id == user_id.value;
+        let reactions = result.entry(item.message_id).or_default();
+
+        match reactions
+            .iter_mut()
+            .find(|reaction| reaction.emoji == emoji)
+        {
+            Some(reaction) => {
+                reaction.count += 1;
+                reaction.me |= me;
+            }
+            None => reactions.push(Reaction {
+                emoji,
+                count: 1,
+                me,
+            }),
+        }
+    }
+
+    Ok(result)
+}
+
+async fn validate_emoji(app_state: &AppState, emoji: &Emoji) -> APIResult<(), MessageError> {
+    let is_valid = match emoji {
+        // Anything that looks like a single symbol rather than text
+        Emoji::Unicode(emoji) => {
+            let length = emoji.chars().count();
+
+            length > 0
+                && length <= MAX_EMOJI_LENGTH
+                && !emoji.is_ascii()
+                && !emoji.chars().any(char::is_whitespace)
+        }
+        Emoji::Custom(id) => CustomEmoji::find_by_id(id.value)
+            .exists(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?,
+    };
+
+    if !is_valid {
+        return Err(APIError::Err(MessageError::InvalidEmoji));
+    }
+
+    Ok(())
+}
+
+async fn broadcast(
+    app_state: &AppState,
+    channel: StoredChannel,
+    update: ReactionUpdate,
+) -> Result<(), DbErr> {
+    for writer in channel_viewers(app_state, channel).await? {
+        update.clone().notify(&writer).await;
+    }
+
+    Ok(())
+}
+
+impl RPCHandle for AddReaction {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        ReactionPayload { message, emoji }: ReactionPayload,
+    ) -> APIResult<(), MessageError> {
+        check_auth!(connection_state);
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let model = find_message(&app_state, message).await?;
+        let channel = StoredChannel::from_model(&model);
+
+        authorize_channel(
+            &app_state,
+            &connection_state,
+            channel.channel(),
+            Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES,
+        )
+        .await?;
+
+        validate_emoji(&app_state, &emoji).await?;
+
+        let existing = ReactionEntity::find()
+            .filter(reaction::Column::MessageId.eq(model.id))
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let existing = existing
+            .iter()
+            .filter_map(|item| Some((item.user_id, emoji_from_model(item)?)))
+            .collect::<Vec<_>>();
+
+        if existing.contains(&(user_id.value, emoji.clone())) {
+            return Ok(());
+        }
+
+        let is_new_emoji = !existing.iter().any(|(_, item)| *item == emoji);
+        let emoji_count = existing
+            .iter()
+            .map(|(_, item)| item)
+            .collect::<HashSet<_>>()
+            .len();
+
+        if is_new_emoji && emoji_count >= MAX_REACTIONS_PER_MESSAGE {
+            return Err(APIError::Err(MessageError::TooManyReactions));
+        }
+
+        let (unicode, custom) = match &emoji {
+            Emoji::Unicode(emoji) => (Some(emoji.clone()), None),
+            Emoji::Custom(id) => (None, Some(id.value)),
+        };
+
+        let inserted = reaction::ActiveModel {
+            message_id: Set(model.id),
+            user_id: Set(user_id.value),
+            emoji: Set(unicode),
+            custom_emoji_id: Set(custom),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&app_state.db)
+        .await;
+
+        match inserted {
+            Ok(_) => {}
+            // The same reaction was added concurrently and already broadcast
+            Err(err) if matches!(err.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+                return Ok(());
+            }
+            Err(err) => return Err(err.into_api_error()),
+        }
+
+        broadcast(
+            &app_state,
+            channel,
+            ReactionUpdate {
+                channel: channel.channel(),
+                message,
+                user: user_id,
+                emoji,
+                added: true,
+            },
+        )
+        .await
+        .map_err(DbErr::into_api_error)
+    }
+}
+
+impl RPCHandle for RemoveReaction {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        ReactionPayload { message, emoji }: ReactionPayload,
+    ) -> APIResult<(), MessageError> {
+        check_auth!(connection_state);
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let model = find_message(&app_state, message).await?;
+        let channel = StoredChannel::from_model(&model);
+
+        authorize_channel(
+            &app_state,
+            &connection_state,
+            channel.channel(),
+            Permissions::VIEW_CHANNEL,
+        )
+        .await?;
+
+        let result = ReactionEntity::delete_many()
+            .filter(reaction::Column::MessageId.eq(model.id))
+            .filter(reaction::Column::UserId.eq(user_id.value))
+            .filter(emoji_condition(&emoji))
+            .exec(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if result.rows_affected == 0 {
+            return Ok(());
+        }
+
+        broadcast(
+            &app_state,
+            channel,
+            ReactionUpdate {
+                channel: channel.channel(),
+                message,
+                user: user_id,
+                emoji,
+                added: false,
+            },
+        )
+        .await
+        .map_err(DbErr::into_api_error)
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(router, AddReaction, RemoveReaction)
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+use rpc::{
+    check_auth,
+    common::Empty,
+    models::{
+        common::{APIError, APIResult, RPCMethod as _, RPCNotification as _},
+        markers::{Id, UserId},
+        messages::MessageError,
+        permissions::Permissions,
+        read_state::{
+            AckChannel, AckChannelPayload, ChannelUnread, GetUnreadSummary, ReadStateUpdate,
+            SetChannelMuted, SetChannelMutedPayload,
+        },
+    },
+    server::RpcWriter,
+};
+
+use sea_orm::{DbErr, entity::*, query::*, sea_query::OnConflict};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        common::{DbErrReponseCompat as _, RPCHandle},
+        mentions::user_mentions,
+        messages::{StoredChannel, authorize_channel, find_message, visible_channels},
+    },
+    entity::{
+        message::{self, Entity as MessageEntity},
+        read_state::{self, Entity as ReadState},
+        user,
+    },
+    register_endpoints,
+};
+
+async fn find_read_state(
+    app_state: &AppState,
+    user_id: UserId,
+    channel: StoredChannel,
+) -> Result<Option<read_state::Model>, DbErr> {
+    let (channel_id, in_group) = channel.columns();
+
+    ReadState::find()
+        .filter(read_state::Column::UserId.eq(user_id.value))
+        .filter(read_state::Column::ChannelId.eq(channel_id))
+        .filter(read_state::Column::InGroup.eq(in_group))
+        .one(&app_state.db)
+        .await
+}
+
+/// Updates the read state or creates it if the user had none in the channel
+async fn save_read_state(
+    app_state: &AppState,
+    user_id: UserId,
+    channel: StoredChannel,
+    last_read: Option<i32>,
+    is_muted: bool,
+) -> Result<(), DbErr> {
+    let (channel_id, in_group) = channel.columns();
+
+    let model = read_state::ActiveModel {
+        user_id: Set(user_id.value),
+        channel_id: Set(channel_id),
+        in_group: Set(in_group),
+        last_read_message_id: Set(last_read),
+        is_muted: Set(is_muted),
+        ..Default::default()
+    };
+
+    ReadState::insert(model)
+        .on_conflict(
+            OnConflict::columns([
+                read_state::Column::UserId,
+                read_state::Column::ChannelId,
+                read_state::Column::InGroup,
+            ])
+            .update_columns([
+                read_state::Column::LastReadMessageId,
+                read_state::Column::IsMuted,
+            ])
+            .to_owned(),
+        )
+        .exec(&app_state.db)
+        .await?;
+
+    Ok(())
+}
+
+/// Writers of all connections of the current user except this one
+fn other_sessions(app_state: &AppState, connection_state: &ConnectionState) -> Vec<RpcWriter> {
+    let (user_id, addr) = {
+        let conn = connection_state.read().unwrap();
+
+        (conn.get_user_id(), conn.addr)
+    };
+
+    let Some(connections) = user_id.and_then(|id| app_state.connected_clients.get(&id)) else {
+        return vec![];
+    };
+
+    connections
+        .iter()
+        .filter_map(|conn| {
+            let conn = conn.read().unwrap();
+
+            (conn.addr != addr).then(|| conn.writer.clone())
+        })
+        .collect()
+}
+
+async fn sync_sessions(
+    app_state: &AppState,
+    connection_state: &ConnectionState,
+    update: ReadStateUpdate,
+) {
+    for writer in other_sessions(app_state, connection_state) {
+        update.clone().notify(&writer).await;
+    }
+}
+
+/// Counts unread messages of other users and the ones mentioning the user
+async fn channel_unread(
+    app_state: &AppState,
+    user: &user::Model,
+    channel: StoredChannel,
+    state: Option<&read_state::Model>,
+) -> Result<ChannelUnread, DbErr> {
+    let last_read = state.and_then(|state| state.last_read_message_id);
+    let is_muted = state.is_some_and(|state| state.is_muted);
+
+    let mut unread = MessageEntity::find()
+        .filter(channel.condition())
+        .filter(message::Column::DeletedAt.is_null())
+        .filter(message::Column::SentBy.ne(user.id));
+
+    if let Some(last_read) = last_read {
+        unread = unread.filter(message::Column::Id.gt(last_read));
+    }
+
+    let unread_count = if is_muted {
+        0
+    } else {
+        unread.clone().count(&app_state.db).await?
+    };
+
+    let mention_count = user_mentions(user.id, unread).count(&app_state.db).await?;
+
+    Ok(ChannelUnread {
+        channel: channel.channel(),
+        last_read: last_read.map(Id::new),
+        unread_count: unread_count as u32,
+        mention_count: mention_count as u32,
+        is_muted,
+    })
+}
+
+impl RPCHandle for GetUnreadSummary {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        _req: Empty,
+    ) -> APIResult<Vec<ChannelUnread>, ()> {
+        check_auth!(connection_state);
+
+        let user = connection_state
+            .read()
+            .unwrap()
+            .user
+            .clone()
+            .expect("We checked auth above");
+
+        let states = ReadState::find()
+            .filter(read_state::Column::UserId.eq(user.id))
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .into_iter()
+            .map(|state| ((state.channel_id, state.in_group), state))
+            .collect::<HashMap<_, _>>();
+
+        let channels = visible_channels(&app_state, &user)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let mut summary = vec![];
+        for channel in channels {
+            let state = states.get(&channel.columns());
+
+            summary.push(
+                channel_unread(&app_state, &user, channel, state)
+                    .await
+                    .map_err(DbErr::into_api_error)?,
+            );
+        }
+
+        Ok(summary)
+    }
+}
+
+impl RPCHandle for AckChannel {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        AckChannelPayload { channel, message }: AckChannelPayload,
+    ) -> APIResult<(), MessageError> {
+        check_auth!(connection_state);
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let channel = authorize_channel(
+            &app_state,
+            &connection_state,
+            channel,
+            Permissions::VIEW_CHANNEL,
+        )
+        .await?;
+
+        let model = find_message(&app_state, message).await?;
+        if StoredChannel::from_model(&model) != channel {
+            return Err(APIError::Err(MessageError::MessageNotFound));
+        }
+
+        let state = find_read_state(&app_state, user_id, channel)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let last_read = state.as_ref().and_then(|state| state.last_read_message_id);
+        let is_muted = state.is_some_and(|state| state.is_muted);
+
+        if last_read.is_some_and(|last_read| last_read >= model.id) {
+            return Ok(());
+        }
+
+        save_read_state(&app_state, user_id, channel, Some(model.id), is_muted)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        sync_sessions(
+            &app_state,
+            &connection_state,
+            ReadStateUpdate {
+                channel: channel.channel(),
+                last_read: Some(message),
+                is_muted,
+            },
+        )
+        .await;
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for SetChannelMuted {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        SetChannelMutedPayload { channel, is_muted }: SetChannelMutedPayload,
+    ) -> APIResult<(), MessageError> {
+        check_auth!(connection_state);
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let channel = authorize_channel(
+            &app_state,
+            &connection_state,
+            channel,
+            Permissions::VIEW_CHANNEL,
+        )
+        .await?;
+
+        let last_read = find_read_state(&app_state, user_id, channel)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .and_then(|state| state.last_read_message_id);
+
+        save_read_state(&app_state, user_id, channel, last_read, is_muted)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        sync_sessions(
+            &app_state,
+            &connection_state,
+            ReadStateUpdate {
+                channel: channel.channel(),
+                last_read: last_read.map(Id::new),
+                is_muted,
+            },
+        )
+        .await;
+
+        Ok(())
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(router, GetUnreadSummary, AckChannel, SetChannelMuted)
+}
@@ -0,0 +1,460 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+use rpc::{
+    check_auth,
+    common::Empty,
+    models::{
+        common::{APIError, APIResult, RPCMethod as _, RPCNotification as _},
+        markers::{Id, UserId},
+        relationships::{
+            AcceptFriendRequest, BlockUser, CancelFriendRequest, DeclineFriendRequest,
+            GetRelationships, Relationship, RelationshipError, RelationshipKind,
+            RelationshipPayload, RelationshipUpdate, RemoveFriend, SendFriendRequest, UnblockUser,
+        },
+    },
+};
+
+use sea_orm::{ConnectionTrait, DbErr, TransactionTrait as _, entity::*, query::*};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        common::{DbErrReponseCompat as _, RPCHandle},
+        presence::user_presence,
+    },
+    entity::{
+        relationship::{self, Entity as RelationshipEntity},
+        user::{self, Entity as User},
+    },
+    register_endpoints,
+};
+
+fn kind_from_db(value: i32) -> Option<RelationshipKind> {
+    match value {
+        0 => Some(RelationshipKind::Friend),
+        1 => Some(RelationshipKind::IncomingRequest),
+        2 => Some(RelationshipKind::OutgoingRequest),
+        3 => Some(RelationshipKind::Blocked),
+        _ => None,
+    }
+}
+
+fn kind_to_db(kind: RelationshipKind) -> i32 {
+    match kind {
+        RelationshipKind::Friend => 0,
+        RelationshipKind::IncomingRequest => 1,
+        RelationshipKind::OutgoingRequest => 2,
+        RelationshipKind::Blocked => 3,
+    }
+}
+
+async fn find_kind(
+    db: &impl ConnectionTrait,
+    user_id: i32,
+    other_id: i32,
+) -> Result<Option<RelationshipKind>, DbErr> {
+    let model = RelationshipEntity::find()
+        .filter(relationship::Column::UserId.eq(user_id))
+        .filter(relationship::Column::OtherId.eq(other_id))
+        .one(db)
+        .await?;
+
+    Ok(model.and_then(|model| kind_from_db(model.kind)))
+}
+
+/// Replaces the relationship of the user to the other user
+async fn set_kind(
+    db: &impl ConnectionTrait,
+    user_id: i32,
+    other_id: i32,
+    kind: Option<RelationshipKind>,
+) -> Result<(), DbErr> {
+    RelationshipEntity::delete_many()
+        .filter(relationship::Column::UserId.eq(user_id))
+        .filter(relationship::Column::OtherId.eq(other_id))
+        .exec(db)
+        .await?;
+
+    if let Some(kind) = kind {
+        relationship::ActiveModel {
+            user_id: Set(user_id),
+            other_id: Set(other_id),
+            kind: Set(kind_to_db(kind)),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Whether either of the users blocked the other
+pub async fn is_blocked(
+    db: &impl ConnectionTrait,
+    user_id: i32,
+    other_id: i32,
+) -> Result<bool, DbErr> {
+    RelationshipEntity::find()
+        .filter(relationship::Column::Kind.eq(kind_to_db(RelationshipKind::Blocked)))
+        .filter(
+            Condition::any()
+                .add(
+                    Condition::all()
+                        .add(relationship::Column::UserId.eq(user_id))
+                        .add(relationship::Column::OtherId.eq(other_id)),
+                )
+                .add(
+                    Condition::all()
+                        .add(relationship::Column::UserId.eq(other_id))
+                        .add(relationship::Column::OtherId.eq(user_id)),
+                ),
+        )
+        .exists(db)
+        .await
+}
+
+/// Users who blocked the given user
+pub async fn blocked_by(db: &impl ConnectionTrait, user_id: i32) -> Result<HashSet<i32>, DbErr> {
+    let relationships = RelationshipEntity::find()
+        .filter(relationship::Column::OtherId.eq(user_id))
+        .filter(relationship::Column::Kind.eq(kind_to_db(RelationshipKind::Blocked)))
+        .all(db)
+        .await?;
+
+    Ok(relationships.into_iter().map(|item| item.user_id).collect())
+}
+
+async fn notify(app_state: &AppState, user_id: i32, other_id: i32, kind: Option<RelationshipKind>) {
+    for writer in app_state.user_writers(Id::new(user_id)) {
+        RelationshipUpdate {
+            user: Id::new(other_id),
+            kind,
+        }
+        .notify(&writer)
+        .await;
+    }
+}
+
+/// Changes the relationship on both sides and lets both users know
+async fn update_pair(
+    app_state: &AppState,
+    user_id: i32,
+    other_id: i32,
+    mine: Option<RelationshipKind>,
+    theirs: Option<RelationshipKind>,
+) -> Result<(), DbErr> {
+    let txn = app_state.db.begin().await?;
+
+    set_kind(&txn, user_id, other_id, mine).await?;
+    set_kind(&txn, other_id, user_id, theirs).await?;
+
+    txn.commit().await?;
+
+    notify(app_state, user_id, other_id, mine).await;
+    notify(app_state, other_id, user_id, theirs).await;
+
+    Ok(())
+}
+
+/// Checks the target of a relationship change and returns
+/// the current relationships of both users to each other
+async fn prepare(
+    app_state: &AppState,
+    connection_state: &ConnectionState,
+    other: UserId,
+) -> APIResult<(i32, Option<RelationshipKind>, Option<RelationshipKind>), RelationshipError> {
+    let user_id = connection_state
+        .read()
+        .unwrap()
+        .get_user_id()
+        .expect("Relationship handlers check auth first");
+
+    if user_id == other {
+        return Err(APIError::Err(RelationshipError::SelfRelationship));
+    }
+
+    let exists = User::find_by_id(other.value)
+        .exists(&app_state.db)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+    if !exists {
+        return Err(APIError::Err(RelationshipError::UserNotFound));
+    }
+
+    let mine = find_kind(&app_state.db, user_id.value, other.value)
+        .await
+        .map_err(DbErr::into_api_error)?;
+    let theirs = find_kind(&app_state.db, other.value, user_id.value)
+        .await
+        .map_err(DbErr::into_api_error)?;
+
+    Ok((user_id.value, mine, theirs))
+}
+
+impl RPCHandle for GetRelationships {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        _req: Empty,
+    ) -> APIResult<Vec<Relationship>, ()> {
+        check_auth!(connection_state);
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let relationships = RelationshipEntity::find()
+            .filter(relationship::Column::UserId.eq(user_id.value))
+            .order_by_asc(relationship::Column::Id)
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        let friend = kind_to_db(RelationshipKind::Friend);
+        let friends = User::find()
+            .filter(
+                user::Column::Id.is_in(
+                    relationships
+                        .iter()
+                        .filter(|item| item.kind == friend)
+                        .map(|item| item.other_id),
+                ),
+            )
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .into_iter()
+            .map(|user| (user.id, user))
+            .collect::<HashMap<_, _>>();
+
+        Ok(relationships
+            .into_iter()
+            .filter_map(|item| {
+                Some(Relationship {
+                    user: Id::new(item.other_id),
+                    kind: kind_from_db(item.kind)?,
+                    presence: friends
+                        .get(&item.other_id)
+                        .map(|user| user_presence(&app_state, user, false)),
+                })
+            })
+            .collect())
+    }
+}
+
+impl RPCHandle for SendFriendRequest {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        RelationshipPayload { user }: RelationshipPayload,
+    ) -> APIResult<RelationshipKind, RelationshipError> {
+        check_auth!(connection_state);
+
+        let (user_id, mine, theirs) = prepare(&app_state, &connection_state, user).await?;
+
+        if mine == Some(RelationshipKind::Blocked) || theirs == Some(RelationshipKind::Blocked) {
+            return Err(APIError::Err(RelationshipError::Blocked));
+        }
+
+        let (mine, theirs) = match mine {
+            Some(RelationshipKind::Friend) => {
+                return Err(APIError::Err(RelationshipError::AlreadyFriends));
+            }
+            Some(RelationshipKind::OutgoingRequest) => {
+                return Ok(RelationshipKind::OutgoingRequest);
+            }
+            // Both users want to be friends
+            Some(RelationshipKind::IncomingRequest) => {
+                (RelationshipKind::Friend, RelationshipKind::Friend)
+            }
+            _ => (
+                RelationshipKind::OutgoingRequest,
+                RelationshipKind::IncomingRequest,
+            ),
+        };
+
+        update_pair(&app_state, user_id, user.value, Some(mine), Some(theirs))
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        Ok(mine)
+    }
+}
+
+impl RPCHandle for AcceptFriendRequest {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        RelationshipPayload { user }: RelationshipPayload,
+    ) -> APIResult<(), RelationshipError> {
+        check_auth!(connection_state);
+
+        let (user_id, mine, _) = prepare(&app_state, &connection_state, user).await?;
+
+        if mine != Some(RelationshipKind::IncomingRequest) {
+            return Err(APIError::Err(RelationshipError::RequestNotFound));
+        }
+
+        update_pair(
+            &app_state,
+            user_id,
+            user.value,
+            Some(RelationshipKind::Friend),
+            Some(RelationshipKind::Friend),
+        )
+        .await
+        .map_err(DbErr::into_api_error)
+    }
+}
+
+impl RPCHandle for DeclineFriendRequest {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        RelationshipPayload { user }: RelationshipPayload,
+    ) -> APIResult<(), RelationshipError> {
+        check_auth!(connection_state);
+
+        let (user_id, mine, _) = prepare(&app_state, &connection_state, user).await?;
+
+        if mine != Some(RelationshipKind::IncomingRequest) {
+            return Err(APIError::Err(RelationshipError::RequestNotFound));
+        }
+
+        update_pair(&app_state, user_id, user.value, None, None)
+            .await
+            .map_err(DbErr::into_api_error)
+    }
+}
+
+impl RPCHandle for CancelFriendRequest {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        RelationshipPayload { user }: RelationshipPayload,
+    ) -> APIResult<(), RelationshipError> {
+        check_auth!(connection_state);
+
+        let (user_id, mine, _) = prepare(&app_state, &connection_state, user).await?;
+
+        if mine != Some(RelationshipKind::OutgoingRequest) {
+            return Err(APIError::Err(RelationshipError::RequestNotFound));
+        }
+
+        update_pair(&app_state, user_id, user.value, None, None)
+            .await
+            .map_err(DbErr::into_api_error)
+    }
+}
+
+impl RPCHandle for RemoveFriend {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        RelationshipPayload { user }: RelationshipPayload,
+    ) -> APIResult<(), RelationshipError> {
+        check_auth!(connection_state);
+
+        let (user_id, mine, _) = prepare(&app_state, &connection_state, user).await?;
+
+        if mine != Some(RelationshipKind::Friend) {
+            return Err(APIError::Err(RelationshipError::NotFriends));
+        }
+
+        update_pair(&app_state, user_id, user.value, None, None)
+            .await
+            .map_err(DbErr::into_api_error)
+    }
+}
+
+impl RPCHandle for BlockUser {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        RelationshipPayload { user }: RelationshipPayload,
+    ) -> APIResult<(), RelationshipError> {
+        check_auth!(connection_state);
+
+        let (user_id, mine, theirs) = prepare(&app_state, &connection_state, user).await?;
+
+        if mine == Some(RelationshipKind::Blocked) {
+            return Ok(());
+        }
+
+        // A block of the other user stays in place
+        if theirs == Some(RelationshipKind::Blocked) {
+            set_kind(
+                &app_state.db,
+                user_id,
+                user.value,
+                Some(RelationshipKind::Blocked),
+            )
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+            notify(
+                &app_state,
+                user_id,
+                user.value,
+                Some(RelationshipKind::Blocked),
+            )
+            .await;
+
+            return Ok(());
+        }
+
+        update_pair(
+            &app_state,
+            user_id,
+            user.value,
+            Some(RelationshipKind::Blocked),
+            None,
+        )
+        .await
+        .map_err(DbErr::into_api_error)
+    }
+}
+
+impl RPCHandle for UnblockUser {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        RelationshipPayload { user }: RelationshipPayload,
+    ) -> APIResult<(), RelationshipError> {
+        check_auth!(connection_state);
+
+        let (user_id, mine, _) = prepare(&app_state, &connection_state, user).await?;
+
+        if mine != Some(RelationshipKind::Blocked) {
+            return Err(APIError::Err(RelationshipError::NotBlocked));
+        }
+
+        set_kind(&app_state.db, user_id, user.value, None)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        notify(&app_state, user_id, user.value, None).await;
+
+        Ok(())
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(
+        router,
+        GetRelationships,
+        SendFriendRequest,
+        AcceptFriendRequest,
+        DeclineFriendRequest,
+        CancelFriendRequest,
+        RemoveFriend,
+        BlockUser,
+        UnblockUser,
+    )
+}
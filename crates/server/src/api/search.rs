@@ -0,0 +1,433 @@
+use chrono::{DateTime, Utc};
+use rpc::{
+    check_auth,
+    models::{
+        common::{APIError, APIResult, RPCMethod as _},
+        messages::{
+            HIGHLIGHT_END, HIGHLIGHT_START, MessageError, SearchMessages, SearchMessagesPayload,
+            SearchResult, SearchResults,
+        },
+        permissions::Permissions,
+    },
+};
+
+use sea_orm::{
+    ConnectionTrait as _, DatabaseConnection, DbBackend, DbErr, FromQueryResult as _, Statement,
+    Value,
+};
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        common::{DbErrReponseCompat as _, RPCHandle},
+        messages::{StoredChannel, authorize_channel, message_from_model, visible_channels},
+    },
+    entity::message,
+    register_endpoints,
+};
+
+/// Full-text index of message texts, created by a migration along with
+/// the Postgres index of the search expression
+const FTS_TABLE: &str = "message_fts";
+
+/// Postgres text search configuration, like FTS5 `unicode61` it
+/// doesn't stem words. Has to match the indexed expression
+const TS_CONFIG: &str = "simple";
+
+const DEFAULT_PAGE_SIZE: u32 = 25;
+const MAX_PAGE_SIZE: u32 = 50;
+
+/// Maximum number of tokens in a snippet
+const SNIPPET_TOKENS: u32 = 16;
+
+/// Turns user input into an FTS5 query. Every word is quoted,
+/// so the input can't use the query syntax and all words have to match
+fn match_expression(query: &str) -> Option<String> {
+    let terms = query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Postgres uses numbered placeholders instead of `?`
+fn numbered_placeholders(sql: &str) -> String {
+    let mut parts = sql.split('?');
+    let mut numbered = parts.next().unwrap_or_default().to_owned();
+
+    for (index, part) in parts.enumerate() {
+        numbered += &format!("${}{part}", index + 1);
+    }
+
+    numbered
+}
+
+fn timestamp_value(timestamp: i64) -> Value {
+    DateTime::<Utc>::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .naive_utc()
+        .into()
+}
+
+/// Searches messages of the given channels
+async fn find_messages(
+    db: &DatabaseConnection,
+    channels: &[StoredChannel],
+    SearchMessagesPayload {
+        query,
+        author,
+        after,
+        before,
+        has_attachments,
+        offset,
+        limit,
+        ..
+    }: SearchMessagesPayload,
+) -> Result<SearchResults, DbErr> {
+    let Some(expression) = match_expression(&query).filter(|_| !channels.is_empty()) else {
+        return Ok(SearchResults {
+            results: vec![],
+            has_more: false,
+        });
+    };
+
+    let limit = match limit {
+        0 => DEFAULT_PAGE_SIZE,
+        limit => Ord::min(limit, MAX_PAGE_SIZE),
+    };
+
+    let backend = db.get_database_backend();
+    let is_postgres = backend == DbBackend::Postgres;
+
+    let (mut sql, mut values): (String, Vec<Value>) = if is_postgres {
+        let options = format!(
+            "StartSel=\"{HIGHLIGHT_START}\", StopSel=\"{HIGHLIGHT_END}\", \
+             MaxWords={SNIPPET_TOKENS}, MinWords={}",
+            SNIPPET_TOKENS / 2
+        );
+
+        // `plainto_tsquery` ignores the query syntax and requires all words to match
+        (
+            format!(
+                "SELECT message.*, ts_headline('{TS_CONFIG}', message.content->>'content', query, ?) AS snippet
+                 FROM message, plainto_tsquery('{TS_CONFIG}', ?) AS query
+                 WHERE to_tsvector('{TS_CONFIG}', message.content->>'content') @@ query
+                 AND message.deleted_at IS NULL"
+            ),
+            vec![options.into(), query.into()],
+        )
+    } else {
+        (
+            format!(
+                "SELECT message.*, snippet({FTS_TABLE}, 0, ?, ?, '…', {SNIPPET_TOKENS}) AS snippet
+                 FROM {FTS_TABLE} JOIN message ON message.id = {FTS_TABLE}.rowid
+                 WHERE {FTS_TABLE} MATCH ? AND message.deleted_at IS NULL"
+            ),
+            vec![
+                HIGHLIGHT_START.into(),
+                HIGHLIGHT_END.into(),
+                expression.into(),
+            ],
+        )
+    };
+
+    let visible = channels
+        .iter()
+        .map(|channel| {
+            let (channel_id, in_group) = channel.columns();
+            values.extend([channel_id.into(), in_group.into()]);
+
+            "(message.channel_id = ? AND message.in_group = ?)"
+        })
+        .collect::<Vec<_>>();
+    sql += &format!(" AND ({})", visible.join(" OR "));
+
+    if let Some(author) = author {
+        sql += " AND message.sent_by = ?";
+        values.push(author.value.into());
+    }
+
+    if let Some(after) = after {
+        sql += " AND message.sent_at >= ?";
+        values.push(timestamp_value(after));
+    }
+
+    if let Some(before) = before {
+        sql += " AND message.sent_at < ?";
+        values.push(timestamp_value(before));
+    }
+
+    if has_attachments {
+        sql += if is_postgres {
+            " AND json_array_length((message.content->'attached_media')::json) > 0"
+        } else {
+            " AND json_array_length(message.content, '$.attached_media') > 0"
+        };
+    }
+
+    // One more row tells whether there are more results
+    if is_postgres {
+        sql += &format!(
+            " ORDER BY ts_rank(to_tsvector('{TS_CONFIG}', message.content->>'content'), query) DESC,
+             message.id DESC LIMIT ? OFFSET ?"
+        );
+    } else {
+        sql += &format!(" ORDER BY {FTS_TABLE}.rank, message.id DESC LIMIT ? OFFSET ?");
+    }
+    values.extend([i64::from(limit + 1).into(), i64::from(offset).into()]);
+
+    if is_postgres {
+        sql = numbered_placeholders(&sql);
+    }
+
+    let rows = db
+        .query_all_raw(Statement::from_sql_and_values(backend, sql, values))
+        .await?;
+
+    let has_more = rows.len() > limit as usize;
+
+    let mut results = vec![];
+    for row in rows.iter().take(limit as usize) {
+        let model = message::Model::from_query_result(row, "")?;
+        let snippet = row.try_get::<String>("", "snippet")?;
+
+        if let Some(message) = message_from_model(model) {
+            results.push(SearchResult { message, snippet });
+        }
+    }
+
+    Ok(SearchResults { results, has_more })
+}
+
+impl RPCHandle for SearchMessages {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        payload: SearchMessagesPayload,
+    ) -> APIResult<SearchResults, MessageError> {
+        check_auth!(connection_state);
+
+        if match_expression(&payload.query).is_none() {
+            return Err(APIError::Err(MessageError::EmptyQuery));
+        }
+
+        let channels = match payload.channel {
+            Some(channel) => vec![
+                authorize_channel(
+                    &app_state,
+                    &connection_state,
+                    channel,
+                    Permissions::VIEW_CHANNEL,
+                )
+                .await?,
+            ],
+            None => {
+                let user = connection_state
+                    .read()
+                    .unwrap()
+                    .user
+                    .clone()
+                    .expect("We checked auth above");
+
+                visible_channels(&app_state, &user)
+                    .await
+                    .map_err(DbErr::into_api_error)?
+            }
+        };
+
+        find_messages(&app_state.db, &channels, payload)
+            .await
+            .map_err(DbErr::into_api_error)
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(router, SearchMessages)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rpc::models::{markers::Id, messages::MessageContent};
+    use sea_orm::{ActiveModelTrait as _, ActiveValue::Set};
+
+    use super::*;
+
+    fn text_channel(id: i32) -> StoredChannel {
+        StoredChannel::Text(Id::new(id))
+    }
+
+    fn payload(query: &str) -> SearchMessagesPayload {
+        SearchMessagesPayload {
+            query: query.to_owned(),
+            channel: None,
+            author: None,
+            after: None,
+            before: None,
+            has_attachments: false,
+            offset: 0,
+            limit: 0,
+        }
+    }
+
+    fn content(text: &str, attachments: &[i32]) -> serde_json::Value {
+        serde_json::to_value(MessageContent {
+            attached_media: attachments.iter().copied().map(Id::new).collect(),
+            reply: None,
+            content: text.to_owned(),
+        })
+        .unwrap()
+    }
+
+    async fn insert_message(
+        db: &DatabaseConnection,
+        channel: StoredChannel,
+        text: &str,
+        attachments: &[i32],
+    ) -> message::Model {
+        let (channel_id, in_group) = channel.columns();
+
+        message::ActiveModel {
+            content: Set(content(text, attachments)),
+            channel_id: Set(channel_id),
+            in_group: Set(in_group),
+            sent_by: Set(1),
+            sent_at: Set(Utc::now().naive_utc()),
+            edited_at: Set(None),
+            deleted_at: Set(None),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap()
+    }
+
+    async fn search(
+        db: &DatabaseConnection,
+        channels: &[StoredChannel],
+        payload: SearchMessagesPayload,
+    ) -> (HashSet<i32>, bool) {
+        let results = find_messages(db, channels, payload).await.unwrap();
+
+        for result in &results.results {
+            assert!(result.snippet.contains(HIGHLIGHT_START));
+            assert!(result.snippet.contains(HIGHLIGHT_END));
+        }
+
+        let ids = results
+            .results
+            .iter()
+            .map(|result| result.message.id.value)
+            .collect();
+
+        (ids, results.has_more)
+    }
+
+    /// Runs against a migrated database of either backend
+    async fn check_search(db: &DatabaseConnection) {
+        let channel = text_channel(1);
+        let other_channel = text_channel(2);
+
+        let plain = insert_message(db, channel, "hello world", &[]).await;
+        let attached = insert_message(db, channel, "hello there", &[1]).await;
+        insert_message(db, channel, "goodbye world", &[]).await;
+        let other = insert_message(db, other_channel, "hello again", &[]).await;
+
+        // Edits and deletions have to reach the index
+        let edited = insert_message(db, channel, "typo", &[]).await;
+        let mut model: message::ActiveModel = edited.clone().into();
+        model.content = Set(content("hello edited", &[]));
+        model.update(db).await.unwrap();
+
+        let deleted = insert_message(db, channel, "hello deleted", &[]).await;
+        let mut model: message::ActiveModel = deleted.into();
+        model.deleted_at = Set(Some(Utc::now().naive_utc()));
+        model.update(db).await.unwrap();
+
+        let all = HashSet::from([plain.id, attached.id, edited.id]);
+
+        assert_eq!(
+            search(db, &[channel], payload("hello")).await,
+            (all.clone(), false)
+        );
+        assert_eq!(
+            search(db, &[channel], payload("hello world")).await,
+            (HashSet::from([plain.id]), false)
+        );
+        assert_eq!(
+            search(db, &[channel, other_channel], payload("HELLO"))
+                .await
+                .0,
+            &all | &HashSet::from([other.id])
+        );
+        assert_eq!(
+            search(db, &[], payload("hello")).await,
+            (HashSet::new(), false)
+        );
+
+        let attachments = SearchMessagesPayload {
+            has_attachments: true,
+            ..payload("hello")
+        };
+        assert_eq!(
+            search(db, &[channel], attachments).await,
+            (HashSet::from([attached.id]), false)
+        );
+
+        // Pages don't overlap and together contain every result
+        let first_page = SearchMessagesPayload {
+            limit: 2,
+            ..payload("hello")
+        };
+        let (first, has_more) = search(db, &[channel], first_page).await;
+        assert_eq!(first.len(), 2);
+        assert!(has_more);
+
+        let second_page = SearchMessagesPayload {
+            limit: 2,
+            offset: 2,
+            ..payload("hello")
+        };
+        let (second, has_more) = search(db, &[channel], second_page).await;
+        assert_eq!(second.len(), 1);
+        assert!(!has_more);
+        assert_eq!(&first | &second, all);
+    }
+
+    #[test]
+    fn numbers_placeholders() {
+        assert_eq!(
+            numbered_placeholders("a = ? AND b IN (?, ?)"),
+            "a = $1 AND b IN ($2, $3)"
+        );
+    }
+
+    #[test]
+    fn quotes_match_terms() {
+        assert_eq!(
+            match_expression(" hello \"world\" "),
+            Some("\"hello\" \"\"\"world\"\"\"".to_owned())
+        );
+        assert_eq!(match_expression("   "), None);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_search() {
+        let db = crate::migration::tests::sqlite_database().await;
+
+        check_search(&db).await;
+    }
+
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn postgres_search() {
+        let Some((_lock, db)) = crate::migration::tests::postgres_database().await else {
+            return;
+        };
+
+        check_search(&db).await;
+    }
+}
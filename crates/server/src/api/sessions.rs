@@ -0,0 +1,339 @@
+use std::collections::HashSet;
+
+use chrono::{Duration, Utc};
+use rpc::{
+    check_auth,
+    common::Empty,
+    models::{
+        audit_log::AuditAction,
+        auth::{
+            ListSessions, Logout, RevokeSession, RevokeSessionPayload, SessionError, SessionInfo,
+            SessionKey, SessionTerminated, SessionTerminationReason,
+        },
+        common::{APIError, APIResult, RPCMethod as _, RPCNotification as _},
+        markers::{TaggedEntity as _, UserId},
+    },
+};
+
+use sea_orm::{DbErr, entity::*, query::*};
+
+use crate::{
+    AppState, ConnectionState, ConnectionStateInner, GlobalRouter,
+    api::{
+        audit_log::AuditEntry,
+        common::{DbErrReponseCompat as _, RPCHandle},
+    },
+    entity::{
+        session::{self, Entity as Session},
+        user::{self, Entity as User},
+    },
+    register_endpoints,
+};
+
+/// How often connections are checked against bans and sessions
+/// changed outside the server, e.g. from the command line
+const SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+fn session_lifetime(app_state: &AppState) -> Duration {
+    Duration::days(app_state.config.session_lifetime_days)
+}
+
+fn sign_session(app_state: &AppState, session: &session::Model) -> SessionKey {
+    app_state.session_keys.read().unwrap().sign(
+        session.user_id,
+        session.id,
+        session.expires_at.and_utc(),
+    )
+}
+
+/// Stores a new session for the user and signs a key for it
+pub async fn create_session(
+    app_state: &AppState,
+    user_id: i32,
+    device_name: String,
+    ip_address: String,
+) -> Result<SessionKey, DbErr> {
+    let now = Utc::now();
+
+    let session = session::ActiveModel {
+        user_id: Set(user_id),
+        device_name: Set(device_name),
+        ip_address: Set(ip_address),
+        created_at: Set(now.naive_utc()),
+        last_used_at: Set(now.naive_utc()),
+        expires_at: Set((now + session_lifetime(app_state)).naive_utc()),
+        ..Default::default()
+    }
+    .insert(&app_state.db)
+    .await?;
+
+    Ok(sign_session(app_state, &session))
+}
+
+/// Prolongs a still valid session and signs a new key for it.
+/// Returns `None` if the session was revoked or has expired
+pub async fn renew_session(
+    app_state: &AppState,
+    session_key: &SessionKey,
+    ip_address: String,
+) -> Result<Option<(session::Model, SessionKey)>, DbErr> {
+    let now = Utc::now();
+
+    let session = Session::find_by_id(session_key.body.session_id)
+        .filter(session::Column::UserId.eq(session_key.body.user_id))
+        .filter(session::Column::ExpiresAt.gt(now.naive_utc()))
+        .one(&app_state.db)
+        .await?;
+
+    let Some(session) = session else {
+        return Ok(None);
+    };
+
+    let mut session: session::ActiveModel = session.into();
+
+    session.ip_address = Set(ip_address);
+    session.last_used_at = Set(now.naive_utc());
+    session.expires_at = Set((now + session_lifetime(app_state)).naive_utc());
+
+    let session = session.update(&app_state.db).await?;
+    let key = sign_session(app_state, &session);
+
+    Ok(Some((session, key)))
+}
+
+/// Notifies and closes all live connections matching the predicate
+pub async fn terminate_connections(
+    app_state: &AppState,
+    reason: SessionTerminationReason,
+    predicate: impl Fn(&ConnectionStateInner) -> bool,
+) {
+    let writers = app_state
+        .connected_clients
+        .iter()
+        .flat_map(|entry| {
+            entry
+                .value()
+                .iter()
+                .filter_map(|conn| {
+                    let conn = conn.read().unwrap();
+
+                    predicate(&conn).then(|| conn.writer.clone())
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    for writer in writers {
+        SessionTerminated { reason }.notify(&writer).await;
+
+        writer.close();
+    }
+}
+
+/// Closes all live connections of the user, e.g. after a ban
+pub async fn terminate_user_connections(
+    app_state: &AppState,
+    user_id: UserId,
+    reason: SessionTerminationReason,
+) {
+    terminate_connections(app_state, reason, |conn| {
+        conn.get_user_id() == Some(user_id)
+    })
+    .await;
+}
+
+/// Periodically closes connections of banned users and of revoked sessions.
+/// The server terminates them right away when it makes the change itself,
+/// this catches bans and revocations made by the command line tool
+pub async fn sweep_connections(app_state: AppState) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = sweep_once(&app_state).await {
+            log::error!("Failed to check live connections: {err}");
+        }
+    }
+}
+
+async fn sweep_once(app_state: &AppState) -> Result<(), DbErr> {
+    let (user_ids, session_ids) = app_state
+        .connected_clients
+        .iter()
+        .flat_map(|entry| {
+            entry
+                .value()
+                .iter()
+                .map(|conn| {
+                    let conn = conn.read().unwrap();
+
+                    (conn.get_user_id(), conn.session_id)
+                })
+                .collect::<Vec<_>>()
+        })
+        .fold(
+            (HashSet::new(), HashSet::new()),
+            |(mut user_ids, mut session_ids), (user_id, session_id)| {
+                user_ids.extend(user_id.map(|id| id.value));
+                session_ids.extend(session_id.map(|id| id.value));
+
+                (user_ids, session_ids)
+            },
+        );
+
+    if user_ids.is_empty() {
+        return Ok(());
+    }
+
+    let banned = User::find()
+        .filter(user::Column::Id.is_in(user_ids))
+        .filter(user::Column::Banned.eq(true))
+        .all(&app_state.db)
+        .await?
+        .into_iter()
+        .filter(user::Model::is_banned)
+        .map(|user| user.id)
+        .collect::<HashSet<_>>();
+
+    let existing = Session::find()
+        .filter(session::Column::Id.is_in(session_ids))
+        .all(&app_state.db)
+        .await?
+        .into_iter()
+        .map(|session| session.id)
+        .collect::<HashSet<_>>();
+
+    terminate_connections(app_state, SessionTerminationReason::Banned, |conn| {
+        conn.get_user_id()
+            .is_some_and(|user_id| banned.contains(&user_id.value))
+    })
+    .await;
+
+    terminate_connections(app_state, SessionTerminationReason::Revoked, |conn| {
+        conn.session_id
+            .is_some_and(|session_id| !existing.contains(&session_id.value))
+    })
+    .await;
+
+    Ok(())
+}
+
+impl RPCHandle for Logout {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        _req: Empty,
+    ) -> APIResult<(), SessionError> {
+        check_auth!(connection_state);
+
+        let state = connection_state.read().unwrap().clone();
+
+        let Some(session_id) = state.session_id else {
+            return Err(APIError::Err(SessionError::NotFound));
+        };
+
+        Session::delete_by_id(session_id.value)
+            .exec(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        // The connection stays open, but it's no longer authenticated
+        state.disconnect(&app_state).await;
+
+        {
+            let mut state = connection_state.write().unwrap();
+
+            state.user = None;
+            state.session_id = None;
+            state.active_voice_channel = None;
+            state.active_stream = None;
+        }
+
+        Ok(())
+    }
+}
+
+impl RPCHandle for ListSessions {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        _req: Empty,
+    ) -> APIResult<Vec<SessionInfo>, ()> {
+        check_auth!(connection_state);
+
+        let (user_id, current_session) = {
+            let state = connection_state.read().unwrap();
+
+            (
+                state.get_user_id().expect("We checked auth above"),
+                state.session_id,
+            )
+        };
+
+        let sessions = Session::find()
+            .filter(session::Column::UserId.eq(user_id.value))
+            .filter(session::Column::ExpiresAt.gt(Utc::now().naive_utc()))
+            .order_by_desc(session::Column::LastUsedAt)
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        Ok(sessions
+            .into_iter()
+            .map(|session| SessionInfo {
+                id: session.tagged_id(),
+                is_current: Some(session.tagged_id()) == current_session,
+
+                device_name: session.device_name,
+                ip_address: session.ip_address,
+
+                created_at: session.created_at.and_utc().timestamp(),
+                last_used_at: session.last_used_at.and_utc().timestamp(),
+            })
+            .collect())
+    }
+}
+
+impl RPCHandle for RevokeSession {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        RevokeSessionPayload { id }: RevokeSessionPayload,
+    ) -> APIResult<(), SessionError> {
+        check_auth!(connection_state);
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let result = Session::delete_many()
+            .filter(session::Column::Id.eq(id.value))
+            .filter(session::Column::UserId.eq(user_id.value))
+            .exec(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        if result.rows_affected == 0 {
+            return Err(APIError::Err(SessionError::NotFound));
+        }
+
+        AuditEntry::new(AuditAction::SessionRevoke)
+            .target(id)
+            .record(&app_state, &connection_state)
+            .await;
+
+        terminate_connections(&app_state, SessionTerminationReason::Revoked, |conn| {
+            conn.session_id == Some(id)
+        })
+        .await;
+
+        Ok(())
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(router, Logout, ListSessions, RevokeSession)
+}
@@ -0,0 +1,115 @@
+use std::time::{Duration, Instant};
+
+use rpc::{
+    check_auth,
+    models::{
+        common::{APIError, APIResult, RPCMethod as _, RPCNotification as _},
+        markers::UserId,
+        messages::{
+            MessageError, StartTyping, StartTypingPayload, TYPING_INTERVAL_SECS, TypingUpdate,
+        },
+        permissions::Permissions,
+    },
+};
+
+use sea_orm::DbErr;
+
+use crate::{
+    AppState, ConnectionState, GlobalRouter,
+    api::{
+        common::{DbErrReponseCompat as _, RPCHandle},
+        messages::{StoredChannel, authorize_channel, channel_viewers},
+    },
+    register_endpoints,
+};
+
+/// Leaves some room for the network delays between client refreshes
+const TYPING_TIMEOUT: Duration = Duration::from_secs(TYPING_INTERVAL_SECS + 3);
+
+async fn broadcast(
+    app_state: &AppState,
+    channel: StoredChannel,
+    user: UserId,
+    is_typing: bool,
+) -> Result<(), DbErr> {
+    for writer in channel_viewers(app_state, channel).await? {
+        TypingUpdate {
+            channel: channel.channel(),
+            user,
+            is_typing,
+        }
+        .notify(&writer)
+        .await;
+    }
+
+    Ok(())
+}
+
+/// Waits until typing of the user expires and lets everyone know.
+/// Refreshes move the deadline, so it's checked again after waking up
+async fn expire_typing(app_state: AppState, channel: StoredChannel, user_id: UserId) {
+    let key = channel.channel();
+
+    while let Some(expires_at) = app_state.channels.typing_expires_at(key, user_id) {
+        if expires_at > Instant::now() {
+            tokio::time::sleep_until(expires_at.into()).await;
+
+            continue;
+        }
+
+        if app_state.channels.stop_typing(key, user_id)
+            && let Err(err) = broadcast(&app_state, channel, user_id, false).await
+        {
+            log::error!("Failed to notify about stopped typing: {err:?}");
+        }
+
+        return;
+    }
+}
+
+impl RPCHandle for StartTyping {
+    async fn handle(
+        app_state: AppState,
+        connection_state: ConnectionState,
+        StartTypingPayload { channel }: StartTypingPayload,
+    ) -> APIResult<(), MessageError> {
+        check_auth!(connection_state);
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let channel = authorize_channel(
+            &app_state,
+            &connection_state,
+            channel,
+            Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES,
+        )
+        .await?;
+
+        let is_new = app_state.channels.start_typing(
+            channel.channel(),
+            user_id,
+            Instant::now() + TYPING_TIMEOUT,
+        );
+
+        // Refreshes only extend the deadline
+        if !is_new {
+            return Ok(());
+        }
+
+        broadcast(&app_state, channel, user_id, true)
+            .await
+            .map_err(DbErr::into_api_error)?;
+
+        tokio::spawn(expire_typing(app_state, channel, user_id));
+
+        Ok(())
+    }
+}
+
+pub fn merge(router: GlobalRouter) -> GlobalRouter {
+    register_endpoints!(router, StartTyping)
+}
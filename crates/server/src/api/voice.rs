@@ -1,82 +1,179 @@
+use std::collections::HashMap;
+
 use rpc::common::Empty;
 use rpc::models::common::{APIError, APIResult, RPCMethod, RPCNotification};
-use rpc::models::markers::TaggedEntity;
+use rpc::models::markers::{ChannelId, TaggedEntity, VoiceChannelId};
+use rpc::models::permissions::Permissions;
 use rpc::models::voice::{
     GetVoiceChannels, JoinVoiceChannel, JoinVoiceChannelError, JoinVoiceChannelPayload,
     LeaveVoiceChannel, UpdateVoiceUserState, VoiceChannelMember, VoiceChannelUpdate,
-    VoiceChannelUpdateMessage, VoiceUserState,
+    VoiceChannelUpdateMessage, VoiceChannelsSnapshot, VoiceUserState,
 };
 use rpc::server::RpcRouter;
 
 use rpc::{self, check_auth, models};
 
 use crate::api::common::{DbErrReponseCompat, RPCHandle};
-use crate::entity::{user::Entity as User, voice_channel::Entity as VoiceChannel};
+use crate::api::permissions::{PermissionScope, PermissionSnapshot, require_permission};
+use crate::entity::{
+    user::{self, Entity as User},
+    voice_channel::{self, Entity as VoiceChannel},
+};
 use crate::{AppState, ConnectionState, VoiceUser, register_endpoints};
 
+use sea_orm::QueryOrder;
 use sea_orm::prelude::*;
+use tokio::sync::mpsc;
+
+/// Queues the update for every connection, including the one that caused it,
+/// so clients see every version
+pub fn broadcast_voice_update(
+    app_state: &AppState,
+    channel_id: VoiceChannelId,
+    message: VoiceChannelUpdateMessage,
+) {
+    app_state.channels.queue_voice_update(channel_id, message);
+}
+
+/// Versions queued updates and sends them one at a time, so every
+/// client receives them in the order of their versions.
+/// Clients that can't keep up are disconnected rather than stalling
+/// everyone else, they get a fresh snapshot once they reconnect
+pub async fn send_voice_updates(
+    app_state: AppState,
+    mut updates: mpsc::UnboundedReceiver<(VoiceChannelId, VoiceChannelUpdateMessage)>,
+) {
+    while let Some((channel_id, message)) = updates.recv().await {
+        let version = app_state.channels.next_voice_version();
+
+        for (user_id, writer) in app_state.writers() {
+            let update = VoiceChannelUpdate {
+                version,
+                channel_id,
+                message: message.clone(),
+            };
+
+            if !update.try_notify(&writer) {
+                log::warn!(
+                    "Dropping connection of user (ID {}), it is not keeping up with voice updates",
+                    user_id.value
+                );
+
+                writer.close();
+            }
+        }
+    }
+}
 
 impl RPCHandle for GetVoiceChannels {
     async fn handle(
         app_state: AppState,
         connection_state: ConnectionState,
         _req: Empty,
-    ) -> APIResult<Vec<models::voice::VoiceChannel>, ()> {
+    ) -> APIResult<VoiceChannelsSnapshot, ()> {
         check_auth!(connection_state);
 
+        // Updates that race with the snapshot get a newer version than this one,
+        // clients apply them on top of the snapshot even if it already has them
+        let version = app_state.channels.voice_version();
+
+        let connected_users = app_state
+            .channels
+            .voice_channels
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect::<HashMap<VoiceChannelId, Vec<VoiceUser>>>();
+
+        let user_id = connection_state
+            .read()
+            .unwrap()
+            .get_user_id()
+            .expect("We checked auth above");
+
+        let snapshot = PermissionSnapshot::load(
+            &app_state.db,
+            &[user_id.value],
+            PermissionScope::AllChannels,
+        )
+        .await
+        .map_err(DbErr::into_api_error)?;
+
         let voice_channels = VoiceChannel::find()
+            .order_by_asc(voice_channel::Column::Position)
+            .order_by_asc(voice_channel::Column::Id)
+            .all(&app_state.db)
+            .await
+            .map_err(DbErr::into_api_error)?
+            .into_iter()
+            .filter(|channel| {
+                snapshot.can_view(user_id.value, ChannelId::Voice(channel.tagged_id()))
+            })
+            .collect::<Vec<_>>();
+
+        let users = User::find()
+            .filter(
+                user::Column::Id.is_in(
+                    connected_users
+                        .values()
+                        .flatten()
+                        .map(|voice_user| voice_user.id.value),
+                ),
+            )
             .all(&app_state.db)
             .await
-            .map_err(DbErr::into_api_error)?;
+            .map_err(DbErr::into_api_error)?
+            .into_iter()
+            .map(|user| (user.id, user))
+            .collect::<HashMap<_, _>>();
 
-        let mut result = Vec::new();
+        let mut channels = Vec::new();
         for channel in voice_channels.into_iter() {
-            let connected_users = app_state.channels.voice_channels.get(&channel.tagged_id());
-
-            let members = {
-                if let Some(voice_users) = connected_users {
-                    let mut members = vec![];
-
-                    for voice_user in voice_users.iter() {
-                        let user = User::find_by_id(voice_user.id.value)
-                            .one(&app_state.db)
-                            .await
-                            .map_err(DbErr::into_api_error)?;
-
-                        let Some(user) = user else {
-                            log::error!(
-                                "Connected (ChannelID: {}) user (ID {}) does not exist in the DB!",
-                                channel.id,
-                                voice_user.id.value,
-                            );
-
-                            continue;
-                        };
-
-                        members.push(VoiceChannelMember {
-                            id: voice_user.id,
-                            name: user.username,
-
-                            is_muted: false,
-                            is_sound_off: false,
-                        });
-                    }
-
-                    members
-                } else {
-                    vec![]
-                }
-            };
+            let voice_users = connected_users
+                .get(&channel.tagged_id())
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+
+            let mut members = vec![];
+            for voice_user in voice_users {
+                let Some(user) = users.get(&voice_user.id.value) else {
+                    log::error!(
+                        "Connected (ChannelID: {}) user (ID {}) does not exist in the DB!",
+                        channel.id,
+                        voice_user.id.value,
+                    );
+
+                    continue;
+                };
+
+                let is_streaming = app_state
+                    .voice_connection(voice_user.id)
+                    .is_some_and(|connection| connection.read().unwrap().active_stream.is_some());
+
+                members.push(VoiceChannelMember {
+                    id: voice_user.id,
+                    name: user.display_name().to_owned(),
+
+                    is_muted: voice_user.is_muted,
+                    is_sound_off: voice_user.is_sound_off,
+                    is_streaming,
+
+                    server_state: app_state.channels.voice_server_state(voice_user.id),
+                });
+            }
 
             let item = models::voice::VoiceChannel {
                 id: channel.tagged_id(),
                 name: channel.name,
+                position: channel.position,
+                category: channel.category,
+                user_limit: channel.max_participants.max(0) as u32,
+                bitrate: channel.bitrate.max(0) as u32,
                 members,
             };
-            result.push(item);
+            channels.push(item);
         }
 
-        Ok(result)
+        Ok(VoiceChannelsSnapshot { version, channels })
     }
 }
 
@@ -124,24 +221,11 @@ impl RPCHandle for UpdateVoiceUserState {
             }
         }
 
-        for value in app_state.connected_clients.iter() {
-            let Some(user_id) = value.read().unwrap().get_user_id() else {
-                continue;
-            };
-
-            if user_id == current_user_id {
-                continue;
-            }
-
-            let writer = value.read().unwrap().writer.clone();
-
-            VoiceChannelUpdate {
-                channel_id: active_channel,
-                message: VoiceChannelUpdateMessage::UserStateUpdated((current_user_id, req)),
-            }
-            .notify(&writer)
-            .await;
-        }
+        broadcast_voice_update(
+            &app_state,
+            active_channel,
+            VoiceChannelUpdateMessage::UserStateUpdated((current_user_id, req)),
+        );
 
         Ok(())
     }
@@ -173,6 +257,10 @@ impl RPCHandle for LeaveVoiceChannel {
                 .expect("We checked auth above")
         };
 
+        app_state
+            .channels
+            .disonnect_user_from_voice_channel(Some(current_user_id), Some(active_channel));
+
         {
             let mut state = connection_state.write().unwrap();
 
@@ -180,24 +268,11 @@ impl RPCHandle for LeaveVoiceChannel {
             state.active_stream = None;
         }
 
-        for value in app_state.connected_clients.iter() {
-            let Some(user_id) = value.read().unwrap().get_user_id() else {
-                continue;
-            };
-
-            if user_id == current_user_id {
-                continue;
-            }
-
-            let writer = value.read().unwrap().writer.clone();
-
-            VoiceChannelUpdate {
-                channel_id: active_channel,
-                message: VoiceChannelUpdateMessage::UserDisconnected(current_user_id),
-            }
-            .notify(&writer)
-            .await;
-        }
+        broadcast_voice_update(
+            &app_state,
+            active_channel,
+            VoiceChannelUpdateMessage::UserDisconnected(current_user_id),
+        );
 
         Ok(())
     }
@@ -211,14 +286,19 @@ impl RPCHandle for JoinVoiceChannel {
     ) -> APIResult<(), JoinVoiceChannelError> {
         check_auth!(connection_state);
 
-        let exists = VoiceChannel::find_by_id(channel_id.value)
-            .exists(&app_state.db)
+        let channel = VoiceChannel::find_by_id(channel_id.value)
+            .one(&app_state.db)
             .await
-            .map_err(DbErr::into_api_error)?;
+            .map_err(DbErr::into_api_error)?
+            .ok_or(APIError::Err(JoinVoiceChannelError::DoesNotExist))?;
 
-        if !exists {
-            return Err(APIError::Err(JoinVoiceChannelError::DoesNotExist));
-        }
+        require_permission(
+            &app_state,
+            &connection_state,
+            Some(ChannelId::Voice(channel_id)),
+            Permissions::CONNECT,
+        )
+        .await?;
 
         let current_user_id = {
             connection_state
@@ -228,6 +308,18 @@ impl RPCHandle for JoinVoiceChannel {
                 .expect("We checked auth above")
         };
 
+        if channel.max_participants > 0 {
+            let participants = app_state
+                .channels
+                .voice_channels
+                .get(&channel_id)
+                .map_or(0, |users| users.len());
+
+            if participants >= channel.max_participants as usize {
+                return Err(APIError::Err(JoinVoiceChannelError::ChannelIsFull));
+            }
+        }
+
         {
             app_state
                 .channels
@@ -244,26 +336,11 @@ impl RPCHandle for JoinVoiceChannel {
             state.active_voice_channel = Some(channel_id);
         }
 
-        for value in app_state.connected_clients.iter() {
-            let user_id = value.read().unwrap().get_user_id();
-
-            let Some(user_id) = user_id else {
-                continue;
-            };
-
-            if user_id == current_user_id {
-                continue;
-            }
-
-            let writer = value.read().unwrap().writer.clone();
-
-            VoiceChannelUpdate {
-                channel_id,
-                message: VoiceChannelUpdateMessage::UserConnected(current_user_id),
-            }
-            .notify(&writer)
-            .await;
-        }
+        broadcast_voice_update(
+            &app_state,
+            channel_id,
+            VoiceChannelUpdateMessage::UserConnected(current_user_id),
+        );
 
         Ok(())
     }
@@ -0,0 +1,579 @@
+use std::{
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result as AResult, bail};
+use chrono::Utc;
+use clap::{Parser, Subcommand, ValueEnum};
+use rpc::models::{
+    channels::VoiceChannelSettings,
+    markers::{ChannelId, Id},
+};
+
+use sea_orm::{
+    ConnectionTrait as _, Database, DatabaseConnection, DbBackend, DbErr, entity::*, query::*,
+};
+use sea_orm_migration::{MigrationStatus, MigratorTrait as _};
+
+use crate::{
+    api::{
+        auth::hash_password,
+        channels::{DEFAULT_BITRATE, delete_channel_rows, validate_name, validate_voice_settings},
+        invites::create_invite,
+        permissions::grant_administrator,
+    },
+    config::Config,
+    connect_database,
+    entity::{
+        session::{self, Entity as Session},
+        text_channel::{self, Entity as TextChannelEntity},
+        user::{self, Entity as User},
+        voice_channel::{self, Entity as VoiceChannelEntity},
+    },
+    migration::{self, Migrator},
+};
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct Args {
+    /// Fields of the config can be overridden with `HAZEL_*` environment
+    /// variables, e.g. `HAZEL_TCP_ADDR` or `HAZEL_MEDIA__PATH`
+    #[arg(short, long, global = true, default_value = "config.toml")]
+    pub config: PathBuf,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the server, used when no command is given
+    Serve,
+    /// Manage user accounts
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Manage text and voice channels
+    #[command(subcommand)]
+    Channel(ChannelCommand),
+    /// Manage invites needed to register on invite only servers
+    #[command(subcommand)]
+    Invite(InviteCommand),
+    /// Maintain the database file
+    #[command(subcommand)]
+    Db(DbCommand),
+    /// Inspect and apply schema migrations
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+    /// Inspect the config
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum UserCommand {
+    /// Create an account, the password is read from stdin unless it's passed
+    Create {
+        username: String,
+        #[arg(long)]
+        password: Option<String>,
+        /// Grant the administrator permission right away
+        #[arg(long)]
+        admin: bool,
+    },
+    /// Set a new password and sign the user out everywhere
+    ResetPassword {
+        username: String,
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Ban the user and sign them out everywhere
+    Ban {
+        username: String,
+        #[arg(long)]
+        reason: Option<String>,
+        /// Length of the ban, it's permanent if not set
+        #[arg(long)]
+        days: Option<i64>,
+    },
+    Unban {
+        username: String,
+    },
+    List {
+        /// Only list users that are banned right now
+        #[arg(long)]
+        banned: bool,
+    },
+    /// Assign a role with the administrator permission to the user
+    Promote {
+        username: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ChannelCommand {
+    List,
+    Create {
+        #[arg(value_enum)]
+        kind: ChannelKind,
+        name: String,
+        #[arg(long)]
+        category: Option<String>,
+        /// Zero means there's no limit, only used by voice channels
+        #[arg(long, default_value_t = 0)]
+        user_limit: u32,
+        /// Only used by voice channels
+        #[arg(long, default_value_t = DEFAULT_BITRATE)]
+        bitrate: u32,
+    },
+    /// Delete the channel along with all its messages
+    Delete {
+        #[arg(value_enum)]
+        kind: ChannelKind,
+        id: i32,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ChannelKind {
+    Text,
+    Voice,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum InviteCommand {
+    /// Create an invite and print its code
+    Create {
+        /// Number of accounts it can register, unlimited if not set
+        #[arg(long)]
+        max_uses: Option<u32>,
+        /// Days until it expires, it never expires if not set
+        #[arg(long)]
+        days: Option<u32>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommand {
+    /// Write a consistent copy of an SQLite database, safe while the server runs
+    Backup { path: PathBuf },
+    /// Reclaim unused space of the database
+    Vacuum,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MigrateCommand {
+    /// List migrations and whether they were applied
+    Status,
+    /// Apply pending migrations
+    Up {
+        /// Only apply this many, all of them if not set
+        #[arg(short, long)]
+        steps: Option<u32>,
+    },
+    /// Roll back applied migrations, newest first
+    Down {
+        #[arg(short, long, default_value_t = 1)]
+        steps: u32,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Parse and validate the config without starting the server
+    Check,
+}
+
+/// Runs every command except `serve`. Commands work with the database
+/// directly, so clients connected to a running server only see the
+/// changes after they reconnect
+pub async fn run(command: Command, config_path: &Path) -> AResult<()> {
+    let config = Config::load(config_path)?;
+    config.validate()?;
+
+    match command {
+        Command::Config(ConfigCommand::Check) => {
+            println!("{} is valid", config_path.display());
+
+            return Ok(());
+        }
+        // The schema check would refuse to touch an outdated database
+        Command::Migrate(command) => {
+            let db = Database::connect(&config.database_url)
+                .await
+                .with_context(|| format!("Failed to open the database {}", config.database_url))?;
+
+            return run_migrate(&db, command).await;
+        }
+        _ => {}
+    }
+
+    let db = connect_database(&config)
+        .await
+        .with_context(|| format!("Failed to open the database {}", config.database_url))?;
+
+    match command {
+        Command::Serve | Command::Config(_) | Command::Migrate(_) => {
+            unreachable!("Not a database command")
+        }
+        Command::User(command) => run_user(&db, command).await,
+        Command::Channel(command) => run_channel(&db, command).await,
+        Command::Invite(command) => run_invite(&db, command).await,
+        Command::Db(command) => run_db(&db, command).await,
+    }
+}
+
+fn read_password(password: Option<String>) -> AResult<String> {
+    let password = match password {
+        Some(password) => password,
+        None => {
+            eprint!("Password: ");
+            std::io::stderr().flush()?;
+
+            let mut line = String::new();
+            std::io::stdin()
+                .read_line(&mut line)
+                .context("Failed to read the password")?;
+
+            line.trim_end_matches(['\r', '\n']).to_owned()
+        }
+    };
+
+    if password.is_empty() {
+        bail!("Password can't be empty");
+    }
+
+    Ok(password)
+}
+
+async fn find_user(db: &DatabaseConnection, username: &str) -> AResult<user::Model> {
+    User::find()
+        .filter(user::Column::Username.eq(username))
+        .one(db)
+        .await?
+        .with_context(|| format!("User {username} does not exist"))
+}
+
+async fn revoke_sessions(db: &DatabaseConnection, user_id: i32) -> Result<u64, DbErr> {
+    let result = Session::delete_many()
+        .filter(session::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected)
+}
+
+async fn run_user(db: &DatabaseConnection, command: UserCommand) -> AResult<()> {
+    match command {
+        UserCommand::Create {
+            username,
+            password,
+            admin,
+        } => {
+            let username = username.trim().to_owned();
+            if username.is_empty() {
+                bail!("Username can't be empty");
+            }
+
+            let exists = User::find()
+                .filter(user::Column::Username.eq(&username))
+                .exists(db)
+                .await?;
+
+            if exists {
+                bail!("User {username} already exists");
+            }
+
+            let password = read_password(password)?;
+
+            let user = user::ActiveModel {
+                username: Set(username),
+                password: Set(hash_password(&password)),
+                banned: Set(false),
+                ban_reason: Set(None),
+                banned_until: Set(None),
+                created_at: Set(Utc::now().naive_utc()),
+                ..Default::default()
+            }
+            .insert(db)
+            .await?;
+
+            if admin {
+                grant_administrator(db, user.id).await?;
+            }
+
+            println!("Created user {} with ID {}", user.username, user.id);
+        }
+        UserCommand::ResetPassword { username, password } => {
+            let user = find_user(db, &username).await?;
+            let password = read_password(password)?;
+
+            let user_id = user.id;
+
+            let mut user: user::ActiveModel = user.into();
+            user.password = Set(hash_password(&password));
+            user.update(db).await?;
+
+            let revoked = revoke_sessions(db, user_id).await?;
+
+            println!("Password of {username} was reset, {revoked} sessions were revoked");
+        }
+        UserCommand::Ban {
+            username,
+            reason,
+            days,
+        } => {
+            let user = find_user(db, &username).await?;
+            let user_id = user.id;
+
+            let banned_until = match days {
+                Some(days) if days <= 0 => bail!("Ban should last at least a day"),
+                Some(days) => Some((Utc::now() + chrono::Duration::days(days)).naive_utc()),
+                None => None,
+            };
+
+            let mut user: user::ActiveModel = user.into();
+            user.banned = Set(true);
+            user.ban_reason = Set(reason);
+            user.banned_until = Set(banned_until);
+            user.update(db).await?;
+
+            revoke_sessions(db, user_id).await?;
+
+            match banned_until {
+                Some(until) => println!("Banned {username} until {until}"),
+                None => println!("Banned {username} permanently"),
+            }
+        }
+        UserCommand::Unban { username } => {
+            let user = find_user(db, &username).await?;
+
+            let mut user: user::ActiveModel = user.into();
+            user.banned = Set(false);
+            user.ban_reason = Set(None);
+            user.banned_until = Set(None);
+            user.update(db).await?;
+
+            println!("Unbanned {username}");
+        }
+        UserCommand::List { banned } => {
+            let users = User::find().order_by_asc(user::Column::Id).all(db).await?;
+
+            for user in users {
+                let is_banned = user.is_banned();
+
+                if banned && !is_banned {
+                    continue;
+                }
+
+                let status = if is_banned { "banned" } else { "" };
+                println!(
+                    "{:>6}  {:<32}  {}  {status}",
+                    user.id,
+                    user.username,
+                    user.created_at.format("%Y-%m-%d")
+                );
+            }
+        }
+        UserCommand::Promote { username } => {
+            let user = find_user(db, &username).await?;
+
+            if grant_administrator(db, user.id).await? {
+                println!("{username} is now an administrator");
+            } else {
+                println!("{username} is already an administrator");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn next_position(db: &DatabaseConnection, kind: ChannelKind) -> Result<i32, DbErr> {
+    let last = match kind {
+        ChannelKind::Text => TextChannelEntity::find()
+            .order_by_desc(text_channel::Column::Position)
+            .one(db)
+            .await?
+            .map(|channel| channel.position),
+        ChannelKind::Voice => VoiceChannelEntity::find()
+            .order_by_desc(voice_channel::Column::Position)
+            .one(db)
+            .await?
+            .map(|channel| channel.position),
+    };
+
+    Ok(last.map_or(0, |position| position + 1))
+}
+
+async fn run_channel(db: &DatabaseConnection, command: ChannelCommand) -> AResult<()> {
+    match command {
+        ChannelCommand::List => {
+            let text_channels = TextChannelEntity::find()
+                .order_by_asc(text_channel::Column::Position)
+                .all(db)
+                .await?;
+
+            let voice_channels = VoiceChannelEntity::find()
+                .order_by_asc(voice_channel::Column::Position)
+                .all(db)
+                .await?;
+
+            for channel in text_channels {
+                println!(
+                    "text   {:>6}  {}  {}",
+                    channel.id,
+                    channel.name,
+                    channel.category.unwrap_or_default()
+                );
+            }
+
+            for channel in voice_channels {
+                println!(
+                    "voice  {:>6}  {}  {}",
+                    channel.id,
+                    channel.name,
+                    channel.category.unwrap_or_default()
+                );
+            }
+        }
+        ChannelCommand::Create {
+            kind,
+            name,
+            category,
+            user_limit,
+            bitrate,
+        } => {
+            let position = next_position(db, kind).await?;
+
+            let id = match kind {
+                ChannelKind::Text => {
+                    text_channel::ActiveModel {
+                        name: Set(validate_name(&name)?),
+                        position: Set(position),
+                        category: Set(category),
+                        created_at: Set(Utc::now().naive_utc()),
+                        ..Default::default()
+                    }
+                    .insert(db)
+                    .await?
+                    .id
+                }
+                ChannelKind::Voice => {
+                    let settings = VoiceChannelSettings {
+                        name,
+                        position,
+                        category,
+                        user_limit,
+                        bitrate,
+                    };
+
+                    voice_channel::ActiveModel {
+                        name: Set(validate_voice_settings(&settings)?),
+                        position: Set(settings.position),
+                        category: Set(settings.category),
+                        max_participants: Set(Ord::min(settings.user_limit, i32::MAX as u32) as i32),
+                        bitrate: Set(settings.bitrate as i32),
+                        created_at: Set(Utc::now().naive_utc()),
+                        ..Default::default()
+                    }
+                    .insert(db)
+                    .await?
+                    .id
+                }
+            };
+
+            println!("Created channel with ID {id}");
+        }
+        ChannelCommand::Delete { kind, id } => {
+            let channel = match kind {
+                ChannelKind::Text => ChannelId::Text(Id::new(id)),
+                ChannelKind::Voice => ChannelId::Voice(Id::new(id)),
+            };
+
+            if !delete_channel_rows(db, channel).await? {
+                bail!("Channel {id} does not exist");
+            }
+
+            println!("Deleted channel {id}");
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_migrate(db: &DatabaseConnection, command: MigrateCommand) -> AResult<()> {
+    match command {
+        MigrateCommand::Status => {
+            for (name, status) in migration::status(db).await? {
+                let status = match status {
+                    MigrationStatus::Applied => "applied",
+                    MigrationStatus::Pending => "pending",
+                };
+
+                println!("{status:<8}  {name}");
+            }
+        }
+        MigrateCommand::Up { steps } => {
+            Migrator::up(db, steps).await?;
+
+            println!("Database is up to date");
+        }
+        MigrateCommand::Down { steps } => {
+            Migrator::down(db, Some(steps)).await?;
+
+            println!("Rolled back {steps} migrations");
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_invite(db: &DatabaseConnection, command: InviteCommand) -> AResult<()> {
+    match command {
+        InviteCommand::Create { max_uses, days } => {
+            if max_uses == Some(0) {
+                bail!("Invite should allow at least one use");
+            }
+
+            if days == Some(0) {
+                bail!("Invite should last at least a day");
+            }
+
+            let invite = create_invite(db, None, max_uses, days).await?;
+
+            println!("{}", invite.code);
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_db(db: &DatabaseConnection, command: DbCommand) -> AResult<()> {
+    match command {
+        DbCommand::Backup { path } => {
+            if db.get_database_backend() != DbBackend::Sqlite {
+                bail!("Only SQLite databases can be backed up, use pg_dump for Postgres");
+            }
+
+            if path.exists() {
+                bail!("{} already exists", path.display());
+            }
+
+            let path = path.to_str().context("Backup path is not valid UTF-8")?;
+
+            // `VACUUM INTO` doesn't accept bound parameters
+            db.execute_unprepared(&format!("VACUUM INTO '{}'", path.replace('\'', "''")))
+                .await?;
+
+            println!("Database was backed up to {path}");
+        }
+        DbCommand::Vacuum => {
+            db.execute_unprepared("VACUUM").await?;
+
+            println!("Database was vacuumed");
+        }
+    }
+
+    Ok(())
+}
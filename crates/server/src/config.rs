@@ -1,3 +1,6 @@
+use std::{collections::HashMap, net::ToSocketAddrs as _, path::Path};
+
+use anyhow::{Context as _, Result as AResult, bail};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -12,16 +15,378 @@ pub struct TextChannel {
     pub name: String,
 }
 
+/// Prefix of environment variables overriding config fields
+const ENV_PREFIX: &str = "HAZEL_";
+
+fn default_database_url() -> String {
+    "sqlite://db.sqlite?mode=rwc".into()
+}
+
+fn default_auto_migrate() -> bool {
+    true
+}
+
+fn default_session_keys_path() -> String {
+    "session_keys.toml".into()
+}
+
+fn default_session_lifetime_days() -> i64 {
+    30
+}
+
+fn default_audit_log_retention_days() -> i64 {
+    90
+}
+
+fn default_media_path() -> String {
+    "media".into()
+}
+
+fn default_max_file_size() -> u64 {
+    25 * 1024 * 1024
+}
+
+fn default_user_quota() -> u64 {
+    1024 * 1024 * 1024
+}
+
+fn default_server_quota() -> u64 {
+    20 * 1024 * 1024 * 1024
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MediaConfig {
+    /// Directory where uploaded files are stored
+    #[serde(default = "default_media_path")]
+    pub path: String,
+    /// Largest file that can be uploaded, in bytes
+    #[serde(default = "default_max_file_size")]
+    pub max_file_size: u64,
+    /// Total size of files a single user can upload, in bytes
+    #[serde(default = "default_user_quota")]
+    pub user_quota: u64,
+    /// Total size of files all users can upload, in bytes
+    #[serde(default = "default_server_quota")]
+    pub server_quota: u64,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        Self {
+            path: default_media_path(),
+            max_file_size: default_max_file_size(),
+            user_quota: default_user_quota(),
+            server_quota: default_server_quota(),
+        }
+    }
+}
+
+/// Token bucket limit of an RPC method
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct RateLimit {
+    /// Requests that can be made at once
+    pub burst: u32,
+    /// Requests restored every minute
+    pub per_minute: u32,
+}
+
+impl RateLimit {
+    pub fn per_second(self) -> f64 {
+        self.per_minute as f64 / 60.
+    }
+}
+
+fn default_max_login_failures() -> u32 {
+    5
+}
+
+fn default_base_lockout_secs() -> u64 {
+    30
+}
+
+fn default_max_lockout_secs() -> u64 {
+    60 * 60
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoginLockoutConfig {
+    /// Failed logins from an address or to an account before it's locked out
+    #[serde(default = "default_max_login_failures")]
+    pub max_failures: u32,
+    /// Lockout after reaching `max_failures`, doubled by every next failure
+    #[serde(default = "default_base_lockout_secs")]
+    pub base_lockout_secs: u64,
+    /// Longest lockout, failures older than this are forgotten
+    #[serde(default = "default_max_lockout_secs")]
+    pub max_lockout_secs: u64,
+}
+
+impl Default for LoginLockoutConfig {
+    fn default() -> Self {
+        Self {
+            max_failures: default_max_login_failures(),
+            base_lockout_secs: default_base_lockout_secs(),
+            max_lockout_secs: default_max_lockout_secs(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
     /// TCP address and port
     pub tcp_addr: String,
     /// UDP address and port
     pub udp_addr: String,
+    /// Address of the HTTP listener serving Prometheus metrics on `/metrics`
+    /// and a health check on `/healthz`. It has no authentication, so it
+    /// should only be reachable locally. Disabled if it's not set
+    #[serde(default)]
+    pub metrics_addr: Option<String>,
+
+    /// Connection URL of the database, either `sqlite://` or `postgres://`.
+    /// Postgres requires the `postgres` feature
+    #[serde(default = "default_database_url")]
+    pub database_url: String,
+    /// Apply pending migrations on start, otherwise the server refuses to
+    /// start until `hazel-server migrate up` is run
+    #[serde(default = "default_auto_migrate")]
+    pub auto_migrate: bool,
 
-    /// List of text channels that will be present on the server
+    /// List of text channels that will be present on the server.
+    /// Missing ones are created on start and when the config is reloaded
+    #[serde(default)]
     pub text_channels: Vec<TextChannel>,
 
     /// List of voice channels that will be present on the server
-    pub voice_channels: Vec<TextChannel>,
+    #[serde(default)]
+    pub voice_channels: Vec<VoiceChannel>,
+
+    /// Message of the day shown to users, can be changed at runtime
+    #[serde(default)]
+    pub motd: Option<String>,
+
+    /// Secret used to sign session keys. If it's not set, keys are
+    /// generated into `session_keys_path` and can be rotated at runtime
+    #[serde(default)]
+    pub session_secret: Option<String>,
+    /// File with generated session signing keys
+    #[serde(default = "default_session_keys_path")]
+    pub session_keys_path: String,
+    /// How long a session stays valid since it was last used
+    #[serde(default = "default_session_lifetime_days")]
+    pub session_lifetime_days: i64,
+
+    /// New accounts can only be registered with an invite, created with
+    /// `hazel-server invite create` or by users who can create invites
+    #[serde(default)]
+    pub invite_only: bool,
+
+    /// How long audit log entries are kept, 0 keeps them forever
+    #[serde(default = "default_audit_log_retention_days")]
+    pub audit_log_retention_days: i64,
+
+    /// Storage of uploaded files
+    #[serde(default)]
+    pub media: MediaConfig,
+
+    /// Limits of RPC methods by name, replacing the built-in ones.
+    /// Requests are counted per user, or per address before logging in
+    #[serde(default)]
+    pub rate_limits: HashMap<String, RateLimit>,
+    /// Lockout of addresses and accounts with repeated failed logins
+    #[serde(default)]
+    pub login_lockout: LoginLockoutConfig,
+}
+
+/// Parses the value as TOML, so numbers, booleans, arrays and inline
+/// tables can be passed. Anything else is taken as a plain string
+fn parse_env_value(value: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {value}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_owned()))
+}
+
+/// Fields that are always strings, even if the value looks like a number,
+/// e.g. a numeric secret. Nested fields are joined with a dot
+const STRING_FIELDS: &[&str] = &[
+    "tcp_addr",
+    "udp_addr",
+    "metrics_addr",
+    "database_url",
+    "motd",
+    "session_secret",
+    "session_keys_path",
+    "media.path",
+];
+
+/// Tables whose keys aren't field names, like method names in `rate_limits`
+const CASE_SENSITIVE_TABLES: &[&str] = &["rate_limits"];
+
+/// Keys are lowercased to match field names. Keys of case-sensitive tables
+/// keep their case, or take the one from the file if it only differs in case
+fn resolve_key(table: &toml::Table, parents: &[String], key: &str) -> String {
+    if !CASE_SENSITIVE_TABLES.contains(&parents.join(".").as_str()) {
+        return key.to_lowercase();
+    }
+
+    table
+        .keys()
+        .find(|existing| existing.eq_ignore_ascii_case(key))
+        .cloned()
+        .unwrap_or_else(|| key.to_owned())
+}
+
+/// `HAZEL_TCP_ADDR` overrides `tcp_addr`, fields of nested tables are
+/// separated with two underscores, e.g. `HAZEL_MEDIA__MAX_FILE_SIZE`
+fn apply_env_overrides(
+    table: &mut toml::Table,
+    vars: impl Iterator<Item = (String, String)>,
+) -> AResult<()> {
+    for (name, value) in vars {
+        let Some(path) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+
+        let mut keys = path.split("__").collect::<Vec<_>>();
+        let Some(field) = keys.pop().filter(|field| !field.is_empty()) else {
+            continue;
+        };
+
+        let mut table = &mut *table;
+        let mut parents = vec![];
+        for key in keys {
+            let key = resolve_key(table, &parents, key);
+
+            table = table
+                .entry(key.clone())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .with_context(|| format!("{name} overrides `{key}` which is not a table"))?;
+
+            parents.push(key);
+        }
+
+        let field = resolve_key(table, &parents, field);
+        parents.push(field.clone());
+        let is_string_field = STRING_FIELDS.contains(&parents.join(".").as_str());
+
+        // Values that are strings in the file stay strings too
+        let value = match table.get(&field) {
+            Some(toml::Value::String(_)) => toml::Value::String(value),
+            _ if is_string_field => toml::Value::String(value),
+            _ => parse_env_value(&value),
+        };
+
+        table.insert(field, value);
+    }
+
+    Ok(())
+}
+
+impl Config {
+    /// Reads the config file and applies `HAZEL_*` environment variables
+    /// on top of it. The file can be missing if the variables provide
+    /// every required field
+    pub fn load(path: &Path) -> AResult<Self> {
+        let mut table = if path.exists() {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+
+            toml::from_str(&content)
+                .with_context(|| format!("{} is not valid TOML", path.display()))?
+        } else {
+            log::warn!(
+                "{} does not exist, using environment variables only",
+                path.display()
+            );
+
+            toml::Table::new()
+        };
+
+        apply_env_overrides(&mut table, std::env::vars())?;
+
+        // Usernames can be claimed by anyone before they are registered
+        if table.contains_key("admins") {
+            bail!(
+                "`admins` is no longer supported, grant the administrator role \
+                 with `hazel-server user promote <username>` instead"
+            );
+        }
+
+        toml::Value::Table(table)
+            .try_into()
+            .with_context(|| format!("Config {} is invalid", path.display()))
+    }
+
+    /// Catches mistakes that deserialization doesn't
+    pub fn validate(&self) -> AResult<()> {
+        for (name, addr) in [("tcp_addr", &self.tcp_addr), ("udp_addr", &self.udp_addr)] {
+            addr.to_socket_addrs()
+                .with_context(|| format!("`{name}` is not a valid address: {addr}"))?;
+        }
+
+        if let Some(addr) = &self.metrics_addr {
+            addr.to_socket_addrs()
+                .with_context(|| format!("`metrics_addr` is not a valid address: {addr}"))?;
+        }
+
+        // The backend is picked by the scheme of the URL
+        let scheme = self
+            .database_url
+            .split_once("://")
+            .map(|(scheme, _)| scheme);
+        match scheme {
+            Some("sqlite") if cfg!(feature = "sqlite") => {}
+            Some("postgres" | "postgresql") if cfg!(feature = "postgres") => {}
+            Some(scheme @ ("sqlite" | "postgres" | "postgresql")) => {
+                bail!("`database_url` uses {scheme}, but the server was built without its feature")
+            }
+            _ => bail!("`database_url` should start with sqlite:// or postgres://"),
+        }
+
+        let channel_names = self
+            .text_channels
+            .iter()
+            .map(|channel| &channel.name)
+            .chain(self.voice_channels.iter().map(|channel| &channel.name));
+
+        for name in channel_names {
+            if name.trim().is_empty() {
+                bail!("Channel names in `text_channels` and `voice_channels` can't be empty");
+            }
+        }
+
+        if let Some(secret) = &self.session_secret
+            && secret.len() < 16
+        {
+            bail!("`session_secret` should be at least 16 characters long");
+        }
+
+        if self.session_lifetime_days <= 0 {
+            bail!("`session_lifetime_days` should be positive");
+        }
+
+        if self.audit_log_retention_days < 0 {
+            bail!("`audit_log_retention_days` can't be negative");
+        }
+
+        if self.media.path.is_empty() {
+            bail!("`media.path` can't be empty");
+        }
+
+        if self.media.max_file_size > self.media.user_quota {
+            bail!("`media.max_file_size` is larger than `media.user_quota`");
+        }
+
+        if self.media.user_quota > self.media.server_quota {
+            bail!("`media.user_quota` is larger than `media.server_quota`");
+        }
+
+        if self.login_lockout.max_failures == 0 {
+            bail!("`login_lockout.max_failures` should be at least 1");
+        }
+
+        Ok(())
+    }
 }
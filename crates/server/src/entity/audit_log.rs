@@ -0,0 +1,28 @@
+use rpc::{models::markers, tag_entity};
+
+use sea_orm::entity::prelude::*;
+
+/// Administrative or moderation action, see `api::audit_log`
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "audit_log")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub actor_id: i32,
+    #[sea_orm(indexed)]
+    pub action: i32,
+    pub target_kind: Option<i32>,
+    #[sea_orm(indexed)]
+    pub target_id: Option<i32>,
+    pub reason: Option<String>,
+    pub before: Option<Json>,
+    pub after: Option<Json>,
+    #[sea_orm(indexed)]
+    pub created_at: DateTime,
+}
+
+tag_entity!(Model, markers::AuditLogEntry);
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,20 @@
+use sea_orm::entity::prelude::*;
+
+/// Allows or denies permissions of a role in a specific channel.
+/// Exactly one of the channel columns is set
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "channel_permission_override")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub text_channel_id: Option<i32>,
+    #[sea_orm(indexed)]
+    pub voice_channel_id: Option<i32>,
+    pub role_id: i32,
+    pub allow: i64,
+    pub deny: i64,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,24 @@
+use rpc::{models::markers, tag_entity};
+
+use sea_orm::entity::prelude::*;
+
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "conversation")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub name: Option<String>,
+    pub owner_id: Option<i32>,
+    pub is_direct: bool,
+    /// `"{lower user id}:{higher user id}"` for direct conversations,
+    /// so there's only one per pair of users
+    #[sea_orm(unique)]
+    pub direct_key: Option<String>,
+    pub created_at: DateTime,
+    pub last_activity_at: DateTime,
+}
+
+tag_entity!(Model, markers::Group);
+
+impl ActiveModelBehavior for ActiveModel {}
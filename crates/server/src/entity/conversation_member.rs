@@ -0,0 +1,16 @@
+use sea_orm::entity::prelude::*;
+
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "conversation_member")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub conversation_id: i32,
+    #[sea_orm(indexed)]
+    pub user_id: i32,
+    pub joined_at: DateTime,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
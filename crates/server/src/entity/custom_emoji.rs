@@ -0,0 +1,20 @@
+use rpc::{models::markers, tag_entity};
+
+use sea_orm::entity::prelude::*;
+
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "custom_emoji")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub name: String,
+    pub media_id: i32,
+    pub created_by: i32,
+    pub created_at: DateTime,
+}
+
+tag_entity!(Model, markers::Emoji);
+
+impl ActiveModelBehavior for ActiveModel {}
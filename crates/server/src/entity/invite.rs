@@ -0,0 +1,24 @@
+use rpc::{models::markers, tag_entity};
+
+use sea_orm::entity::prelude::*;
+
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "invite")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub code: String,
+    /// Not set for invites created from the command line
+    pub created_by: Option<i32>,
+    /// Unlimited if not set
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    pub expires_at: Option<DateTime>,
+    pub created_at: DateTime,
+}
+
+tag_entity!(Model, markers::Invite);
+
+impl ActiveModelBehavior for ActiveModel {}
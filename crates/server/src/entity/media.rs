@@ -0,0 +1,26 @@
+use rpc::{models::markers, tag_entity};
+
+use sea_orm::entity::prelude::*;
+
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "media")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub file_name: String,
+    pub mime_type: String,
+    /// Size in bytes
+    pub size: i64,
+    /// Hex encoded SHA-256 of the content, identical files share the stored copy
+    #[sea_orm(indexed)]
+    pub sha256: String,
+    #[sea_orm(indexed)]
+    pub uploaded_by: i32,
+    pub uploaded_at: DateTime,
+    pub has_thumbnail: bool,
+}
+
+tag_entity!(Model, markers::Media);
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,19 @@
+use sea_orm::entity::prelude::*;
+
+/// A user mentioned in a message. `role_id` and `is_here` tell
+/// how the user was mentioned, a direct mention has neither
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "mention")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub message_id: i32,
+    #[sea_orm(indexed)]
+    pub user_id: i32,
+    pub role_id: Option<i32>,
+    pub is_here: bool,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -9,9 +9,15 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub content: Json,
+    /// ID of a text channel or of a conversation if `in_group` is set
     pub channel_id: i32,
+    #[sea_orm(default_value = false)]
+    pub in_group: bool,
     pub sent_by: i32,
     pub sent_at: DateTime,
+    pub edited_at: Option<DateTime>,
+    /// Deleted messages are kept, but hidden from everyone
+    pub deleted_at: Option<DateTime>,
 }
 
 tag_entity!(Model, markers::Message);
@@ -0,0 +1,18 @@
+use sea_orm::entity::prelude::*;
+
+/// Content a message had before it was edited
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "message_revision")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub message_id: i32,
+    pub content: Json,
+    pub edited_by: i32,
+    /// When the edit replacing this content happened
+    pub replaced_at: DateTime,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,6 +1,21 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.16
 
+pub mod audit_log;
+pub mod channel_permission_override;
+pub mod conversation;
+pub mod conversation_member;
+pub mod custom_emoji;
+pub mod invite;
+pub mod media;
+pub mod mention;
 pub mod message;
+pub mod message_revision;
+pub mod reaction;
+pub mod read_state;
+pub mod relationship;
+pub mod role;
+pub mod session;
 pub mod text_channel;
 pub mod user;
+pub mod user_role;
 pub mod voice_channel;
@@ -0,0 +1,20 @@
+use sea_orm::entity::prelude::*;
+
+/// Exactly one of `emoji` and `custom_emoji_id` is set
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "reaction")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub message_id: i32,
+    pub user_id: i32,
+    /// Unicode emoji
+    pub emoji: Option<String>,
+    #[sea_orm(indexed)]
+    pub custom_emoji_id: Option<i32>,
+    pub created_at: DateTime,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,19 @@
+use sea_orm::entity::prelude::*;
+
+/// What a user has read in a channel or a conversation,
+/// `channel_id` and `in_group` follow the `message` table
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "read_state")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub user_id: i32,
+    pub channel_id: i32,
+    pub in_group: bool,
+    pub last_read_message_id: Option<i32>,
+    pub is_muted: bool,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,21 @@
+use sea_orm::entity::prelude::*;
+
+/// Relationship of `user_id` to `other_id` as seen by `user_id`.
+/// Friendships and requests are stored for both users, blocks
+/// only for the user who blocked
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "relationship")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub user_id: i32,
+    #[sea_orm(indexed)]
+    pub other_id: i32,
+    /// `RelationshipKind`, see `api::relationships`
+    pub kind: i32,
+    pub created_at: DateTime,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,22 @@
+use rpc::{models::markers, tag_entity};
+
+use sea_orm::entity::prelude::*;
+
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "role")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub name: String,
+    /// Bitset of `rpc::models::permissions::Permissions`
+    pub permissions: i64,
+    pub position: i32,
+    /// The default role is implicitly assigned to every user
+    pub is_default: bool,
+    pub created_at: DateTime,
+}
+
+tag_entity!(Model, markers::Role);
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,22 @@
+use rpc::{models::markers, tag_entity};
+
+use sea_orm::entity::prelude::*;
+
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "session")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub user_id: i32,
+    pub device_name: String,
+    pub ip_address: String,
+    pub created_at: DateTime,
+    pub last_used_at: DateTime,
+    pub expires_at: DateTime,
+}
+
+tag_entity!(Model, markers::Session);
+
+impl ActiveModelBehavior for ActiveModel {}
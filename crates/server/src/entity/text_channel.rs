@@ -9,6 +9,9 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub name: String,
+    #[sea_orm(default_value = 0)]
+    pub position: i32,
+    pub category: Option<String>,
     pub created_at: DateTime,
 }
 
@@ -12,8 +12,41 @@ pub struct Model {
     pub password: String,
     pub created_at: DateTime,
     pub banned: bool,
+    pub ban_reason: Option<String>,
+    /// Permanent ban if it's not set
+    pub banned_until: Option<DateTime>,
+    /// `PresenceStatus` picked by the user, see `api::presence`
+    #[sea_orm(default_value = 0)]
+    pub status: i32,
+    pub custom_status: Option<String>,
+    pub custom_status_expires_at: Option<DateTime>,
+    pub display_name: Option<String>,
+    pub avatar_id: Option<i32>,
+    pub bio: Option<String>,
+    /// RGB color
+    pub accent_color: Option<i32>,
+    /// Set by moderators, see `VoiceServerState`
+    #[sea_orm(default_value = false)]
+    pub is_server_muted: bool,
+    #[sea_orm(default_value = false)]
+    pub is_server_deafened: bool,
 }
 
 tag_entity!(Model, markers::User);
 
+impl Model {
+    /// Name shown to other users
+    pub fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.username)
+    }
+
+    /// Whether the user is banned right now, expired bans are ignored
+    pub fn is_banned(&self) -> bool {
+        self.banned
+            && self
+                .banned_until
+                .is_none_or(|until| until > chrono::Utc::now().naive_utc())
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
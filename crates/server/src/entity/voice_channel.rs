@@ -9,7 +9,13 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub name: String,
+    #[sea_orm(default_value = 0)]
+    pub position: i32,
+    pub category: Option<String>,
+    /// Zero means there's no limit
     pub max_participants: i32,
+    #[sea_orm(default_value = 128000)]
+    pub bitrate: i32,
     pub created_at: DateTime,
 }
 
@@ -1,37 +1,61 @@
 use std::{
     net::SocketAddr,
-    sync::{Arc, RwLock},
+    path::PathBuf,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Instant,
 };
 
 use dashmap::DashMap;
 
 use rpc::{
     models::{
-        common::RPCNotification,
-        general::{UserConnectionUpdate, UserConnectionUpdateMessage},
-        markers::{TaggedEntity, TextChannelId, UserId, VoiceChannelId},
-        voice::{VoiceChannelUpdate, VoiceChannelUpdateMessage},
+        markers::{SessionId, TaggedEntity, UserId, VoiceChannelId},
+        messages::TextMessageChannel,
+        voice::{VoiceChannelUpdateMessage, VoiceServerState},
     },
     server::{RpcRouter, RpcWriter, serve},
 };
 
-use sea_orm::{Database, DatabaseConnection};
+use anyhow::Context as _;
+use clap::Parser as _;
+use sea_orm::{Database, DatabaseConnection, DbErr};
+use tokio::sync::mpsc;
 
 use entity::user::Model as User;
 
 use crate::{
-    api::{auth, messages, voice},
+    api::{
+        audit_log, auth, channels, emojis, groups, invites, media, mentions, messages, moderation,
+        motd, permissions, presence, profiles, reactions, read_state, relationships, search,
+        sessions, typing, voice,
+    },
+    cli::{Args, Command},
     config::Config,
+    media_storage::MediaStorage,
+    rate_limit::RateLimiter,
+    session_keys::SessionKeyring,
     streaming::open_udp_socket,
 };
 
 mod api;
+mod cli;
 mod config;
 mod entity;
+mod media_storage;
+mod metrics;
+mod migration;
+mod rate_limit;
+#[cfg(unix)]
+mod reload;
+mod session_keys;
 mod streaming;
 
 pub type GlobalRouter = RpcRouter<AppState, ConnectionState>;
 
+#[derive(Clone, Copy)]
 pub struct VoiceUser {
     id: UserId,
 
@@ -50,13 +74,46 @@ impl VoiceUser {
     }
 }
 
+pub struct TypingUser {
+    id: UserId,
+    /// Typing stops unless it's refreshed before this moment
+    expires_at: Instant,
+}
+
 /// This state holds connected users to respective channels
 pub struct ChannelsState {
-    pub text_channels: DashMap<TextChannelId, Vec<UserId>>,
+    /// Users typing in text channels and conversations
+    pub text_channels: DashMap<TextMessageChannel, Vec<TypingUser>>,
     pub voice_channels: DashMap<VoiceChannelId, Vec<VoiceUser>>,
+    /// Server mute/deafen set by moderators, a copy of the user rows
+    /// so forwarding voice doesn't query the database
+    pub voice_server_states: DashMap<UserId, VoiceServerState>,
+    /// Version of the last voice channel update. Starts at the startup time,
+    /// so versions keep growing across restarts
+    voice_version: AtomicU64,
+    /// Updates waiting to be versioned and sent by `voice::send_voice_updates`
+    voice_updates: mpsc::UnboundedSender<(VoiceChannelId, VoiceChannelUpdateMessage)>,
 }
 
 impl ChannelsState {
+    pub fn voice_version(&self) -> u64 {
+        self.voice_version.load(Ordering::SeqCst)
+    }
+
+    pub fn next_voice_version(&self) -> u64 {
+        self.voice_version.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    pub fn queue_voice_update(
+        &self,
+        channel_id: VoiceChannelId,
+        message: VoiceChannelUpdateMessage,
+    ) {
+        if self.voice_updates.send((channel_id, message)).is_err() {
+            log::error!("Voice update sender has stopped");
+        }
+    }
+
     fn disonnect_user_from_voice_channel(
         &self,
         user_id: Option<UserId>,
@@ -74,6 +131,89 @@ impl ChannelsState {
 
         true
     }
+
+    fn move_voice_user(&self, user_id: UserId, from: VoiceChannelId, to: VoiceChannelId) -> bool {
+        let user = {
+            let Some(mut users) = self.voice_channels.get_mut(&from) else {
+                return false;
+            };
+
+            let Some(index) = users.iter().position(|user| user.id == user_id) else {
+                return false;
+            };
+
+            users.remove(index)
+        };
+
+        self.voice_channels.entry(to).or_default().push(user);
+
+        true
+    }
+
+    /// Returns `true` if the user wasn't typing in the channel before
+    fn start_typing(
+        &self,
+        channel: TextMessageChannel,
+        user_id: UserId,
+        expires_at: Instant,
+    ) -> bool {
+        let mut users = self.text_channels.entry(channel).or_default();
+
+        if let Some(user) = users.iter_mut().find(|user| user.id == user_id) {
+            user.expires_at = expires_at;
+
+            return false;
+        }
+
+        users.push(TypingUser {
+            id: user_id,
+            expires_at,
+        });
+
+        true
+    }
+
+    fn typing_expires_at(&self, channel: TextMessageChannel, user_id: UserId) -> Option<Instant> {
+        self.text_channels
+            .get(&channel)?
+            .iter()
+            .find(|user| user.id == user_id)
+            .map(|user| user.expires_at)
+    }
+
+    /// Returns `true` if the user was typing in the channel
+    fn stop_typing(&self, channel: TextMessageChannel, user_id: UserId) -> bool {
+        let was_typing = {
+            let Some(mut users) = self.text_channels.get_mut(&channel) else {
+                return false;
+            };
+
+            let count = users.len();
+            users.retain(|user| user.id != user_id);
+
+            users.len() != count
+        };
+
+        self.text_channels
+            .remove_if(&channel, |_, users| users.is_empty());
+
+        was_typing
+    }
+
+    pub fn voice_server_state(&self, user_id: UserId) -> VoiceServerState {
+        self.voice_server_states
+            .get(&user_id)
+            .map(|state| *state)
+            .unwrap_or_default()
+    }
+
+    fn set_voice_server_state(&self, user_id: UserId, state: VoiceServerState) {
+        if state == VoiceServerState::default() {
+            self.voice_server_states.remove(&user_id);
+        } else {
+            self.voice_server_states.insert(user_id, state);
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -85,18 +225,72 @@ pub struct UDPStreamState {
 #[derive(Clone)]
 pub struct AppState {
     pub db: DatabaseConnection,
+    pub config: Arc<Config>,
+
+    /// Keys used to sign and verify session keys
+    pub session_keys: Arc<RwLock<SessionKeyring>>,
+    /// Uploaded files and unfinished uploads
+    pub media: Arc<MediaStorage>,
+    pub rate_limiter: Arc<RateLimiter>,
+    /// Message of the day, replaced when the config is reloaded
+    pub motd: Arc<RwLock<Option<String>>>,
 
     pub channels: Arc<ChannelsState>,
-    pub connected_clients: Arc<DashMap<UserId, ConnectionState>>,
+    /// A user can be connected from several devices at once
+    pub connected_clients: Arc<DashMap<UserId, Vec<ConnectionState>>>,
 }
 
 impl AppState {
-    fn disconnect(&self, user_id: Option<UserId>) {
+    fn disconnect(&self, user_id: Option<UserId>, addr: SocketAddr) {
         let Some(user_id) = user_id else {
             return;
         };
 
-        self.connected_clients.remove(&user_id);
+        self.connected_clients
+            .remove_if_mut(&user_id, |_, connections| {
+                connections.retain(|conn| conn.read().unwrap().addr != addr);
+
+                connections.is_empty()
+            });
+    }
+
+    /// Writers of all authenticated connections along with their owners
+    pub fn writers(&self) -> Vec<(UserId, RpcWriter)> {
+        self.connected_clients
+            .iter()
+            .flat_map(|entry| {
+                let user_id = *entry.key();
+
+                entry
+                    .value()
+                    .iter()
+                    .map(|conn| (user_id, conn.read().unwrap().writer.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Writers of all connections of a specific user
+    pub fn user_writers(&self, user_id: UserId) -> Vec<RpcWriter> {
+        self.connected_clients
+            .get(&user_id)
+            .map(|connections| {
+                connections
+                    .iter()
+                    .map(|conn| conn.read().unwrap().writer.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Connection of the user that is currently joined to a voice channel
+    pub fn voice_connection(&self, user_id: UserId) -> Option<ConnectionState> {
+        let connections = self.connected_clients.get(&user_id)?;
+
+        connections
+            .iter()
+            .find(|conn| conn.read().unwrap().active_voice_channel.is_some())
+            .cloned()
     }
 }
 
@@ -106,8 +300,14 @@ impl AppState {
 #[derive(Debug, Clone)]
 pub struct ConnectionStateInner {
     pub user: Option<User>,
+    pub session_id: Option<SessionId>,
+    /// Address of the client, unique among active connections
+    pub addr: SocketAddr,
+
     pub active_voice_channel: Option<VoiceChannelId>,
     pub active_stream: Option<SocketAddr>,
+    /// The client reported that the user is away
+    pub is_idle: bool,
 
     /// This is mostly used to send notifications to the user
     pub writer: RpcWriter,
@@ -119,34 +319,28 @@ impl ConnectionStateInner {
         let user_id = self.get_user_id();
         let channel_id = self.active_voice_channel;
 
-        state.disconnect(self.get_user_id());
+        state.disconnect(self.get_user_id(), self.addr);
         self.disconnect_from_voice_channel(state);
 
-        let (Some(user_id), Some(channel_id)) = (user_id, channel_id) else {
+        let Some(user_id) = user_id else {
             return;
         };
 
-        let writers = state
-            .connected_clients
-            .iter()
-            .map(|user| user.read().unwrap().writer.clone())
-            .collect::<Vec<_>>();
-
-        for writer in writers {
-            VoiceChannelUpdate {
+        if let Some(channel_id) = channel_id {
+            voice::broadcast_voice_update(
+                state,
                 channel_id,
-                message: VoiceChannelUpdateMessage::UserDisconnected(user_id),
-            }
-            .notify(&writer)
-            .await;
+                VoiceChannelUpdateMessage::UserDisconnected(user_id),
+            );
+        }
 
-            UserConnectionUpdate {
-                user_id,
-                message: UserConnectionUpdateMessage::UserDisconnected,
+        // Database futures aren't Sync, so this can't be awaited in the disconnect callback
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(err) = presence::broadcast_presence(&state, user_id).await {
+                log::error!("Failed to broadcast presence: {err}");
             }
-            .notify(&writer)
-            .await;
-        }
+        });
     }
 
     pub fn get_user_id(&self) -> Option<UserId> {
@@ -168,54 +362,142 @@ impl ConnectionStateInner {
 
 pub type ConnectionState = Arc<RwLock<ConnectionStateInner>>;
 
-async fn init_state() -> AppState {
-    let db = Database::connect("sqlite://db.sqlite?mode=rwc")
+/// Opens the database and checks that its schema is up to date
+pub async fn connect_database(config: &Config) -> Result<DatabaseConnection, DbErr> {
+    let db = Database::connect(&config.database_url).await?;
+
+    migration::check(&db, config.auto_migrate).await?;
+
+    permissions::ensure_default_role(&db).await?;
+
+    Ok(db)
+}
+
+async fn init_state(config: Config) -> anyhow::Result<AppState> {
+    let session_keys = SessionKeyring::load(&config).context("Failed to load session keys")?;
+    let media = MediaStorage::open(&config.media).context("Failed to open media storage")?;
+    let rate_limiter = RateLimiter::new(&config);
+    let motd = config.motd.clone();
+
+    let db = connect_database(&config)
         .await
-        .unwrap();
+        .with_context(|| format!("Failed to open the database {}", config.database_url))?;
 
-    db.get_schema_registry("hazel_server::entity::*")
-        .sync(&db)
+    let voice_server_states = moderation::load_voice_server_states(&db)
         .await
-        .unwrap();
+        .context("Failed to load voice server states")?;
+
+    let (voice_updates, queued_voice_updates) = mpsc::unbounded_channel();
 
-    AppState {
+    let state = AppState {
         db,
+        config: Arc::new(config),
+        motd: Arc::new(RwLock::new(motd)),
+        session_keys: Arc::new(RwLock::new(session_keys)),
+        media: Arc::new(media),
+        rate_limiter: Arc::new(rate_limiter),
         channels: Arc::new(ChannelsState {
             text_channels: DashMap::new(),
             voice_channels: DashMap::new(),
+            voice_server_states,
+            voice_version: AtomicU64::new(
+                u64::try_from(chrono::Utc::now().timestamp_micros()).unwrap_or_default(),
+            ),
+            voice_updates,
         }),
         connected_clients: Arc::new(DashMap::new()),
-    }
+    };
+
+    tokio::spawn(voice::send_voice_updates(
+        state.clone(),
+        queued_voice_updates,
+    ));
+
+    Ok(state)
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> anyhow::Result<()> {
     env_logger::init();
 
-    let config = std::fs::read_to_string("./config.toml").expect("Config is not provided");
-    let config = toml::from_str::<Config>(&config).expect("Invalid config");
+    let args = Args::parse();
+
+    match args.command.unwrap_or(Command::Serve) {
+        Command::Serve => run_server(args.config).await,
+        command => cli::run(command, &args.config).await,
+    }
+}
+
+async fn run_server(config_path: PathBuf) -> anyhow::Result<()> {
+    let config = Config::load(&config_path)?;
+    config.validate()?;
+
+    let tcp_addr = config.tcp_addr.clone();
+    let udp_addr = config.udp_addr.clone();
+
+    let state = init_state(config).await?;
+    channels::create_configured_channels(&state, &state.config).await?;
+
+    tokio::spawn(audit_log::prune_old_entries(state.clone()));
+    tokio::spawn(sessions::sweep_connections(state.clone()));
+    tokio::spawn(rate_limit::prune_periodically(state.rate_limiter.clone()));
+    #[cfg(unix)]
+    tokio::spawn(reload::reload_on_sighup(state.clone(), config_path));
+
+    if let Some(metrics_addr) = state.config.metrics_addr.clone() {
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = metrics::serve(state, metrics_addr).await {
+                log::error!("Metrics listener failed: {err:?}");
+            }
+        });
+    }
+
+    let router = RpcRouter::new(state.clone(), move |writer, addr| {
+        metrics::METRICS.connection_opened();
 
-    let state = init_state().await;
-    let router = RpcRouter::new(state.clone(), move |writer| {
         Arc::new(RwLock::new(ConnectionStateInner {
             user: None,
+            session_id: None,
+            addr,
             active_voice_channel: None,
             active_stream: None,
+            is_idle: false,
             writer,
         }))
     });
 
     let router = messages::merge(router);
     let router = auth::merge(router);
+    let router = sessions::merge(router);
+    let router = permissions::merge(router);
+    let router = moderation::merge(router);
+    let router = channels::merge(router);
+    let router = groups::merge(router);
+    let router = media::merge(router);
+    let router = search::merge(router);
+    let router = reactions::merge(router);
+    let router = emojis::merge(router);
+    let router = typing::merge(router);
+    let router = read_state::merge(router);
+    let router = mentions::merge(router);
+    let router = presence::merge(router);
+    let router = profiles::merge(router);
+    let router = relationships::merge(router);
+    let router = audit_log::merge(router);
+    let router = motd::merge(router);
+    let router = invites::merge(router);
     let router = voice::merge(router);
 
-    let tcp_addr = config.tcp_addr.clone();
     tokio::spawn(async move {
         serve(&tcp_addr, router, |state, conn_state| {
             // This function runs *after* the user is disconnected
             // aka we waited a bit for a reconnect but it didn't happen
 
             Box::pin(async move {
+                metrics::METRICS.connection_closed();
+
                 let conn_state = conn_state.read().unwrap().clone();
 
                 conn_state.disconnect(&state).await;
@@ -224,5 +506,5 @@ async fn main() {
         .await;
     });
 
-    open_udp_socket(state, &config.udp_addr).await.unwrap();
+    open_udp_socket(state, &udp_addr).await
 }
@@ -0,0 +1,273 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context as _, Result as AResult};
+use dashmap::DashMap;
+use image::{ImageFormat, ImageReader};
+use rand::Rng as _;
+use sha2::{Digest as _, Sha256};
+use tokio::{
+    fs::{self, File},
+    io::AsyncWriteExt as _,
+    sync::{Mutex, MutexGuard},
+};
+
+use rpc::models::{markers::UserId, media::UploadId};
+
+use crate::config::MediaConfig;
+
+/// Uploads that didn't receive any data for this long are dropped
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Longest side of a generated thumbnail in pixels
+const THUMBNAIL_SIZE: u32 = 320;
+
+/// Data received so far
+pub struct UploadState {
+    pub received: u64,
+
+    hasher: Sha256,
+    file: File,
+    last_activity: Instant,
+}
+
+impl UploadState {
+    pub async fn append(&mut self, data: &[u8]) -> io::Result<()> {
+        self.file.write_all(data).await?;
+
+        self.hasher.update(data);
+        self.received += data.len() as u64;
+        self.last_activity = Instant::now();
+
+        Ok(())
+    }
+}
+
+pub struct PendingUpload {
+    pub user_id: UserId,
+    pub file_name: String,
+    pub mime_type: String,
+    /// Announced size of the file
+    pub size: u64,
+
+    pub state: Mutex<UploadState>,
+    temp_path: PathBuf,
+}
+
+/// File moved into the storage after its upload was finished
+pub struct StoredFile {
+    /// Hex encoded hash of the content
+    pub sha256: String,
+    pub has_thumbnail: bool,
+}
+
+/// Content-addressed storage of uploaded files.
+///
+/// Files are named after the SHA-256 of their content, so identical
+/// uploads share one copy on disk. Unfinished uploads are written
+/// into a separate directory and moved in place once they complete.
+pub struct MediaStorage {
+    root: PathBuf,
+    uploads: DashMap<UploadId, Arc<PendingUpload>>,
+    /// Held while the used storage is checked and changed
+    quota: Mutex<()>,
+}
+
+impl MediaStorage {
+    pub fn open(config: &MediaConfig) -> AResult<Self> {
+        let root = PathBuf::from(&config.path);
+        let uploads_dir = root.join("uploads");
+
+        std::fs::create_dir_all(&uploads_dir)
+            .with_context(|| format!("Failed to create {}", uploads_dir.display()))?;
+
+        // Leftovers of the uploads interrupted by a restart
+        for entry in std::fs::read_dir(&uploads_dir)? {
+            std::fs::remove_file(entry?.path())?;
+        }
+
+        Ok(Self {
+            root,
+            uploads: DashMap::new(),
+            quota: Mutex::new(()),
+        })
+    }
+
+    /// Files are spread over subdirectories by the first byte of the hash
+    pub fn blob_path(&self, sha256: &str) -> PathBuf {
+        self.root.join(&sha256[..2]).join(sha256)
+    }
+
+    pub fn thumbnail_path(&self, sha256: &str) -> PathBuf {
+        self.root
+            .join(&sha256[..2])
+            .join(format!("{sha256}.thumb.png"))
+    }
+
+    /// Serializes quota checks with the changes of the used storage, so
+    /// concurrent uploads can't exceed the quota together
+    pub async fn lock_quota(&self) -> MutexGuard<'_, ()> {
+        self.quota.lock().await
+    }
+
+    /// Announced size of unfinished uploads, only of the user if it's set
+    pub fn pending_size(&self, user_id: Option<UserId>) -> u64 {
+        self.uploads
+            .iter()
+            .filter(|entry| user_id.is_none_or(|user_id| entry.value().user_id == user_id))
+            .map(|entry| entry.value().size)
+            .sum()
+    }
+
+    /// Unfinished uploads started by the user
+    pub fn user_uploads(&self, user_id: UserId) -> Vec<Arc<PendingUpload>> {
+        self.uploads
+            .iter()
+            .filter(|entry| entry.value().user_id == user_id)
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+
+    pub async fn begin(
+        &self,
+        user_id: UserId,
+        file_name: String,
+        mime_type: String,
+        size: u64,
+    ) -> io::Result<UploadId> {
+        let id = rand::rng().random::<UploadId>();
+        let temp_path = self.root.join("uploads").join(id.to_string());
+
+        let file = File::create(&temp_path).await?;
+
+        self.uploads.insert(
+            id,
+            Arc::new(PendingUpload {
+                user_id,
+                file_name,
+                mime_type,
+                size,
+                state: Mutex::new(UploadState {
+                    received: 0,
+                    hasher: Sha256::new(),
+                    file,
+                    last_activity: Instant::now(),
+                }),
+                temp_path,
+            }),
+        );
+
+        Ok(id)
+    }
+
+    /// Upload with the given ID if it was started by the user
+    pub fn get(&self, id: UploadId, user_id: UserId) -> Option<Arc<PendingUpload>> {
+        self.uploads
+            .get(&id)
+            .filter(|upload| upload.user_id == user_id)
+            .map(|upload| upload.clone())
+    }
+
+    /// Stops tracking the upload and deletes the received data
+    pub async fn cancel(&self, id: UploadId) -> io::Result<()> {
+        let Some((_, upload)) = self.uploads.remove(&id) else {
+            return Ok(());
+        };
+
+        fs::remove_file(&upload.temp_path).await
+    }
+
+    /// Drops uploads that were abandoned by their clients
+    pub async fn remove_expired(&self) {
+        let expired = self
+            .uploads
+            .iter()
+            .filter(|entry| {
+                // Locked uploads are receiving data right now
+                entry
+                    .value()
+                    .state
+                    .try_lock()
+                    .is_ok_and(|state| state.last_activity.elapsed() > UPLOAD_TIMEOUT)
+            })
+            .map(|entry| *entry.key())
+            .collect::<Vec<_>>();
+
+        for id in expired {
+            if let Err(err) = self.cancel(id).await {
+                log::warn!("Failed to remove expired upload {id}: {err}");
+            }
+        }
+    }
+
+    /// Moves a completed upload into the storage.
+    /// The content is dropped if the same file is already stored
+    pub async fn finish(&self, id: UploadId) -> io::Result<Option<StoredFile>> {
+        let Some((_, upload)) = self.uploads.remove(&id) else {
+            return Ok(None);
+        };
+
+        let mut state = upload.state.lock().await;
+        state.file.sync_all().await?;
+
+        let sha256 = hex::encode(std::mem::take(&mut state.hasher).finalize());
+        let path = self.blob_path(&sha256);
+
+        if fs::try_exists(&path).await? {
+            fs::remove_file(&upload.temp_path).await?;
+        } else {
+            fs::create_dir_all(path.parent().expect("Blob paths have a parent")).await?;
+            fs::rename(&upload.temp_path, &path).await?;
+        }
+
+        let has_thumbnail =
+            upload.mime_type.starts_with("image/") && self.ensure_thumbnail(&sha256, &path).await;
+
+        Ok(Some(StoredFile {
+            sha256,
+            has_thumbnail,
+        }))
+    }
+
+    /// Generates the thumbnail unless it exists already.
+    /// Returns whether the file has one
+    async fn ensure_thumbnail(&self, sha256: &str, path: &Path) -> bool {
+        let thumbnail_path = self.thumbnail_path(sha256);
+
+        if fs::try_exists(&thumbnail_path).await.unwrap_or(false) {
+            return true;
+        }
+
+        let path = path.to_owned();
+
+        // Decoding is CPU heavy, keep it off the async workers
+        let result = tokio::task::spawn_blocking(move || -> AResult<()> {
+            let image = ImageReader::open(&path)?.with_guessed_format()?.decode()?;
+
+            image
+                .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+                .save_with_format(&thumbnail_path, ImageFormat::Png)?;
+
+            Ok(())
+        })
+        .await;
+
+        match result {
+            Ok(Ok(())) => true,
+            Ok(Err(err)) => {
+                log::warn!("Failed to generate a thumbnail for {sha256}: {err}");
+
+                false
+            }
+            Err(err) => {
+                log::error!("Thumbnail task failed: {err}");
+
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    fmt::Write as _,
+    sync::{
+        LazyLock,
+        atomic::{AtomicI64, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::Result as AResult;
+use dashmap::DashMap;
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::AppState;
+
+/// Upper bounds of RPC latency buckets, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1., 2.5, 5.];
+
+/// Requests larger than this are rejected, only the request line matters
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Clients that don't send the request in time are dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Collected by every part of the server, DB errors are counted in places
+/// that don't have access to `AppState`
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+/// Why a UDP packet wasn't forwarded to anyone
+#[derive(Debug, Clone, Copy)]
+pub enum DropReason {
+    Empty,
+    /// The sender isn't connected to a voice channel
+    NotInVoice,
+    ServerMuted,
+    ChannelNotFound,
+}
+
+impl DropReason {
+    const ALL: [Self; 4] = [
+        Self::Empty,
+        Self::NotInVoice,
+        Self::ServerMuted,
+        Self::ChannelNotFound,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Empty => "empty",
+            Self::NotInVoice => "not_in_voice",
+            Self::ServerMuted => "server_muted",
+            Self::ChannelNotFound => "channel_not_found",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Not cumulative, every observation is counted in a single bucket
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+
+        if let Some(index) = LATENCY_BUCKETS.iter().position(|bound| secs <= *bound) {
+            self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(
+            u64::try_from(duration.as_micros()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+#[derive(Default)]
+struct MethodStats {
+    ok: AtomicU64,
+    error: AtomicU64,
+    rate_limited: AtomicU64,
+    latency: Histogram,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    /// Open TCP connections, including the ones that didn't log in
+    connections: AtomicI64,
+    rpc: DashMap<&'static str, MethodStats>,
+
+    udp_packets_received: AtomicU64,
+    udp_bytes_received: AtomicU64,
+    udp_packets_forwarded: AtomicU64,
+    udp_bytes_forwarded: AtomicU64,
+    udp_dropped: [AtomicU64; DropReason::ALL.len()],
+
+    db_errors: AtomicU64,
+}
+
+impl Metrics {
+    pub fn connection_opened(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_closed(&self) {
+        self.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn rpc_handled(&self, method: &'static str, is_ok: bool, elapsed: Duration) {
+        let stats = self.rpc.entry(method).or_default();
+
+        if is_ok {
+            stats.ok.fetch_add(1, Ordering::Relaxed);
+        } else {
+            stats.error.fetch_add(1, Ordering::Relaxed);
+        }
+
+        stats.latency.observe(elapsed);
+    }
+
+    /// Rejected requests don't reach the handler, so their latency isn't recorded
+    pub fn rpc_rate_limited(&self, method: &'static str) {
+        self.rpc
+            .entry(method)
+            .or_default()
+            .rate_limited
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn udp_received(&self, bytes: usize) {
+        self.udp_packets_received.fetch_add(1, Ordering::Relaxed);
+        self.udp_bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn udp_forwarded(&self, bytes: usize) {
+        self.udp_packets_forwarded.fetch_add(1, Ordering::Relaxed);
+        self.udp_bytes_forwarded
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn udp_dropped(&self, reason: DropReason) {
+        self.udp_dropped[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn db_error(&self) {
+        self.db_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Prometheus text exposition format
+    fn render(&self, app_state: &AppState) -> String {
+        let mut out = String::new();
+
+        let authenticated_connections = app_state
+            .connected_clients
+            .iter()
+            .map(|entry| entry.value().len())
+            .sum::<usize>();
+
+        gauge(
+            &mut out,
+            "hazel_connections",
+            "Open TCP connections",
+            self.connections.load(Ordering::Relaxed),
+        );
+        gauge(
+            &mut out,
+            "hazel_authenticated_connections",
+            "Connections that logged in",
+            authenticated_connections,
+        );
+        gauge(
+            &mut out,
+            "hazel_authenticated_users",
+            "Users with at least one connection",
+            app_state.connected_clients.len(),
+        );
+
+        header(
+            &mut out,
+            "hazel_voice_channel_users",
+            "gauge",
+            "Users joined to a voice channel",
+        );
+        for entry in app_state.channels.voice_channels.iter() {
+            _ = writeln!(
+                out,
+                "hazel_voice_channel_users{{channel=\"{}\"}} {}",
+                entry.key().value,
+                entry.value().len()
+            );
+        }
+
+        header(
+            &mut out,
+            "hazel_rpc_calls_total",
+            "counter",
+            "RPC calls by method and result",
+        );
+        for entry in self.rpc.iter() {
+            let method = entry.key();
+
+            for (result, count) in [
+                ("ok", &entry.ok),
+                ("error", &entry.error),
+                ("rate_limited", &entry.rate_limited),
+            ] {
+                _ = writeln!(
+                    out,
+                    "hazel_rpc_calls_total{{method=\"{method}\",result=\"{result}\"}} {}",
+                    count.load(Ordering::Relaxed)
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "hazel_rpc_duration_seconds",
+            "histogram",
+            "Time spent handling RPC calls",
+        );
+        for entry in self.rpc.iter() {
+            let method = entry.key();
+            let latency = &entry.latency;
+
+            let mut cumulative = 0;
+            for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&latency.buckets) {
+                cumulative += bucket.load(Ordering::Relaxed);
+
+                _ = writeln!(
+                    out,
+                    "hazel_rpc_duration_seconds_bucket{{method=\"{method}\",le=\"{bound}\"}} {cumulative}"
+                );
+            }
+
+            let count = latency.count.load(Ordering::Relaxed);
+            let sum = latency.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.;
+
+            _ = writeln!(
+                out,
+                "hazel_rpc_duration_seconds_bucket{{method=\"{method}\",le=\"+Inf\"}} {count}"
+            );
+            _ = writeln!(
+                out,
+                "hazel_rpc_duration_seconds_sum{{method=\"{method}\"}} {sum}"
+            );
+            _ = writeln!(
+                out,
+                "hazel_rpc_duration_seconds_count{{method=\"{method}\"}} {count}"
+            );
+        }
+
+        for (name, help, counter) in [
+            (
+                "hazel_udp_packets_received_total",
+                "UDP packets received",
+                &self.udp_packets_received,
+            ),
+            (
+                "hazel_udp_bytes_received_total",
+                "UDP bytes received",
+                &self.udp_bytes_received,
+            ),
+            (
+                "hazel_udp_packets_forwarded_total",
+                "UDP packets sent to voice channel members",
+                &self.udp_packets_forwarded,
+            ),
+            (
+                "hazel_udp_bytes_forwarded_total",
+                "UDP bytes sent to voice channel members",
+                &self.udp_bytes_forwarded,
+            ),
+            (
+                "hazel_db_errors_total",
+                "Failed database queries",
+                &self.db_errors,
+            ),
+        ] {
+            header(&mut out, name, "counter", help);
+            _ = writeln!(out, "{name} {}", counter.load(Ordering::Relaxed));
+        }
+
+        header(
+            &mut out,
+            "hazel_udp_packets_dropped_total",
+            "counter",
+            "UDP packets that weren't forwarded by reason",
+        );
+        for reason in DropReason::ALL {
+            _ = writeln!(
+                out,
+                "hazel_udp_packets_dropped_total{{reason=\"{}\"}} {}",
+                reason.label(),
+                self.udp_dropped[reason as usize].load(Ordering::Relaxed)
+            );
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    _ = writeln!(out, "# HELP {name} {help}");
+    _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: impl std::fmt::Display) {
+    header(out, name, "gauge", help);
+    _ = writeln!(out, "{name} {value}");
+}
+
+/// Reads the request line, headers and the body are ignored
+async fn read_request_line(stream: &mut TcpStream) -> Option<String> {
+    let mut buf = Vec::with_capacity(1024);
+
+    loop {
+        if let Some(end) = buf.windows(2).position(|window| window == b"\r\n") {
+            return String::from_utf8(buf[..end].to_vec()).ok();
+        }
+
+        if buf.len() >= MAX_REQUEST_SIZE {
+            return None;
+        }
+
+        let mut chunk = [0; 1024];
+        let read = stream.read(&mut chunk).await.ok()?;
+
+        if read == 0 {
+            return None;
+        }
+
+        buf.extend_from_slice(&chunk[..read]);
+    }
+}
+
+async fn handle_request(app_state: &AppState, mut stream: TcpStream) -> std::io::Result<()> {
+    let Ok(Some(request_line)) =
+        tokio::time::timeout(REQUEST_TIMEOUT, read_request_line(&mut stream)).await
+    else {
+        return Ok(());
+    };
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next(), parts.next());
+
+    let (status, content_type, body) = match (method, path) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            METRICS.render(app_state),
+        ),
+        (Some("GET"), Some("/healthz")) => match app_state.db.ping().await {
+            Ok(()) => ("200 OK", "text/plain", "ok\n".to_owned()),
+            Err(err) => {
+                log::error!("Health check failed: {err}");
+
+                (
+                    "503 Service Unavailable",
+                    "text/plain",
+                    "database unavailable\n".to_owned(),
+                )
+            }
+        },
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "not found\n".to_owned()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n".to_owned(),
+        ),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Serves `/metrics` and `/healthz`, meant to be bound to a local address
+pub async fn serve(app_state: AppState, addr: String) -> AResult<()> {
+    let listener = TcpListener::bind(&addr).await?;
+
+    log::info!("Serving metrics on {addr}");
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let app_state = app_state.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = handle_request(&app_state, stream).await {
+                log::debug!("Failed to answer a metrics request: {err}");
+            }
+        });
+    }
+}
@@ -0,0 +1,690 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+/// Schema as it was created by schema sync before migrations were
+/// introduced. Everything is created only if it's missing, so databases
+/// created by schema sync are adopted as they are
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+    Username,
+    Password,
+    CreatedAt,
+    Banned,
+    BanReason,
+    BannedUntil,
+    Status,
+    CustomStatus,
+    CustomStatusExpiresAt,
+    DisplayName,
+    AvatarId,
+    Bio,
+    AccentColor,
+}
+
+#[derive(DeriveIden)]
+enum Session {
+    Table,
+    Id,
+    UserId,
+    DeviceName,
+    IpAddress,
+    CreatedAt,
+    LastUsedAt,
+    ExpiresAt,
+}
+
+#[derive(DeriveIden)]
+enum Role {
+    Table,
+    Id,
+    Name,
+    Permissions,
+    Position,
+    IsDefault,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum UserRole {
+    Table,
+    Id,
+    UserId,
+    RoleId,
+}
+
+#[derive(DeriveIden)]
+enum ChannelPermissionOverride {
+    Table,
+    Id,
+    TextChannelId,
+    VoiceChannelId,
+    RoleId,
+    Allow,
+    Deny,
+}
+
+#[derive(DeriveIden)]
+enum TextChannel {
+    Table,
+    Id,
+    Name,
+    Position,
+    Category,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum VoiceChannel {
+    Table,
+    Id,
+    Name,
+    Position,
+    Category,
+    MaxParticipants,
+    Bitrate,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Message {
+    Table,
+    Id,
+    Content,
+    ChannelId,
+    InGroup,
+    SentBy,
+    SentAt,
+    EditedAt,
+    DeletedAt,
+}
+
+#[derive(DeriveIden)]
+enum MessageRevision {
+    Table,
+    Id,
+    MessageId,
+    Content,
+    EditedBy,
+    ReplacedAt,
+}
+
+#[derive(DeriveIden)]
+enum Reaction {
+    Table,
+    Id,
+    MessageId,
+    UserId,
+    Emoji,
+    CustomEmojiId,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum CustomEmoji {
+    Table,
+    Id,
+    Name,
+    MediaId,
+    CreatedBy,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Media {
+    Table,
+    Id,
+    FileName,
+    MimeType,
+    Size,
+    Sha256,
+    UploadedBy,
+    UploadedAt,
+    HasThumbnail,
+}
+
+#[derive(DeriveIden)]
+enum Mention {
+    Table,
+    Id,
+    MessageId,
+    UserId,
+    RoleId,
+    IsHere,
+}
+
+#[derive(DeriveIden)]
+enum ReadState {
+    Table,
+    Id,
+    UserId,
+    ChannelId,
+    InGroup,
+    LastReadMessageId,
+    IsMuted,
+}
+
+#[derive(DeriveIden)]
+enum Conversation {
+    Table,
+    Id,
+    Name,
+    OwnerId,
+    IsDirect,
+    DirectKey,
+    CreatedAt,
+    LastActivityAt,
+}
+
+#[derive(DeriveIden)]
+enum ConversationMember {
+    Table,
+    Id,
+    ConversationId,
+    UserId,
+    JoinedAt,
+}
+
+#[derive(DeriveIden)]
+enum Relationship {
+    Table,
+    Id,
+    UserId,
+    OtherId,
+    Kind,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum AuditLog {
+    Table,
+    Id,
+    ActorId,
+    Action,
+    TargetKind,
+    TargetId,
+    Reason,
+    Before,
+    After,
+    CreatedAt,
+}
+
+async fn create_table(
+    manager: &SchemaManager<'_>,
+    table: &mut TableCreateStatement,
+) -> Result<(), DbErr> {
+    manager.create_table(table.if_not_exists().to_owned()).await
+}
+
+/// Names match the ones schema sync gave to indexed columns
+async fn create_index(
+    manager: &SchemaManager<'_>,
+    name: &str,
+    table: impl IntoIden,
+    column: impl IntoIden,
+) -> Result<(), DbErr> {
+    manager
+        .create_index(
+            Index::create()
+                .if_not_exists()
+                .name(name)
+                .table(table)
+                .col(column)
+                .to_owned(),
+        )
+        .await
+}
+
+async fn create_unique_index<T: IntoIden>(
+    manager: &SchemaManager<'_>,
+    name: &str,
+    table: impl IntoIden,
+    columns: impl IntoIterator<Item = T>,
+) -> Result<(), DbErr> {
+    let mut index = Index::create();
+    index.if_not_exists().name(name).table(table).unique();
+
+    for column in columns {
+        index.col(column);
+    }
+
+    manager.create_index(index).await
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        create_table(
+            manager,
+            Table::create()
+                .table(User::Table)
+                .col(pk_auto(User::Id))
+                .col(string(User::Username))
+                .col(string(User::Password))
+                .col(date_time(User::CreatedAt))
+                .col(boolean(User::Banned))
+                .col(string_null(User::BanReason))
+                .col(date_time_null(User::BannedUntil))
+                .col(integer(User::Status).default(0))
+                .col(string_null(User::CustomStatus))
+                .col(date_time_null(User::CustomStatusExpiresAt))
+                .col(string_null(User::DisplayName))
+                .col(integer_null(User::AvatarId))
+                .col(string_null(User::Bio))
+                .col(integer_null(User::AccentColor)),
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(Session::Table)
+                .col(pk_auto(Session::Id))
+                .col(integer(Session::UserId))
+                .col(string(Session::DeviceName))
+                .col(string(Session::IpAddress))
+                .col(date_time(Session::CreatedAt))
+                .col(date_time(Session::LastUsedAt))
+                .col(date_time(Session::ExpiresAt)),
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-session-user_id",
+            Session::Table,
+            Session::UserId,
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(Role::Table)
+                .col(pk_auto(Role::Id))
+                .col(string(Role::Name))
+                .col(big_integer(Role::Permissions))
+                .col(integer(Role::Position))
+                .col(boolean(Role::IsDefault))
+                .col(date_time(Role::CreatedAt)),
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(UserRole::Table)
+                .col(pk_auto(UserRole::Id))
+                .col(integer(UserRole::UserId))
+                .col(integer(UserRole::RoleId)),
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-user_role-user_id",
+            UserRole::Table,
+            UserRole::UserId,
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-user_role-role_id",
+            UserRole::Table,
+            UserRole::RoleId,
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(ChannelPermissionOverride::Table)
+                .col(pk_auto(ChannelPermissionOverride::Id))
+                .col(integer_null(ChannelPermissionOverride::TextChannelId))
+                .col(integer_null(ChannelPermissionOverride::VoiceChannelId))
+                .col(integer(ChannelPermissionOverride::RoleId))
+                .col(big_integer(ChannelPermissionOverride::Allow))
+                .col(big_integer(ChannelPermissionOverride::Deny)),
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-channel_permission_override-text_channel_id",
+            ChannelPermissionOverride::Table,
+            ChannelPermissionOverride::TextChannelId,
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-channel_permission_override-voice_channel_id",
+            ChannelPermissionOverride::Table,
+            ChannelPermissionOverride::VoiceChannelId,
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(TextChannel::Table)
+                .col(pk_auto(TextChannel::Id))
+                .col(string(TextChannel::Name))
+                .col(integer(TextChannel::Position).default(0))
+                .col(string_null(TextChannel::Category))
+                .col(date_time(TextChannel::CreatedAt)),
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(VoiceChannel::Table)
+                .col(pk_auto(VoiceChannel::Id))
+                .col(string(VoiceChannel::Name))
+                .col(integer(VoiceChannel::Position).default(0))
+                .col(string_null(VoiceChannel::Category))
+                .col(integer(VoiceChannel::MaxParticipants))
+                .col(integer(VoiceChannel::Bitrate).default(128_000))
+                .col(date_time(VoiceChannel::CreatedAt)),
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(Message::Table)
+                .col(pk_auto(Message::Id))
+                .col(json(Message::Content))
+                .col(integer(Message::ChannelId))
+                .col(boolean(Message::InGroup).default(false))
+                .col(integer(Message::SentBy))
+                .col(date_time(Message::SentAt))
+                .col(date_time_null(Message::EditedAt))
+                .col(date_time_null(Message::DeletedAt)),
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(MessageRevision::Table)
+                .col(pk_auto(MessageRevision::Id))
+                .col(integer(MessageRevision::MessageId))
+                .col(json(MessageRevision::Content))
+                .col(integer(MessageRevision::EditedBy))
+                .col(date_time(MessageRevision::ReplacedAt)),
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-message_revision-message_id",
+            MessageRevision::Table,
+            MessageRevision::MessageId,
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(Reaction::Table)
+                .col(pk_auto(Reaction::Id))
+                .col(integer(Reaction::MessageId))
+                .col(integer(Reaction::UserId))
+                .col(string_null(Reaction::Emoji))
+                .col(integer_null(Reaction::CustomEmojiId))
+                .col(date_time(Reaction::CreatedAt)),
+        )
+        .await?;
+        // A user reacts with the same emoji only once. Only one of the emoji
+        // columns is set and nulls never conflict, so each index covers one kind
+        create_unique_index(
+            manager,
+            "idx-reaction-message_id-user_id-emoji",
+            Reaction::Table,
+            [Reaction::MessageId, Reaction::UserId, Reaction::Emoji],
+        )
+        .await?;
+        create_unique_index(
+            manager,
+            "idx-reaction-message_id-user_id-custom_emoji_id",
+            Reaction::Table,
+            [
+                Reaction::MessageId,
+                Reaction::UserId,
+                Reaction::CustomEmojiId,
+            ],
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-reaction-custom_emoji_id",
+            Reaction::Table,
+            Reaction::CustomEmojiId,
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(CustomEmoji::Table)
+                .col(pk_auto(CustomEmoji::Id))
+                .col(string_uniq(CustomEmoji::Name))
+                .col(integer(CustomEmoji::MediaId))
+                .col(integer(CustomEmoji::CreatedBy))
+                .col(date_time(CustomEmoji::CreatedAt)),
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(Media::Table)
+                .col(pk_auto(Media::Id))
+                .col(string(Media::FileName))
+                .col(string(Media::MimeType))
+                .col(big_integer(Media::Size))
+                .col(string(Media::Sha256))
+                .col(integer(Media::UploadedBy))
+                .col(date_time(Media::UploadedAt))
+                .col(boolean(Media::HasThumbnail)),
+        )
+        .await?;
+        create_index(manager, "idx-media-sha256", Media::Table, Media::Sha256).await?;
+        create_index(
+            manager,
+            "idx-media-uploaded_by",
+            Media::Table,
+            Media::UploadedBy,
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(Mention::Table)
+                .col(pk_auto(Mention::Id))
+                .col(integer(Mention::MessageId))
+                .col(integer(Mention::UserId))
+                .col(integer_null(Mention::RoleId))
+                .col(boolean(Mention::IsHere)),
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-mention-message_id",
+            Mention::Table,
+            Mention::MessageId,
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-mention-user_id",
+            Mention::Table,
+            Mention::UserId,
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(ReadState::Table)
+                .col(pk_auto(ReadState::Id))
+                .col(integer(ReadState::UserId))
+                .col(integer(ReadState::ChannelId))
+                .col(boolean(ReadState::InGroup))
+                .col(integer_null(ReadState::LastReadMessageId))
+                .col(boolean(ReadState::IsMuted)),
+        )
+        .await?;
+        // Read states are upserted, so a user has one per channel
+        create_unique_index(
+            manager,
+            "idx-read_state-user_id-channel_id-in_group",
+            ReadState::Table,
+            [ReadState::UserId, ReadState::ChannelId, ReadState::InGroup],
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(Conversation::Table)
+                .col(pk_auto(Conversation::Id))
+                .col(string_null(Conversation::Name))
+                .col(integer_null(Conversation::OwnerId))
+                .col(boolean(Conversation::IsDirect))
+                .col(string_null(Conversation::DirectKey).unique_key())
+                .col(date_time(Conversation::CreatedAt))
+                .col(date_time(Conversation::LastActivityAt)),
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(ConversationMember::Table)
+                .col(pk_auto(ConversationMember::Id))
+                .col(integer(ConversationMember::ConversationId))
+                .col(integer(ConversationMember::UserId))
+                .col(date_time(ConversationMember::JoinedAt)),
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-conversation_member-conversation_id",
+            ConversationMember::Table,
+            ConversationMember::ConversationId,
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-conversation_member-user_id",
+            ConversationMember::Table,
+            ConversationMember::UserId,
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(Relationship::Table)
+                .col(pk_auto(Relationship::Id))
+                .col(integer(Relationship::UserId))
+                .col(integer(Relationship::OtherId))
+                .col(integer(Relationship::Kind))
+                .col(date_time(Relationship::CreatedAt)),
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-relationship-user_id",
+            Relationship::Table,
+            Relationship::UserId,
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-relationship-other_id",
+            Relationship::Table,
+            Relationship::OtherId,
+        )
+        .await?;
+
+        create_table(
+            manager,
+            Table::create()
+                .table(AuditLog::Table)
+                .col(pk_auto(AuditLog::Id))
+                .col(integer(AuditLog::ActorId))
+                .col(integer(AuditLog::Action))
+                .col(integer_null(AuditLog::TargetKind))
+                .col(integer_null(AuditLog::TargetId))
+                .col(string_null(AuditLog::Reason))
+                .col(json_null(AuditLog::Before))
+                .col(json_null(AuditLog::After))
+                .col(date_time(AuditLog::CreatedAt)),
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-audit_log-actor_id",
+            AuditLog::Table,
+            AuditLog::ActorId,
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-audit_log-action",
+            AuditLog::Table,
+            AuditLog::Action,
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-audit_log-target_id",
+            AuditLog::Table,
+            AuditLog::TargetId,
+        )
+        .await?;
+        create_index(
+            manager,
+            "idx-audit_log-created_at",
+            AuditLog::Table,
+            AuditLog::CreatedAt,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let tables = [
+            AuditLog::Table.into_iden(),
+            Relationship::Table.into_iden(),
+            ConversationMember::Table.into_iden(),
+            Conversation::Table.into_iden(),
+            ReadState::Table.into_iden(),
+            Mention::Table.into_iden(),
+            Media::Table.into_iden(),
+            CustomEmoji::Table.into_iden(),
+            Reaction::Table.into_iden(),
+            MessageRevision::Table.into_iden(),
+            Message::Table.into_iden(),
+            VoiceChannel::Table.into_iden(),
+            TextChannel::Table.into_iden(),
+            ChannelPermissionOverride::Table.into_iden(),
+            UserRole::Table.into_iden(),
+            Role::Table.into_iden(),
+            Session::Table.into_iden(),
+            User::Table.into_iden(),
+        ];
+
+        for table in tables {
+            manager
+                .drop_table(Table::drop().table(table).if_exists().to_owned())
+                .await?;
+        }
+
+        Ok(())
+    }
+}
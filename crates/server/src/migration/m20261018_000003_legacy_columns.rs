@@ -0,0 +1,129 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+/// Databases created by schema sync before the initial schema only have the
+/// columns of the first release, the initial schema keeps existing tables as
+/// they are. Columns added since then are added here if they're missing
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    BanReason,
+    BannedUntil,
+    Status,
+    CustomStatus,
+    CustomStatusExpiresAt,
+    DisplayName,
+    AvatarId,
+    Bio,
+    AccentColor,
+}
+
+#[derive(DeriveIden)]
+enum TextChannel {
+    Table,
+    Position,
+    Category,
+}
+
+#[derive(DeriveIden)]
+enum VoiceChannel {
+    Table,
+    Position,
+    Category,
+    Bitrate,
+}
+
+#[derive(DeriveIden)]
+enum Message {
+    Table,
+    InGroup,
+    EditedAt,
+    DeletedAt,
+}
+
+/// Every column is nullable or has a default, so rows that already exist stay valid
+fn columns() -> Vec<(DynIden, ColumnDef)> {
+    vec![
+        (User::Table.into_iden(), string_null(User::BanReason)),
+        (User::Table.into_iden(), date_time_null(User::BannedUntil)),
+        (
+            User::Table.into_iden(),
+            integer(User::Status).default(0).to_owned(),
+        ),
+        (User::Table.into_iden(), string_null(User::CustomStatus)),
+        (
+            User::Table.into_iden(),
+            date_time_null(User::CustomStatusExpiresAt),
+        ),
+        (User::Table.into_iden(), string_null(User::DisplayName)),
+        (User::Table.into_iden(), integer_null(User::AvatarId)),
+        (User::Table.into_iden(), string_null(User::Bio)),
+        (User::Table.into_iden(), integer_null(User::AccentColor)),
+        (
+            TextChannel::Table.into_iden(),
+            integer(TextChannel::Position).default(0).to_owned(),
+        ),
+        (
+            TextChannel::Table.into_iden(),
+            string_null(TextChannel::Category),
+        ),
+        (
+            VoiceChannel::Table.into_iden(),
+            integer(VoiceChannel::Position).default(0).to_owned(),
+        ),
+        (
+            VoiceChannel::Table.into_iden(),
+            string_null(VoiceChannel::Category),
+        ),
+        (
+            VoiceChannel::Table.into_iden(),
+            integer(VoiceChannel::Bitrate).default(128_000).to_owned(),
+        ),
+        (
+            Message::Table.into_iden(),
+            boolean(Message::InGroup).default(false).to_owned(),
+        ),
+        (
+            Message::Table.into_iden(),
+            date_time_null(Message::EditedAt),
+        ),
+        (
+            Message::Table.into_iden(),
+            date_time_null(Message::DeletedAt),
+        ),
+    ]
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite can't add several columns in one statement
+        for (table, mut column) in columns() {
+            if manager
+                .has_column(table.to_string(), column.get_column_name())
+                .await?
+            {
+                continue;
+            }
+
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // There's no telling which columns were added, they're dropped
+        // with their tables when the initial schema is reverted
+        Ok(())
+    }
+}
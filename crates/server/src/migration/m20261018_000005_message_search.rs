@@ -0,0 +1,107 @@
+use sea_orm_migration::{prelude::*, sea_orm::DbBackend};
+
+/// Full-text index of message texts. SQLite uses FTS5 kept in sync by
+/// triggers, Postgres indexes the search expression directly.
+/// Databases where the index was created on start are adopted as they are
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        match manager.get_database_backend() {
+            DbBackend::Sqlite => {
+                // Existing messages are indexed only when the table is new
+                if !manager.has_table("message_fts").await? {
+                    db.execute_unprepared(
+                        "CREATE VIRTUAL TABLE message_fts
+                         USING fts5(content, tokenize = 'unicode61 remove_diacritics 2')",
+                    )
+                    .await?;
+
+                    db.execute_unprepared(
+                        "INSERT INTO message_fts (rowid, content)
+                         SELECT id, json_extract(content, '$.content') FROM message
+                         WHERE deleted_at IS NULL",
+                    )
+                    .await?;
+                }
+
+                db.execute_unprepared(
+                    "CREATE TRIGGER IF NOT EXISTS message_fts_insert AFTER INSERT ON message
+                     WHEN new.deleted_at IS NULL
+                     BEGIN
+                         INSERT INTO message_fts (rowid, content)
+                         VALUES (new.id, json_extract(new.content, '$.content'));
+                     END",
+                )
+                .await?;
+
+                // Covers both edits and soft deletion
+                db.execute_unprepared(
+                    "CREATE TRIGGER IF NOT EXISTS message_fts_update
+                     AFTER UPDATE OF content, deleted_at ON message
+                     BEGIN
+                         DELETE FROM message_fts WHERE rowid = old.id;
+                         INSERT INTO message_fts (rowid, content)
+                         SELECT new.id, json_extract(new.content, '$.content')
+                         WHERE new.deleted_at IS NULL;
+                     END",
+                )
+                .await?;
+
+                db.execute_unprepared(
+                    "CREATE TRIGGER IF NOT EXISTS message_fts_delete AFTER DELETE ON message
+                     BEGIN
+                         DELETE FROM message_fts WHERE rowid = old.id;
+                     END",
+                )
+                .await?;
+            }
+            // `simple` doesn't stem words, like FTS5 `unicode61`
+            DbBackend::Postgres => {
+                db.execute_unprepared(
+                    "CREATE INDEX IF NOT EXISTS message_search ON message
+                     USING GIN (to_tsvector('simple', content->>'content'))",
+                )
+                .await?;
+            }
+            backend => {
+                return Err(DbErr::Migration(format!(
+                    "Message search doesn't support {backend:?}"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        match manager.get_database_backend() {
+            DbBackend::Sqlite => {
+                for trigger in [
+                    "message_fts_insert",
+                    "message_fts_update",
+                    "message_fts_delete",
+                ] {
+                    db.execute_unprepared(&format!("DROP TRIGGER IF EXISTS {trigger}"))
+                        .await?;
+                }
+
+                db.execute_unprepared("DROP TABLE IF EXISTS message_fts")
+                    .await?;
+            }
+            DbBackend::Postgres => {
+                db.execute_unprepared("DROP INDEX IF EXISTS message_search")
+                    .await?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+/// Invite codes, required to register when the server is invite only
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Invite {
+    Table,
+    Id,
+    Code,
+    CreatedBy,
+    MaxUses,
+    Uses,
+    ExpiresAt,
+    CreatedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Invite::Table)
+                    .col(pk_auto(Invite::Id))
+                    .col(string_uniq(Invite::Code))
+                    .col(integer_null(Invite::CreatedBy))
+                    .col(integer_null(Invite::MaxUses))
+                    .col(integer(Invite::Uses).default(0))
+                    .col(date_time_null(Invite::ExpiresAt))
+                    .col(date_time(Invite::CreatedAt))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Invite::Table).to_owned())
+            .await
+    }
+}
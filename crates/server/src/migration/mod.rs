@@ -0,0 +1,270 @@
+use sea_orm::{DatabaseConnection, DbErr};
+use sea_orm_migration::{MigrationStatus, MigrationTrait, MigratorTrait};
+
+mod m20261018_000001_initial_schema;
+mod m20261018_000002_voice_server_state;
+mod m20261018_000003_legacy_columns;
+mod m20261018_000005_message_search;
+mod m20261018_000006_invites;
+
+pub struct Migrator;
+
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20261018_000001_initial_schema::Migration),
+            Box::new(m20261018_000002_voice_server_state::Migration),
+            Box::new(m20261018_000003_legacy_columns::Migration),
+            Box::new(m20261018_000005_message_search::Migration),
+            Box::new(m20261018_000006_invites::Migration),
+        ]
+    }
+}
+
+/// Makes sure the schema matches this build before the database is used.
+/// Pending migrations are applied if `auto_migrate` is set, a database
+/// migrated by a newer build is never touched
+pub async fn check(db: &DatabaseConnection, auto_migrate: bool) -> Result<(), DbErr> {
+    // Fails if the database has migrations this build doesn't know about
+    let pending = Migrator::get_pending_migrations(db).await?;
+
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let names = pending
+        .iter()
+        .map(|migration| migration.name())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if !auto_migrate {
+        return Err(DbErr::Migration(format!(
+            "{} migrations are pending ({names}), run `hazel-server migrate up`",
+            pending.len()
+        )));
+    }
+
+    log::info!("Applying migrations: {names}");
+
+    Migrator::up(db, None).await
+}
+
+/// Name and status of every known migration, oldest first
+pub async fn status(db: &DatabaseConnection) -> Result<Vec<(String, MigrationStatus)>, DbErr> {
+    let migrations = Migrator::get_migration_with_status(db).await?;
+
+    Ok(migrations
+        .iter()
+        .map(|migration| (migration.name().to_owned(), migration.status()))
+        .collect())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use chrono::Utc;
+    use sea_orm::{Database, EntityTrait as _};
+    use sea_orm_migration::{
+        SchemaManager,
+        schema::*,
+        sea_query::{Query, Table},
+    };
+    #[cfg(feature = "postgres")]
+    use tokio::sync::{Mutex, MutexGuard};
+
+    use super::*;
+    use crate::entity::{
+        message::Entity as Message, text_channel::Entity as TextChannel, user::Entity as User,
+        voice_channel::Entity as VoiceChannel,
+    };
+
+    /// Postgres tests share one database, so they run one at a time
+    #[cfg(feature = "postgres")]
+    static POSTGRES: Mutex<()> = Mutex::const_new(());
+
+    /// In-memory database with every migration applied
+    #[cfg(feature = "sqlite")]
+    pub async fn sqlite_database() -> DatabaseConnection {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        check(&db, true).await.unwrap();
+
+        db
+    }
+
+    /// Database from `HAZEL_TEST_POSTGRES_URL` with every migration applied.
+    /// Everything in it is dropped first, so it has to be a throwaway one.
+    /// Tests are skipped if the variable isn't set
+    #[cfg(feature = "postgres")]
+    pub async fn postgres_database() -> Option<(MutexGuard<'static, ()>, DatabaseConnection)> {
+        let Ok(url) = std::env::var("HAZEL_TEST_POSTGRES_URL") else {
+            eprintln!("HAZEL_TEST_POSTGRES_URL is not set, skipping the test");
+
+            return None;
+        };
+
+        let lock = POSTGRES.lock().await;
+
+        let db = Database::connect(&url).await.unwrap();
+        Migrator::fresh(&db).await.unwrap();
+
+        Some((lock, db))
+    }
+
+    async fn assert_all(db: &DatabaseConnection, expected: MigrationStatus) {
+        let migrations = status(db).await.unwrap();
+
+        assert!(!migrations.is_empty());
+        for (name, status) in migrations {
+            assert_eq!(status, expected, "{name}");
+        }
+    }
+
+    /// Every migration can be reverted and applied again
+    async fn check_migrations(db: &DatabaseConnection) {
+        assert_all(db, MigrationStatus::Applied).await;
+
+        Migrator::down(db, None).await.unwrap();
+        assert_all(db, MigrationStatus::Pending).await;
+
+        // `check` refuses to apply them unless it's allowed to
+        assert!(check(db, false).await.is_err());
+        check(db, true).await.unwrap();
+        assert_all(db, MigrationStatus::Applied).await;
+    }
+
+    /// Creates the tables of the first release the way schema sync did,
+    /// with a row in each, before migrations were introduced
+    async fn create_legacy_schema(db: &DatabaseConnection) {
+        let manager = SchemaManager::new(db);
+
+        let tables = [
+            Table::create()
+                .table("user")
+                .col(pk_auto("id"))
+                .col(string("username"))
+                .col(string("password"))
+                .col(date_time("created_at"))
+                .col(boolean("banned"))
+                .to_owned(),
+            Table::create()
+                .table("text_channel")
+                .col(pk_auto("id"))
+                .col(string("name"))
+                .col(date_time("created_at"))
+                .to_owned(),
+            Table::create()
+                .table("voice_channel")
+                .col(pk_auto("id"))
+                .col(string("name"))
+                .col(integer("max_participants"))
+                .col(date_time("created_at"))
+                .to_owned(),
+            Table::create()
+                .table("message")
+                .col(pk_auto("id"))
+                .col(json("content"))
+                .col(integer("channel_id"))
+                .col(integer("sent_by"))
+                .col(date_time("sent_at"))
+                .to_owned(),
+        ];
+
+        for table in tables {
+            manager.create_table(table).await.unwrap();
+        }
+
+        let now = Utc::now().naive_utc();
+        let rows = [
+            Query::insert()
+                .into_table("user")
+                .columns(["username", "password", "created_at", "banned"])
+                .values_panic(["alice".into(), "hash".into(), now.into(), false.into()])
+                .to_owned(),
+            Query::insert()
+                .into_table("text_channel")
+                .columns(["name", "created_at"])
+                .values_panic(["general".into(), now.into()])
+                .to_owned(),
+            Query::insert()
+                .into_table("voice_channel")
+                .columns(["name", "max_participants", "created_at"])
+                .values_panic(["lounge".into(), 8.into(), now.into()])
+                .to_owned(),
+            Query::insert()
+                .into_table("message")
+                .columns(["content", "channel_id", "sent_by", "sent_at"])
+                .values_panic([
+                    serde_json::json!({ "text": "hello" }).into(),
+                    1.into(),
+                    1.into(),
+                    now.into(),
+                ])
+                .to_owned(),
+        ];
+
+        for row in rows {
+            manager.exec_stmt(row).await.unwrap();
+        }
+    }
+
+    /// Databases created by schema sync are migrated in place and keep their rows
+    async fn check_legacy_adoption(db: &DatabaseConnection) {
+        create_legacy_schema(db).await;
+
+        check(db, true).await.unwrap();
+        assert_all(db, MigrationStatus::Applied).await;
+
+        let users = User::find().all(db).await.unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].username, "alice");
+        assert_eq!(users[0].display_name, None);
+
+        let voice_channels = VoiceChannel::find().all(db).await.unwrap();
+        assert_eq!(voice_channels.len(), 1);
+        assert_eq!(voice_channels[0].bitrate, 128_000);
+
+        assert_eq!(TextChannel::find().all(db).await.unwrap().len(), 1);
+
+        let messages = Message::find().all(db).await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(!messages[0].in_group);
+        assert_eq!(messages[0].deleted_at, None);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_migrations() {
+        check_migrations(&sqlite_database().await).await;
+    }
+
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn postgres_migrations() {
+        let Some((_lock, db)) = postgres_database().await else {
+            return;
+        };
+
+        check_migrations(&db).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_legacy_adoption() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+
+        check_legacy_adoption(&db).await;
+    }
+
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn postgres_legacy_adoption() {
+        let Some((_lock, db)) = postgres_database().await else {
+            return;
+        };
+
+        // Starts out empty, like a database that was never migrated
+        Migrator::down(&db, None).await.unwrap();
+
+        check_legacy_adoption(&db).await;
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug},
+    net::IpAddr,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use rpc::models::{
+    auth::{ChangePassword, GetSessionKey, Login},
+    common::{APIError, RPCMethod as _},
+    markers::UserId,
+    messages::{SendMessage, StartTyping},
+    voice::{JoinVoiceChannel, UpdateVoiceUserState},
+};
+
+use crate::{
+    ConnectionStateInner,
+    config::{Config, LoginLockoutConfig, RateLimit},
+};
+
+/// How often buckets and login failures that don't matter anymore are dropped
+const PRUNE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Limits applied unless the config overrides them
+fn default_limits() -> HashMap<String, RateLimit> {
+    [
+        (GetSessionKey::key(), 5, 10),
+        (Login::key(), 10, 30),
+        (ChangePassword::key(), 3, 5),
+        (JoinVoiceChannel::key(), 5, 20),
+        (UpdateVoiceUserState::key(), 10, 60),
+        (SendMessage::key(), 10, 60),
+        (StartTyping::key(), 5, 30),
+    ]
+    .into_iter()
+    .map(|(method, burst, per_minute)| (method.to_owned(), RateLimit { burst, per_minute }))
+    .collect()
+}
+
+fn configured_limits(config: &Config) -> HashMap<String, RateLimit> {
+    let mut limits = default_limits();
+    limits.extend(
+        config
+            .rate_limits
+            .iter()
+            .map(|(method, limit)| (method.clone(), *limit)),
+    );
+
+    limits
+}
+
+pub fn rate_limited<E: Debug>(retry_after: Duration) -> APIError<E> {
+    APIError::RateLimited {
+        retry_after_ms: u64::try_from(retry_after.as_millis()).unwrap_or(u64::MAX),
+    }
+}
+
+/// Who a request is counted against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RateLimitKey {
+    User(UserId),
+    /// Requests made before logging in
+    Ip(IpAddr),
+}
+
+impl RateLimitKey {
+    pub fn of(connection: &ConnectionStateInner) -> Self {
+        match connection.get_user_id() {
+            Some(user_id) => Self::User(user_id),
+            None => Self::Ip(connection.addr.ip()),
+        }
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn full(limit: RateLimit) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            updated_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * limit.per_second()).min(limit.burst as f64);
+        self.updated_at = now;
+    }
+
+    /// Takes a token, or returns how long it takes for one to be restored
+    fn take(&mut self, limit: RateLimit) -> Result<(), Duration> {
+        self.refill(limit, Instant::now());
+
+        if self.tokens >= 1. {
+            self.tokens -= 1.;
+
+            return Ok(());
+        }
+
+        let per_second = limit.per_second();
+        if per_second <= 0. {
+            return Err(Duration::MAX);
+        }
+
+        Err(Duration::from_secs_f64((1. - self.tokens) / per_second))
+    }
+
+    fn is_full(&mut self, limit: RateLimit) -> bool {
+        self.refill(limit, Instant::now());
+
+        self.tokens >= limit.burst as f64
+    }
+}
+
+struct LoginFailures {
+    count: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// What failed logins are counted against. Accounts are locked too,
+/// so spreading guesses over many addresses doesn't help
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum LoginTarget {
+    Ip(IpAddr),
+    Username(String),
+}
+
+impl fmt::Display for LoginTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(ip) => write!(f, "address {ip}"),
+            Self::Username(username) => write!(f, "account {username}"),
+        }
+    }
+}
+
+fn login_targets(ip: IpAddr, username: &str) -> [LoginTarget; 2] {
+    [
+        LoginTarget::Ip(ip),
+        LoginTarget::Username(username.to_owned()),
+    ]
+}
+
+/// Token bucket limits of RPC methods and lockout of repeated failed logins
+pub struct RateLimiter {
+    /// Both are replaced when the config is reloaded
+    limits: RwLock<HashMap<String, RateLimit>>,
+    lockout: RwLock<LoginLockoutConfig>,
+
+    buckets: DashMap<(&'static str, RateLimitKey), TokenBucket>,
+    login_failures: DashMap<LoginTarget, LoginFailures>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            limits: RwLock::new(configured_limits(config)),
+            lockout: RwLock::new(config.login_lockout.clone()),
+            buckets: DashMap::new(),
+            login_failures: DashMap::new(),
+        }
+    }
+
+    /// Applies new limits, buckets that were already filled keep their tokens
+    pub fn reload(&self, config: &Config) {
+        *self.limits.write().unwrap() = configured_limits(config);
+        *self.lockout.write().unwrap() = config.login_lockout.clone();
+    }
+
+    /// Takes a token from the bucket of the method. Methods without
+    /// a limit are never limited
+    pub fn check(&self, method: &'static str, key: RateLimitKey) -> Result<(), Duration> {
+        let Some(limit) = self.limits.read().unwrap().get(method).copied() else {
+            return Ok(());
+        };
+
+        self.buckets
+            .entry((method, key))
+            .or_insert_with(|| TokenBucket::full(limit))
+            .take(limit)
+    }
+
+    /// Fails with the longest remaining lockout of the address and the account
+    pub fn check_login(&self, ip: IpAddr, username: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+
+        let remaining = login_targets(ip, username)
+            .iter()
+            .filter_map(|target| self.login_failures.get(target)?.locked_until)
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+            .max();
+
+        match remaining {
+            Some(remaining) => Err(remaining),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts the failure against both the address and the account
+    pub fn login_failed(&self, ip: IpAddr, username: &str) {
+        let lockout_config = self.lockout.read().unwrap().clone();
+
+        for target in login_targets(ip, username) {
+            self.record_login_failure(target, &lockout_config);
+        }
+    }
+
+    /// Every failure past the threshold doubles the lockout
+    fn record_login_failure(&self, target: LoginTarget, lockout_config: &LoginLockoutConfig) {
+        let now = Instant::now();
+        let max_lockout = Duration::from_secs(lockout_config.max_lockout_secs);
+
+        let mut failures = self
+            .login_failures
+            .entry(target.clone())
+            .or_insert(LoginFailures {
+                count: 0,
+                last_failure: now,
+                locked_until: None,
+            });
+
+        // Failures are forgotten after a while
+        if now.duration_since(failures.last_failure) > max_lockout {
+            failures.count = 0;
+        }
+
+        failures.count += 1;
+        failures.last_failure = now;
+
+        if failures.count < lockout_config.max_failures {
+            return;
+        }
+
+        let exponent = (failures.count - lockout_config.max_failures).min(16);
+        let lockout = Duration::from_secs(lockout_config.base_lockout_secs)
+            .saturating_mul(1 << exponent)
+            .min(max_lockout);
+
+        log::warn!(
+            "Locking out logins to the {target} for {}s after {} failures",
+            lockout.as_secs(),
+            failures.count
+        );
+
+        failures.locked_until = Some(now + lockout);
+    }
+
+    pub fn login_succeeded(&self, ip: IpAddr, username: &str) {
+        for target in login_targets(ip, username) {
+            self.login_failures.remove(&target);
+        }
+    }
+
+    /// Drops buckets that are full again and failures that were forgotten
+    pub fn prune(&self) {
+        {
+            let limits = self.limits.read().unwrap();
+
+            self.buckets.retain(|(method, _), bucket| {
+                limits
+                    .get(*method)
+                    .is_some_and(|limit| !bucket.is_full(*limit))
+            });
+        }
+
+        let max_lockout = Duration::from_secs(self.lockout.read().unwrap().max_lockout_secs);
+        self.login_failures
+            .retain(|_, failures| failures.last_failure.elapsed() <= max_lockout);
+    }
+}
+
+pub async fn prune_periodically(rate_limiter: Arc<RateLimiter>) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        rate_limiter.prune();
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result as AResult;
+use tokio::signal::unix::{SignalKind, signal};
+
+use crate::{
+    AppState,
+    api::{channels, motd},
+    config::Config,
+};
+
+/// Applies the settings of the config that can change at runtime:
+/// channels, rate limits and the message of the day. Everything else
+/// is only read on start
+async fn reload(app_state: &AppState, path: &Path) -> AResult<()> {
+    let config = Config::load(path)?;
+    config.validate()?;
+
+    app_state.rate_limiter.reload(&config);
+    channels::create_configured_channels(app_state, &config).await?;
+    motd::set_motd(app_state, config.motd).await;
+
+    Ok(())
+}
+
+/// Re-reads the config every time the process receives SIGHUP,
+/// connections stay open while it happens
+pub async fn reload_on_sighup(app_state: AppState, path: PathBuf) {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            log::error!("Failed to listen for SIGHUP, config reload is disabled: {err}");
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        log::info!("Reloading config from {}", path.display());
+
+        match reload(&app_state, &path).await {
+            Ok(()) => log::info!("Config was reloaded"),
+            // The previous settings stay in effect
+            Err(err) => log::error!("Failed to reload config: {err:#}"),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result as AResult, bail};
+use chrono::{DateTime, Utc};
+use rand::RngCore as _;
+use serde::{Deserialize, Serialize};
+
+use rpc::models::auth::{SessionKey, SessionKeyBody};
+
+use crate::config::Config;
+
+/// Size of a generated secret in bytes
+const SECRET_LEN: usize = 32;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct StoredKey {
+    id: u32,
+    /// Hex encoded secret
+    secret: String,
+    created_at: i64,
+}
+
+/// On-disk representation of the keyring
+#[derive(Serialize, Deserialize, Debug)]
+struct KeyringFile {
+    current: u32,
+    keys: Vec<StoredKey>,
+}
+
+enum KeyringSource {
+    /// The secret is set in the config, it can't be rotated
+    Config,
+    /// Keys are generated by the server and stored in a file
+    File {
+        path: PathBuf,
+        stored: Vec<StoredKey>,
+    },
+}
+
+/// Set of HMAC keys used to sign and verify [`SessionKey`].
+///
+/// Only the current key is used to sign new sessions,
+/// but sessions signed with any other known key stay valid
+/// until the key is retired.
+pub struct SessionKeyring {
+    current: u32,
+    keys: HashMap<u32, Vec<u8>>,
+
+    source: KeyringSource,
+}
+
+impl SessionKeyring {
+    pub fn load(config: &Config) -> AResult<Self> {
+        if let Some(secret) = &config.session_secret {
+            if secret.len() < 16 {
+                bail!("`session_secret` should be at least 16 characters long");
+            }
+
+            return Ok(Self {
+                current: 0,
+                keys: HashMap::from([(0, secret.as_bytes().to_vec())]),
+                source: KeyringSource::Config,
+            });
+        }
+
+        let path = PathBuf::from(&config.session_keys_path);
+
+        if !path.exists() {
+            log::info!("Generating session keys into {}", path.display());
+
+            let file = KeyringFile {
+                current: 0,
+                keys: vec![Self::generate_key(0)],
+            };
+
+            Self::write_file(&path, &file)?;
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read session keys from {}", path.display()))?;
+
+        let file = toml::from_str::<KeyringFile>(&content)
+            .with_context(|| format!("Session keys file {} is malformed", path.display()))?;
+
+        let mut keys = HashMap::with_capacity(file.keys.len());
+        for key in file.keys.iter() {
+            let secret = hex::decode(&key.secret)
+                .with_context(|| format!("Session key {} is not a valid hex string", key.id))?;
+
+            keys.insert(key.id, secret);
+        }
+
+        if !keys.contains_key(&file.current) {
+            bail!("Current session key {} is missing", file.current);
+        }
+
+        Ok(Self {
+            current: file.current,
+            keys,
+            source: KeyringSource::File {
+                path,
+                stored: file.keys,
+            },
+        })
+    }
+
+    /// Creates a new key and makes it the current one. Sessions signed
+    /// with older keys stay valid unless `retire_previous` is set.
+    pub fn rotate(&mut self, retire_previous: bool) -> AResult<u32> {
+        let KeyringSource::File { path, stored } = &mut self.source else {
+            bail!("Session keys provided by the config can't be rotated");
+        };
+
+        let id = self.keys.keys().max().map_or(0, |id| id + 1);
+        let key = Self::generate_key(id);
+        let secret = hex::decode(&key.secret)?;
+
+        let mut keys = if retire_previous {
+            vec![]
+        } else {
+            stored.clone()
+        };
+        keys.push(key);
+
+        let file = KeyringFile { current: id, keys };
+
+        // Persist first, so we never sign sessions with a key we can't restore
+        Self::write_file(path, &file)?;
+
+        if retire_previous {
+            self.keys.clear();
+        }
+
+        self.keys.insert(id, secret);
+        self.current = id;
+
+        *stored = file.keys;
+
+        Ok(id)
+    }
+
+    pub fn is_managed_by_config(&self) -> bool {
+        matches!(self.source, KeyringSource::Config)
+    }
+
+    pub fn sign(&self, user_id: i32, session_id: i32, expires_at: DateTime<Utc>) -> SessionKey {
+        let key = &self.keys[&self.current];
+
+        let body = SessionKeyBody {
+            key_id: self.current,
+            session_id,
+            user_id,
+            expires_at: expires_at.timestamp(),
+        };
+
+        SessionKey::new(body, key)
+    }
+
+    pub fn verify(&self, session_key: &SessionKey) -> bool {
+        let Some(key) = self.keys.get(&session_key.body.key_id) else {
+            return false;
+        };
+
+        session_key.verify(key)
+    }
+
+    fn generate_key(id: u32) -> StoredKey {
+        let mut secret = [0_u8; SECRET_LEN];
+        rand::rng().fill_bytes(&mut secret);
+
+        StoredKey {
+            id,
+            secret: hex::encode(secret),
+            created_at: Utc::now().timestamp(),
+        }
+    }
+
+    fn write_file(path: &Path, file: &KeyringFile) -> AResult<()> {
+        let content = toml::to_string(file)?;
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+
+        // Nobody except the server should be able to read the secrets
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt as _;
+
+            options.mode(0o600);
+        }
+
+        let mut out = options
+            .open(path)
+            .with_context(|| format!("Failed to open {} for writing", path.display()))?;
+
+        out.write_all(content.as_bytes())?;
+        out.sync_all()?;
+
+        Ok(())
+    }
+}
@@ -3,7 +3,10 @@ use bytes::BytesMut;
 use rpc::models::markers::{Id, User};
 use tokio::net::UdpSocket;
 
-use crate::AppState;
+use crate::{
+    AppState,
+    metrics::{DropReason, METRICS},
+};
 use streaming_common::{UDPPacket, UDPPacketType};
 
 pub async fn open_udp_socket(state: AppState, udp_addr: &str) -> AResult<()> {
@@ -18,8 +21,10 @@ pub async fn open_udp_socket(state: AppState, udp_addr: &str) -> AResult<()> {
         buf.resize(4800 * 4, 0);
 
         let (bytes_read, addr) = sock.recv_from(&mut buf).await?;
+        METRICS.udp_received(bytes_read);
 
         if bytes_read == 0 {
+            METRICS.udp_dropped(DropReason::Empty);
             continue;
         }
         buf.truncate(bytes_read);
@@ -41,11 +46,12 @@ pub async fn open_udp_socket(state: AppState, udp_addr: &str) -> AResult<()> {
 
         let current_user_id = Id::<User>::new(packet.user_id);
 
-        let (voice_channel, addr_differs) = match state.connected_clients.get(&current_user_id) {
+        let (voice_channel, addr_differs) = match state.voice_connection(current_user_id) {
             Some(state) => {
                 let state = state.read().unwrap();
 
                 let Some(channel_id) = state.active_voice_channel else {
+                    METRICS.udp_dropped(DropReason::NotInVoice);
                     continue;
                 };
 
@@ -56,12 +62,13 @@ pub async fn open_udp_socket(state: AppState, udp_addr: &str) -> AResult<()> {
                 }
             }
             None => {
+                METRICS.udp_dropped(DropReason::NotInVoice);
                 continue;
             }
         };
 
         if addr_differs {
-            let Some(state) = state.connected_clients.get(&current_user_id) else {
+            let Some(state) = state.voice_connection(current_user_id) else {
                 continue;
             };
 
@@ -70,7 +77,19 @@ pub async fn open_udp_socket(state: AppState, udp_addr: &str) -> AResult<()> {
             state.active_stream = Some(addr);
         }
 
+        // Server muted users still keep their stream address up to date,
+        // but nobody hears them
+        if state
+            .channels
+            .voice_server_state(current_user_id)
+            .is_server_muted
+        {
+            METRICS.udp_dropped(DropReason::ServerMuted);
+            continue;
+        }
+
         let Some(voice_users) = state.channels.voice_channels.get(&voice_channel) else {
+            METRICS.udp_dropped(DropReason::ChannelNotFound);
             continue;
         };
 
@@ -79,11 +98,21 @@ pub async fn open_udp_socket(state: AppState, udp_addr: &str) -> AResult<()> {
                 continue;
             }
 
-            if let Some(user) = state.connected_clients.get(&user.id) {
+            if state
+                .channels
+                .voice_server_state(user.id)
+                .is_server_deafened
+            {
+                continue;
+            }
+
+            if let Some(user) = state.voice_connection(user.id) {
                 let addr = { user.read().unwrap().active_stream };
 
-                if let Some(addr) = addr {
-                    _ = sock.send_to(&buf[..bytes_read], addr).await;
+                if let Some(addr) = addr
+                    && sock.send_to(&buf[..bytes_read], addr).await.is_ok()
+                {
+                    METRICS.udp_forwarded(bytes_read);
                 }
             }
         }